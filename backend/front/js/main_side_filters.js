@@ -60,11 +60,12 @@ apply_btn.addEventListener("click", () => {
     price_min: document.getElementById("f_min").value,
     price_max: document.getElementById("f_max").value,
     query: document.getElementById("f_query").value,
+    min_discount: document.getElementById("f_min_discount").value,
     card_appearing: checkedRadio.value,
   };
 
   const url =
-    `/api/filters?appid=${encodeURIComponent(msg.appid)}&price_min=${encodeURIComponent(msg.price_min)}&price_max=${encodeURIComponent(msg.price_max)}&query=${encodeURIComponent(msg.query)}&card_appearing=${encodeURIComponent(msg.card_appearing)}`;
+    `/api/filters?appid=${encodeURIComponent(msg.appid)}&price_min=${encodeURIComponent(msg.price_min)}&price_max=${encodeURIComponent(msg.price_max)}&query=${encodeURIComponent(msg.query)}&min_discount=${encodeURIComponent(msg.min_discount)}&card_appearing=${encodeURIComponent(msg.card_appearing)}`;
 
   fetch(url).then(res => res.json()).then(json => console.log("Session filters updated:", json));
   sendMainWS(msg);
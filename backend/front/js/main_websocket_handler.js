@@ -1,3 +1,5 @@
+import { formatSteamPrice } from "./misc_shared_fns.js";
+
 function wsUrl(path) {
   const proto = location.protocol === "https:" ? "wss" : "ws";
   return `${proto}://${location.host}${path}`;
@@ -142,6 +144,7 @@ ad_main_ws.onerror = (err) => console.log("ad_main_ws ERROR", err);
 ad_main_ws.onmessage = (event) => {
   if (CardAdAppearing == "stores" || CardAdAppearing == "stores_items") {
     const data = JSON.parse(event.data);
+    if (data.type !== "ads") return;
 
     if (!data.user_ads || !data.user_ads[0]) {
       console.error("No ads received or ads data is invalid");
@@ -191,6 +194,11 @@ main_ws.onmessage = (event) => {
   const payload = JSON.parse(event.data);
 
   // console.log("Received update:", payload);
+  if (payload.type === "error") {
+    console.warn("main_ws rejected a message:", payload.error, payload.message);
+    return;
+  }
+
   if (payload.type === "filters") {
     if (payload.card_appearing) {
       CardAdAppearing = payload.card_appearing;
@@ -227,7 +235,7 @@ main_ws.onmessage = (event) => {
 
             </div>
             <span class="hidden-text">${item.name}</span>
-            <span class="text-price">$${item.converted_price}</span>
+            <span class="text-price">${item.price ? formatSteamPrice(item.price.cents, item.price.currency) : "?"}</span>
           </div>
         </div>
       </div>
@@ -223,4 +223,28 @@ export async function get_inventory_games(store_steamid, element) {
       option.textContent = `${game.name} (${game.items})`;
       element.appendChild(option);
     });
-  }
\ No newline at end of file
+  }
+// Steam currency ids (ECurrencyCode) -> ISO codes. Keep in sync with STEAM_CURRENCIES in backend/src/currency.rs
+const STEAM_CURRENCIES = {
+  1: "USD",
+  2: "GBP",
+  3: "EUR",
+  4: "CHF",
+  5: "RUB",
+  6: "PLN",
+  7: "BRL",
+  8: "JPY",
+  9: "NOK",
+  17: "TRY",
+  18: "UAH",
+  20: "CAD",
+  21: "AUD",
+  23: "CNY",
+  37: "KZT",
+};
+
+// Steam sends prices in cents of the given currency
+export function formatSteamPrice(cents, currency) {
+  const code = (currency && STEAM_CURRENCIES[currency]) || "EUR";
+  return new Intl.NumberFormat(undefined, { style: "currency", currency: code }).format(cents / 100);
+}
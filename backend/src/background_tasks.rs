@@ -1,19 +1,23 @@
 use actix_web::{web};
 use std::time::Duration;
-use tokio::sync::{broadcast, mpsc};
+use tokio::sync::mpsc;
+
+use steam_market_parser::observed_rates;
 
 use crate::{
     UserAdState,
     FeedItemsState,
     SteamMostRecentResponse,
-    GameListState
+    GameListState,
+    AlertState
 };
 
-use crate::db::DataBase;
+use crate::db::{AlertRepo, DbError, DbPool, DealScoring, FeedRepo, FeedRetention, WalletRepo};
 use crate::payments::stripe::payment::create_transfer;
 
 use crate::websocket::{
     AdsBroadcastPayload,
+    AlertsBroadcastPayload,
     BroadcastPayload
 };
 
@@ -36,53 +40,123 @@ pub async fn tokio_user_ad_loop(state: web::Data<UserAdState>){
     }
 }
 
-pub async fn tokio_db_update_game_list(game_list: web::Data<GameListState>){
-    loop {
-        
-        let db = DataBase::connect_to_db();
+pub async fn tokio_receiver_most_recent_items_request(
+    mut receiver: mpsc::Receiver<SteamMostRecentResponse>,
+    db_pool: DbPool,
+    state: web::Data<FeedItemsState>,
+    game_list: web::Data<GameListState>,
+    alert_state: web::Data<AlertState>,
+    scoring: DealScoring,
+) {
+    println!("Deal scoring: {scoring:?}");
+
+    while let Some(most_recent_items_response) = receiver.recv().await {
+        let scoring = scoring.clone();
+        let stored = db_pool
+            .run(move |db| {
+                let observed = observed_rates(most_recent_items_response.listinginfo.values());
+                if let Err(e) = db.db_upsert_currency_rates(&observed) {
+                    eprintln!("db_upsert_currency_rates failed: {e}");
+                }
+
+                let (start_id, end_id) = db.db_post_most_recent_items(most_recent_items_response)?;
 
-        let list = match db.db_update_game_list() {
-            Ok(vec) => vec,
+                let rates = db.db_get_currency_rates().unwrap_or_else(|e| {
+                    eprintln!("db_get_currency_rates failed: {e}");
+                    Default::default()
+                });
+
+                //Counts move with every poll, a failed refresh keeps the last list
+                let games = db.db_get_games().map_err(|e| eprintln!("db_get_games failed: {e}")).ok();
+
+                let mut items = db.db_get_most_recent_items(start_id, end_id)?;
+                let now = chrono::Utc::now().timestamp();
+
+                //Unscored listings still go out, just without a discount
+                if let Err(e) = db.db_score_deals(&mut items, &scoring, now) {
+                    eprintln!("db_score_deals failed: {e}");
+                }
+
+                //A failed check costs these alerts, not the feed
+                let notifications = db
+                    .db_notify_saved_searches(&items, &rates, now)
+                    .unwrap_or_else(|e| {
+                        eprintln!("db_notify_saved_searches failed: {e}");
+                        Vec::new()
+                    });
+
+                Ok::<_, DbError>((rates, games, items, notifications))
+            })
+            .await;
+
+        let (rates, games, result, notifications) = match stored {
+            Ok(stored) => stored,
             Err(e) => {
-                eprintln!("db_update_game_list failed: {e}");
-                tokio::time::sleep(Duration::from_secs(10)).await;
+                eprintln!("Storing feed items failed: {e}");
                 continue;
             }
         };
 
-        println!("List {list:#?}");
         {
-            let mut game_list_guard = game_list.game_list.lock().await;
-            *game_list_guard = list;
+            let mut items = state.items.lock().await;
+            *items = result.clone();
+        }
+        if let Some(games) = games {
+            *game_list.game_list.lock().await = games;
         }
+        let payload = BroadcastPayload {
+            items: result,
+            rates,
+        };
+        let _ = state.broadcaster.send(payload);
 
-        drop(db);
-        tokio::time::sleep(Duration::from_secs(86400)).await;
+        if !notifications.is_empty() {
+            let _ = alert_state.broadcaster.send(AlertsBroadcastPayload { notifications });
+        }
     }
 }
 
-pub async fn tokio_receiver_most_recent_items_request(
-    mut receiver: mpsc::Receiver<SteamMostRecentResponse>,
-    db: DataBase,
-    state: web::Data<FeedItemsState>,
-) {
-    while let Some(most_recent_items_response) = receiver.recv().await {
-        let (start_id, end_id) = db.db_post_most_recent_items(most_recent_items_response);
+/// Compacts raw price points older than PRICE_HISTORY_RAW_DAYS (default 7) into hourly candles.
+pub async fn tokio_price_history_rollup(db_pool: DbPool) {
+    let raw_days: i64 = std::env::var("PRICE_HISTORY_RAW_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(7);
 
-        if let Ok(result) = db.db_get_most_recent_items(start_id, end_id) {
-            {
-                let mut items = state.items.lock().await;
-                *items = result.clone();
-            }
-            let payload = BroadcastPayload {
-                items: result.clone(),
-            };
-            let _ = state.broadcaster.send(payload);
+    loop {
+        let cutoff = chrono::Utc::now().timestamp() - raw_days * 86400;
+
+        match db_pool.run(move |db| db.db_rollup_price_history(cutoff)).await {
+            Ok(removed) => println!("Price history rollup compacted {removed} raw points"),
+            Err(e) => eprintln!("db_rollup_price_history failed: {e}"),
+        }
+
+        tokio::time::sleep(Duration::from_secs(3600)).await;
+    }
+}
+
+/// Archives item_feed rows that fell out of the retention window, see FeedRetention::from_env.
+pub async fn tokio_feed_retention(db_pool: DbPool, retention: FeedRetention) {
+    println!("Feed retention: {retention:?}");
+
+    loop {
+        let policy = retention.clone();
+        let pruned = db_pool
+            .run(move |db| db.db_prune_feed(&policy, chrono::Utc::now().timestamp()))
+            .await;
+
+        match pruned {
+            Ok(0) => {}
+            Ok(removed) if retention.archive => println!("Feed retention archived {removed} listings"),
+            Ok(removed) => println!("Feed retention deleted {removed} listings"),
+            Err(e) => eprintln!("db_prune_feed failed: {e}"),
         }
+
+        tokio::time::sleep(retention.interval).await;
     }
 }
 
-pub async fn tokio_db_check_transaction_availability() {
+pub async fn tokio_db_check_transaction_availability(db_pool: DbPool) {
     loop {
         println!("Checking stripe_wallet availability + transfers");
 
@@ -96,17 +170,21 @@ pub async fn tokio_db_check_transaction_availability() {
         };
         let client = stripe::Client::new(stripe_key);
 
-        let db = DataBase::connect_to_db();
-
         // 1) unlock matured credits
-        db.db_check_stripe_wallet_transaction_availability();
-
         // 2) transfer available credits
-        let rows = match db.db_get_stripe_wallet_available(50) {
+        let rows = db_pool
+            .run(|db| {
+                if let Err(e) = db.db_check_stripe_wallet_transaction_availability(chrono::Utc::now()) {
+                    eprintln!("db_check_stripe_wallet_transaction_availability failed: {e}");
+                }
+                db.db_get_stripe_wallet_available(50)
+            })
+            .await;
+
+        let rows = match rows {
             Ok(v) => v,
             Err(e) => {
                 eprintln!("db_get_stripe_wallet_available failed: {e}");
-                drop(db);
                 tokio::time::sleep(Duration::from_secs(600)).await;
                 continue;
             }
@@ -121,10 +199,15 @@ pub async fn tokio_db_check_transaction_availability() {
 
             match create_transfer(&client, &seller_acct, amount_cents, &offer_id).await {
                 Ok(tr) => {
-                    if let Err(e) = db.db_mark_stripe_wallet_transferred(row_id, tr.id.as_str()) {
-                        eprintln!("db_mark_stripe_wallet_transferred failed row_id={row_id}: {e}");
-                    } else {
-                        println!("Transferred row_id={row_id} offer_id={offer_id} -> {seller_acct} transfer={}", tr.id);
+                    let transfer_id = tr.id.to_string();
+                    let marked = db_pool
+                        .run(move |db| db.db_mark_stripe_wallet_transferred(row_id, &transfer_id))
+                        .await;
+
+                    match marked {
+                        Ok(true) => println!("Transferred row_id={row_id} offer_id={offer_id} -> {seller_acct} transfer={}", tr.id),
+                        Ok(false) => eprintln!("stripe_wallet row_id={row_id} was no longer AVAILABLE, transfer={} not recorded", tr.id),
+                        Err(e) => eprintln!("db_mark_stripe_wallet_transferred failed row_id={row_id}: {e}"),
                     }
                 }
                 Err(e) => {
@@ -134,7 +217,6 @@ pub async fn tokio_db_check_transaction_availability() {
             }
         }
 
-        drop(db);
         tokio::time::sleep(Duration::from_secs(20)).await;
     }
 }
\ No newline at end of file
@@ -0,0 +1,162 @@
+use serde::Serialize;
+
+use crate::{Action, Assets, DescriptionText, ItemDescription, Tag};
+
+/// What Steam tells us about one (appid, classid, instanceid), whichever endpoint
+/// it came from. Listings and inventories both describe items this way.
+#[derive(Clone, Debug, Default, Serialize, PartialEq)]
+pub struct CatalogItem {
+    pub appid: u32,
+    pub classid: String,
+    pub instanceid: String,
+    pub name: String,
+    pub market_hash_name: String,
+    pub item_type: Option<String>,
+    pub rarity: Option<String>,
+    pub exterior: Option<String>,
+    /// Still has Steam's %owner_steamid% / %assetid% placeholders
+    pub inspect_link: Option<String>,
+    pub icon_url: String,
+    pub name_color: Option<String>,
+    pub tradable: bool,
+    pub marketable: bool,
+    /// Days a fresh copy can't be traded / sold, 0 or None when unrestricted
+    pub trade_restriction_days: Option<u32>,
+    pub market_restriction_days: Option<u32>,
+    /// Non-empty description lines, markup left as Steam sends it
+    pub descriptions: Vec<String>,
+}
+
+//CS2 puts the wear in the market name, listings don't carry the tag
+const EXTERIORS: &[&str] = &["Factory New", "Minimal Wear", "Field-Tested", "Well-Worn", "Battle-Scarred"];
+
+impl CatalogItem {
+    /// From an asset of /market/recent. None when it lacks the fields every row needs.
+    pub fn from_listing_asset(asset: &Assets) -> Option<Self> {
+        let appid = u32::try_from(asset.appid).ok()?;
+        let market_hash_name = asset.market_hash_name.as_ref()?.trim().to_string();
+        let inspect_link = inspect_link(
+            asset.market_actions.iter().chain(asset.owner_actions.iter()).flatten().map(|action| (&action.name, &action.link)),
+        );
+
+        Some(CatalogItem {
+            appid,
+            classid: asset.classid.clone(),
+            instanceid: asset.instanceid.clone(),
+            name: asset.market_name.as_deref().or(asset.name.as_deref()).unwrap_or(&market_hash_name).trim().to_string(),
+            exterior: exterior_from_name(&market_hash_name),
+            market_hash_name,
+            item_type: non_empty(asset.item_type.as_deref()),
+            rarity: None,
+            inspect_link,
+            icon_url: asset.icon_url.clone().unwrap_or_default(),
+            name_color: non_empty(asset.name_color.as_deref()),
+            tradable: asset.tradable == Some(1),
+            marketable: asset.marketable == Some(1),
+            trade_restriction_days: asset.market_tradable_restriction.and_then(|days| u32::try_from(days).ok()),
+            market_restriction_days: asset.market_marketable_restriction.and_then(|days| u32::try_from(days).ok()),
+            descriptions: asset.descriptions.iter().flatten().filter_map(|line| non_empty(line.value.as_deref())).collect(),
+        })
+    }
+
+    /// From a description of /inventory. These carry tags, so rarity and exterior come from there.
+    pub fn from_inventory_description(description: &ItemDescription) -> Option<Self> {
+        let market_hash_name = description.market_hash_name.as_ref()?.trim().to_string();
+        let tags = description.tags.as_deref().unwrap_or_default();
+
+        Some(CatalogItem {
+            appid: description.appid?,
+            classid: description.classid.clone()?,
+            instanceid: description.instanceid.clone().unwrap_or_else(|| "0".to_string()),
+            name: description.market_name.as_deref().or(description.name.as_deref()).unwrap_or(&market_hash_name).trim().to_string(),
+            exterior: tag(tags, "Exterior").or_else(|| exterior_from_name(&market_hash_name)),
+            rarity: tag(tags, "Rarity"),
+            market_hash_name,
+            item_type: non_empty(description.item_type.as_deref()),
+            inspect_link: inspect_link(description.actions.iter().flatten().map(|Action { name, link }| (name, link))),
+            icon_url: description.icon_url.clone().unwrap_or_default(),
+            name_color: non_empty(description.name_color.as_deref()),
+            tradable: description.tradable == Some(1),
+            marketable: description.marketable == Some(1),
+            trade_restriction_days: description.market_tradable_restriction,
+            market_restriction_days: None,
+            descriptions: description_lines(description.descriptions.as_deref().unwrap_or_default()),
+        })
+    }
+}
+
+fn non_empty(text: Option<&str>) -> Option<String> {
+    text.map(str::trim).filter(|text| !text.is_empty()).map(str::to_string)
+}
+
+fn inspect_link<'a>(actions: impl Iterator<Item = (&'a Option<String>, &'a Option<String>)>) -> Option<String> {
+    actions
+        .filter(|(name, _)| name.as_deref().is_some_and(|name| name.contains("Inspect")))
+        .find_map(|(_, link)| non_empty(link.as_deref()))
+}
+
+fn tag(tags: &[Tag], category: &str) -> Option<String> {
+    tags.iter()
+        .find(|tag| tag.category.as_deref() == Some(category))
+        .and_then(|tag| non_empty(tag.localized_tag_name.as_deref()))
+}
+
+fn exterior_from_name(market_hash_name: &str) -> Option<String> {
+    let (_, wear) = market_hash_name.trim_end().strip_suffix(')')?.rsplit_once('(')?;
+    EXTERIORS.contains(&wear).then(|| wear.to_string())
+}
+
+fn description_lines(lines: &[DescriptionText]) -> Vec<String> {
+    lines.iter().filter_map(|line| non_empty(line.value.as_deref())).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listing_asset_keeps_inspect_link_and_wear() {
+        let asset: Assets = serde_json::from_value(serde_json::json!({
+            "currency": 0, "appid": 730, "contextid": "2", "id": "42",
+            "classid": "310776", "instanceid": "480085569", "amount": "1", "status": 2, "original_amount": "1",
+            "icon_url": "icon", "tradable": 1, "marketable": 1,
+            "type": "Classified Rifle",
+            "name": "AK-47 | Redline", "market_name": "AK-47 | Redline (Field-Tested)",
+            "market_hash_name": "AK-47 | Redline (Field-Tested)",
+            "market_tradable_restriction": 7,
+            "descriptions": [{ "value": "Exterior: Field-Tested" }, { "value": " " }],
+            "market_actions": [{ "name": "Inspect in Game...", "link": "steam://rungame/730/%assetid%" }]
+        }))
+        .unwrap();
+
+        let item = CatalogItem::from_listing_asset(&asset).unwrap();
+
+        assert_eq!((item.appid, item.classid.as_str(), item.instanceid.as_str()), (730, "310776", "480085569"));
+        assert_eq!(item.exterior.as_deref(), Some("Field-Tested"));
+        assert_eq!(item.item_type.as_deref(), Some("Classified Rifle"));
+        assert_eq!(item.inspect_link.as_deref(), Some("steam://rungame/730/%assetid%"));
+        assert_eq!(item.trade_restriction_days, Some(7));
+        assert_eq!(item.descriptions, ["Exterior: Field-Tested"]);
+        assert!(item.tradable && item.marketable);
+    }
+
+    #[test]
+    fn inventory_description_reads_tags() {
+        let description: ItemDescription = serde_json::from_value(serde_json::json!({
+            "appid": 730, "classid": "1", "instanceid": "0",
+            "market_hash_name": "Sticker | Crown (Foil)", "tradable": 0,
+            "tags": [
+                { "category": "Rarity", "localized_tag_name": "Exotic" },
+                { "category": "Type", "localized_tag_name": "Sticker" }
+            ]
+        }))
+        .unwrap();
+
+        let item = CatalogItem::from_inventory_description(&description).unwrap();
+
+        assert_eq!(item.rarity.as_deref(), Some("Exotic"));
+        assert_eq!(item.exterior, None);
+        assert_eq!(item.name, "Sticker | Crown (Foil)");
+        assert!(!item.tradable);
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+
+use crate::Listinginfo;
+
+/// Steam wallet currencies (ECurrencyCode) we let users pick for display.
+pub const STEAM_CURRENCIES: &[(u32, &str)] = &[
+    (1, "USD"),
+    (2, "GBP"),
+    (3, "EUR"),
+    (4, "CHF"),
+    (5, "RUB"),
+    (6, "PLN"),
+    (7, "BRL"),
+    (8, "JPY"),
+    (9, "NOK"),
+    (17, "TRY"),
+    (18, "UAH"),
+    (20, "CAD"),
+    (21, "AUD"),
+    (23, "CNY"),
+    (37, "KZT"),
+];
+
+pub fn currency_code(currency: u32) -> Option<&'static str> {
+    STEAM_CURRENCIES
+        .iter()
+        .find(|(id, _)| *id == currency)
+        .map(|(_, code)| *code)
+}
+
+/// Exchange rates between Steam currency ids, learned from listings
+/// Steam already converted for us. `rate(from, to)` multiplies cents in `from`.
+#[derive(Clone, Debug, Default)]
+pub struct CurrencyRates {
+    rates: HashMap<(u32, u32), f64>,
+}
+
+impl CurrencyRates {
+    pub fn insert(&mut self, from: u32, to: u32, rate: f64) {
+        if rate.is_finite() && rate > 0.0 {
+            self.rates.insert((from, to), rate);
+        }
+    }
+
+    /// Direct pair, its inverse, or one hop through a shared currency.
+    pub fn rate(&self, from: u32, to: u32) -> Option<f64> {
+        if from == to {
+            return Some(1.0);
+        }
+
+        self.direct(from, to).or_else(|| {
+            self.rates
+                .keys()
+                .flat_map(|&(a, b)| [a, b])
+                .find_map(|mid| Some(self.direct(from, mid)? * self.direct(mid, to)?))
+        })
+    }
+
+    pub fn convert(&self, cents: i64, from: u32, to: u32) -> Option<i64> {
+        self.rate(from, to).map(|rate| (cents as f64 * rate).round() as i64)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rates.is_empty()
+    }
+
+    fn direct(&self, from: u32, to: u32) -> Option<f64> {
+        self.rates
+            .get(&(from, to))
+            .copied()
+            .or_else(|| self.rates.get(&(to, from)).map(|rate| 1.0 / rate))
+    }
+}
+
+/// One currency pair seen in a feed response.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObservedRate {
+    pub from_currency: u32,
+    pub to_currency: u32,
+    pub rate: f64,
+    pub samples: i64,
+}
+
+/// Average listing price -> converted price ratio per currency pair.
+/// Listings in the feed currency itself tell us nothing and are skipped.
+pub fn observed_rates<'a>(listings: impl IntoIterator<Item = &'a Listinginfo>) -> Vec<ObservedRate> {
+    let mut sums: HashMap<(u32, u32), (f64, f64, i64)> = HashMap::new();
+
+    for listing in listings {
+        let (Some(converted), Some(to)) = (listing.converted_price, listing.converted_currency()) else {
+            continue;
+        };
+        let from = listing.listing_currency();
+
+        if from == to || listing.price <= 0.0 || converted == 0 {
+            continue;
+        }
+
+        let entry = sums.entry((from, to)).or_insert((0.0, 0.0, 0));
+        entry.0 += listing.price;
+        entry.1 += converted as f64;
+        entry.2 += 1;
+    }
+
+    let mut rates: Vec<ObservedRate> = sums
+        .into_iter()
+        .map(|((from_currency, to_currency), (original, converted, samples))| ObservedRate {
+            from_currency,
+            to_currency,
+            rate: converted / original,
+            samples,
+        })
+        .collect();
+
+    rates.sort_by_key(|r| (r.from_currency, r.to_currency));
+    rates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_use_inverse_and_one_hop() {
+        let mut rates = CurrencyRates::default();
+        rates.insert(5, 3, 0.01);
+        rates.insert(1, 3, 0.9);
+
+        assert_eq!(rates.convert(100, 3, 3), Some(100));
+        assert_eq!(rates.convert(10000, 5, 3), Some(100));
+        assert_eq!(rates.convert(100, 3, 5), Some(10000));
+        assert_eq!(rates.convert(1000, 1, 5), Some(90000));
+        assert_eq!(rates.convert(100, 3, 2), None);
+    }
+
+    #[test]
+    fn observed_rates_average_per_pair() {
+        let listing = |price: f64, currencyid: usize, converted: usize| -> Listinginfo {
+            serde_json::from_value(serde_json::json!({
+                "listingid": "1",
+                "price": price,
+                "fee": 0,
+                "publisher_fee_app": 730,
+                "publisher_fee_percent": "0.10",
+                "currencyid": currencyid,
+                "converted_price": converted,
+                "converted_currencyid": 2003,
+                "asset": { "currency": 0, "appid": 730, "contextid": "2", "id": "1", "amount": "1" }
+            }))
+            .unwrap()
+        };
+
+        let listings = [
+            listing(1000.0, 2005, 10),
+            listing(3000.0, 2005, 30),
+            listing(50.0, 2003, 50),
+        ];
+
+        assert_eq!(
+            observed_rates(&listings),
+            vec![ObservedRate { from_currency: 5, to_currency: 3, rate: 0.01, samples: 2 }]
+        );
+    }
+}
@@ -1,33 +1,125 @@
 use rusqlite::Connection;
 use tokio::sync::Mutex;
-use chrono::Utc;
-use std::{collections::{HashMap, VecDeque}, time::{self, SystemTime, UNIX_EPOCH}};
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, VecDeque};
 use steam_market_parser::{
     AdCardHistoryVec, 
+    CatalogItem,
     MostRecent, 
-    SteamMostRecentResponse, 
     SteamUser, 
     StoreQueueHashmap, 
     UserProfileAds,
     OfferItems,
-    OfferContentUpdated,
-    CurrentStatusOffer,
-    OfferContentToCheck,
-    OfferCheckResult,
     DraftItem,
+    Money,
     OfferDraft,
-    UserParamsFromDB
+    CurrencyRates,
+    FeedMatcher,
+    FeedQuery,
+    Game,
+    highlight_html,
+    Notification,
+    SavedSearch,
+    SavedSearchInput,
+    SearchHit,
+    SearchQuery,
+    ObservedRate,
+    OrderBook,
+    PriceCandle,
+    PriceOverview,
+    UserParamsFromDB,
+    CandleInterval,
+    OFFER_CURRENCY,
+    build_candles
 };
 
 use uuid::Uuid;
 
+mod error;
+#[cfg(test)]
+mod memory;
+pub mod migrations;
+mod pool;
+mod repo;
+mod deal_scoring;
+mod retention;
+pub use error::DbError;
+pub use pool::{DbConfig, DbPool};
+pub use deal_scoring::DealScoring;
+pub use retention::FeedRetention;
+pub use repo::{AlertRepo, CatalogRepo, DraftRepo, FeedRepo, NewFeedItem, NewGame, OfferRepo, UserRepo, WalletRepo, STRIPE_WALLET_HOLD};
+
 pub struct DataBase{
     connection: Connection,
 }
 
-/// One row from offer_log (id, offer_id, round, item_asset_id, item_name, items_price, item_link, time).
-///
+//Explicit list, `SELECT *` breaks once a migration rebuilds a column
+const OFFER_LOG_ITEM_COLUMNS: &str =
+    "item_asset_id, item_contextid, item_appid, item_name, items_price, item_link, item_image";
+
+fn ad_user_from_row(row: &rusqlite::Row) -> Result<UserProfileAds, rusqlite::Error> {
+    Ok(UserProfileAds {
+        steamid: row.get(1)?,
+        nickname: row.get(2)?,
+        avatar: row.get(3)?,
+        first_item_image: row.get(4)?,
+        second_item_image: row.get(5)?,
+        third_item_image: row.get(6)?,
+        fourth_item_image: row.get(7)?,
+    })
+}
+
+//What most_recent_from_row reads, `f` being item_feed. Extra columns go after these.
+//COALESCE(f.currency, 3): rows from before the currency column were all polled in EUR.
+const MOST_RECENT_COLUMNS: &str =
+    "f.id, f.listinginfo_id, c.name, f.converted_price, COALESCE(g.name, ''), f.appid,
+     c.market_hash_name, c.tradable, c.icon_url, COALESCE(g.icon, ''), COALESCE(f.currency, 3),
+     c.rarity, c.exterior, f.reference_price";
+
+const MOST_RECENT_JOINS: &str =
+    "JOIN item_catalog c USING (appid, classid, instanceid)
+     LEFT JOIN games g ON g.appid = f.appid";
+
+fn most_recent_from_row(row: &rusqlite::Row) -> Result<MostRecent, rusqlite::Error> {
+    let cents: Option<i64> = row.get(3)?;
+    let currency: u32 = row.get(10)?;
+    let reference: Option<i64> = row.get(13)?;
+
+    let mut item = MostRecent{
+        id: row.get(0)?,
+        listinginfo_id: row.get(1)?,
+        name: row.get(2)?,
+        price: cents.map(|cents| Money::new(cents, currency)),
+        game: row.get(4)?,
+        appid: row.get::<_, u32>(5)?.to_string(),
+        market_hash_name: row.get(6)?,
+        tradable: if row.get(7)? { "1" } else { "0" }.to_string(),
+        icon: row.get(8)?,
+        game_icon: row.get(9)?,
+        rarity: row.get(11)?,
+        exterior: row.get(12)?,
+        reference_price: None,
+        discount_pct: None,
+    };
+    item.score_deal(reference.map(|cents| Money::new(cents, currency)));
+
+    Ok(item)
+}
+
+fn offer_item_from_row(row: &rusqlite::Row) -> Result<OfferItems, rusqlite::Error> {
+    Ok(OfferItems {
+        item_asset_id: row.get(0)?,
+        item_contextid: row.get(1)?,
+        item_appid: row.get(2)?,
+        item_name: row.get(3)?,
+        item_price: Money::new(row.get(4)?, OFFER_CURRENCY),
+        item_link: row.get(5)?,
+        item_image: row.get(6)?,
+    })
+}
 
+// One row from offer_log (id, offer_id, round, item_asset_id, item_name, items_price, item_link, time).
+//
 // #[derive(Debug)]
 // pub struct OfferLogRow {
 //     pub id: i64,
@@ -41,137 +133,34 @@ pub struct DataBase{
 // }
 
 impl DataBase{
-    pub fn connect_to_db()-> DataBase{
-        
-        let db = DataBase{
-            connection: Connection::open("steam_items.db").expect("DB: Cant connect to database"),
-        };
-        // Set the busy timeout to wait for 5 seconds before throwing the DatabaseBusy error
-        db.connection.execute_batch("PRAGMA busy_timeout = 5000;").expect("DB: Failed to set busy timeout");
-        db.create_tables();
-        db
-        
-    }
-    
-    ///Work on price. This is wrong one now
-    pub fn db_post_most_recent_items(&self, data: SteamMostRecentResponse) -> (i64, i64) {
-        // ID BEFORE inserts
-        let start_id = self.connection.last_insert_rowid();
-
-        let count: i64 = self.connection.query_one(
-            "SELECT COUNT(*) FROM item_feed",
-            [],
-            |row| row.get(0),
-        ).expect("DB: Can't count item_feed");
-        
-        if count > 0 {
-            let start_id: i64 = self.connection.query_one(
-                "SELECT MAX(id) FROM item_feed",
-                [],
-                |row| row.get(0),
-            ).expect("DB: Can't get max id from item_feed");
-        
-            println!("Start ID: {}", start_id);
-        }
-    
-        for listing in data.listinginfo.values() {
-            let listinginfo_id = listing.listingid.to_string();
-            let appid = listing.asset.appid.to_string();
-            let contextid = listing.asset.contextid.to_string();
-            let assetid = listing.asset.id.to_string();
-    
-            let listing_asset = data.assets
-                .get(&appid).unwrap()
-                .get(&contextid).unwrap()
-                .get(&assetid).unwrap();
-    
-            let icon = listing_asset.icon_url.as_ref().expect("DB: No icon_url").to_string();
-            let converted_price = match listing.converted_price {
-                Some(conv_price) => conv_price.to_string(),
-                // None => format!("{:.2}", listing.price * 0.100),
-                None => "?".to_string(),
-            };
-            let game = data.app_data.get(&appid).unwrap().name.to_owned();
-            let game_icon = data.app_data.get(&appid).unwrap().icon.to_owned();
-            let tradable = listing_asset.tradable.as_ref().expect("DB: No tradable").to_string();
-            let name = listing_asset.market_name.as_ref().unwrap().trim().to_string();
-            let market_hash_name = listing_asset.market_hash_name.as_ref().unwrap().trim().to_string();
-    
-            self.connection.execute(
-                "INSERT OR IGNORE INTO item_feed 
-                (listinginfo_id, name, converted_price, game, appid, icon_url, game_icon, market_hash_name, tradable) 
-                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
-                [
-                    listinginfo_id, name, converted_price, game, appid, icon,
-                    game_icon, market_hash_name, tradable,
-                ],
-            ).expect("DB: Can't insert listing data into DB");
-        }
-    
-        // ID AFTER inserts
-        let end_id = self.connection.last_insert_rowid();
-    
-        (start_id, end_id)
-    }
-
-    pub fn db_get_most_recent_items(
-        &self,
-        start_id: i64,
-        end_id: i64
-    ) -> Result<Vec<MostRecent>, rusqlite::Error> {
-
-        let mut query = self.connection.prepare(
-            "SELECT id, listinginfo_id, name, converted_price, game, appid, 
-                    market_hash_name, tradable, icon_url, game_icon
-             FROM item_feed
-             WHERE id > ?1 AND id <= ?2
-             ORDER BY id"
-        )?;
+    /// Opens one connection with WAL on. Schema migrations are run by `DbPool::open`.
+    pub fn open(config: &DbConfig) -> Result<DataBase, rusqlite::Error> {
+        let connection = Connection::open(&config.path)?;
 
-        // println!("{:#?}", query);
-
-        let result = query.query_map([start_id, end_id], |row| {
-            Ok(MostRecent{
-                id: row.get(0).expect("DB: Cant get id from DB"),
-                listinginfo_id: row.get(1).expect("DB: Cant get listinginfo_id from DB"),
-                name: row.get(2).expect("DB: Cant get name from DB"),
-                converted_price: row.get(3).expect("DB: Cant get converted_price from DB"),
-                game: row.get(4).expect("DB: Cant get game from DB"),
-                appid: row.get(5).expect("DB: Cant get appid from DB"),
-                market_hash_name: row.get(6).expect("DB: Cant get market_hash_name from DB"),
-                tradable: row.get(7).expect("DB: Cant get icon from DB"),
-                icon: row.get(8).expect("DB: Cant get icon from DB"),
-                game_icon: row.get(9).expect("DB: Cant get game_icon from DB"),
-            })
-        })?.collect();
+        connection.busy_timeout(config.busy_timeout)?;
+        //WAL lets the feed writer and readers work at the same time
+        connection.query_row("PRAGMA journal_mode = WAL", [], |row| row.get::<_, String>(0))?;
+        connection.execute_batch("PRAGMA synchronous = NORMAL;")?;
 
-        result
+        Ok(DataBase{
+            connection,
+        })
     }
 
-    ///Check whats an excluded meaning
-    pub fn db_add_steam_user(&self, steam_user: &SteamUser){
-
-        println!("{steam_user:#?}");
+    /// Fresh in-memory database with every migration applied.
+    #[cfg(test)]
+    pub fn open_in_memory() -> DataBase {
+        let mut connection = Connection::open_in_memory().expect("DB: in-memory database");
+        migrations::migrate(&mut connection).expect("DB: migrations on an empty database");
+        DataBase { connection }
+    }
 
-        self.connection.execute(
-            "INSERT OR IGNORE INTO steam_user 
-            (steamid, nickname, avatar_url_small, avatar_url_full, status) 
-            VALUES (?1, ?2, ?3, ?4, ?5)
-            ON CONFLICT(steamid) DO UPDATE SET
-                steamid = excluded.steamid,
-                nickname = excluded.nickname,
-                avatar_url_small = excluded.avatar_url_small,
-                avatar_url_full = excluded.avatar_url_full,
-                status = excluded.status;
-                ",
-            [
-                &steam_user.steamid, &steam_user.nickname, &steam_user.avatar_url_small, &steam_user.avatar_url_full,
-                &steam_user.status
-            ],
-        ).expect("DB: Can't insert steam_user data into DB");
+    /// Prints pending schema migrations and leaves the database as is.
+    pub fn migrate_dry_run(config: &DbConfig) -> Result<(), rusqlite::Error> {
+        migrations::dry_run(&Self::open(config)?.connection)
     }
-    
-    pub fn db_add_ad_steam_user(&self, steam_user: &UserProfileAds){
+
+    pub fn db_add_ad_steam_user(&self, steam_user: &UserProfileAds) -> Result<(), DbError> {
         self.connection.execute(
             "INSERT INTO ad_steam_user (
                 steamid, nickname, avatar_url_full,
@@ -195,51 +184,28 @@ impl DataBase{
                 &steam_user.third_item_image,
                 &steam_user.fourth_item_image,
             ],
-        ).expect("DB: Can't upsert ad_steam_user data");
-        
+        )?;
+        Ok(())
     }
 
-    pub fn db_get_ad_steam_user(&self)-> VecDeque<UserProfileAds>{
-        let mut row_users = self.connection.prepare("SELECT * FROM ad_steam_user")
-        .expect("DB: Can't get data from ad_steam_user");
+    pub fn db_get_ad_steam_user(&self)-> Result<VecDeque<UserProfileAds>, DbError>{
+        let mut row_users = self.connection.prepare("SELECT * FROM ad_steam_user")?;
 
-        let vec_ad_users = row_users.query_map([], |row|{
-            Ok(UserProfileAds{
-                steamid: row.get(1).expect("DB: Can't get steamid from ad_steam_user"),
-                nickname: row.get(2).expect("DB: Can't get nickname from ad_steam_user"),
-                avatar: row.get(3).expect("DB: Can't get avatar_url_full from ad_steam_user"),
-                first_item_image: row.get(4).expect("DB: Can't get first_item_image from ad_steam_user"),
-                second_item_image: row.get(5).expect("DB: Can't get second_item_image from ad_steam_user"),
-                third_item_image: row.get(6).expect("DB: Can't get third_item_image from ad_steam_user"),
-                fourth_item_image: row.get(7).expect("DB: Can't get fourth_item_image from ad_steam_user"),
-            })
-        }).expect("DB: Query_map on row_users is NOT successful");
+        let vec_ad_users = row_users.query_map([], ad_user_from_row)?;
 
         let mut queue = VecDeque::new();
 
         for user in vec_ad_users {
-            if let Ok(u) = user {
-                queue.push_back(u);
-            }
+            queue.push_back(user?);
         }
 
-        queue
+        Ok(queue)
     }
 
-    pub fn db_get_ad_cards_history(&self, steamid: String)-> Result<AdCardHistoryVec, rusqlite::Error>{
+    pub fn db_get_ad_cards_history(&self, steamid: String)-> Result<AdCardHistoryVec, DbError>{
         let mut query = self.connection.prepare("SELECT * FROM ad_steam_user WHERE steamid=?1")?;
 
-        let rows = query.query_map([steamid], |row| {
-            Ok(UserProfileAds{
-                steamid: row.get(1).expect("DB: Cant get steamid from DB"),
-                nickname: row.get(2).expect("DB: Cant get nickname from DB"),
-                avatar: row.get(3).expect("DB: Cant get avatar from DB"),
-                first_item_image: row.get(4).expect("DB: Cant get first_item_image from DB"),
-                second_item_image: row.get(5).expect("DB: Cant get second_item_image from DB"),
-                third_item_image: row.get(6).expect("DB: Cant get third_item_image from DB"),
-                fourth_item_image: row.get(7).expect("DB: Cant get fourth_item_image from DB"),
-            })
-        })?;
+        let rows = query.query_map([steamid], ad_user_from_row)?;
 
         let mut result = Vec::new();
 
@@ -253,26 +219,13 @@ impl DataBase{
 
     }
 
-    pub fn db_change_user_status(&self, session: SteamUser){
-
-        let steamid = session.steamid;
-
-        self.connection.execute(
-            "UPDATE steam_user 
-             SET status = ?1
-             WHERE steamid = ?2",
-            ("offline".to_string(), steamid),
-        ).expect("DB: Cant update steam_user status");
-
-    }
-
-    pub fn db_fill_store_hashmap(&self, mut store_hashmap: StoreQueueHashmap)->Result<StoreQueueHashmap, rusqlite::Error>{
+    pub fn db_fill_store_hashmap(&self, mut store_hashmap: StoreQueueHashmap)->Result<StoreQueueHashmap, DbError>{
         let mut query = self.connection.prepare("
             SELECT * FROM steam_user
-        ").expect("DB: Cant get all steam_userS");
+        ")?;
 
         let rows = query.query_map([], |row|{
-            let steamid: String = row.get(1).expect("DB: Can't get steamid from steam_user");
+            let steamid: String = row.get(1)?;
             store_hashmap.hashmap.entry(steamid).or_insert_with(|| Mutex::new(VecDeque::new()));
             Ok(())
         })?;
@@ -285,33 +238,304 @@ impl DataBase{
         Ok(store_hashmap)
     }
 
-    pub fn db_offer_make_offer(&self, buyer: String, trader: String) -> String{
-        let time = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    //==================
+    //Price overview cache
+    pub fn db_get_price_overview(&self, appid: u32, market_hash_name: &str, currency: u32) -> Result<Option<PriceOverview>, DbError> {
+        let mut stmt = self.connection.prepare(
+            "SELECT lowest_price, median_price, volume, lowest_price_text, median_price_text, fetched_at
+             FROM price_overview
+             WHERE appid = ?1 AND market_hash_name = ?2 AND currency = ?3"
+        )?;
+
+        let mut rows = stmt.query(rusqlite::params![appid, market_hash_name, currency])?;
+
+        if let Some(row) = rows.next()? {
+            Ok(Some(PriceOverview {
+                appid,
+                market_hash_name: market_hash_name.to_string(),
+                currency,
+                lowest_price: row.get(0)?,
+                median_price: row.get(1)?,
+                volume: row.get(2)?,
+                lowest_price_text: row.get(3)?,
+                median_price_text: row.get(4)?,
+                fetched_at: row.get(5)?,
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn db_upsert_price_overview(&self, overview: &PriceOverview) -> Result<(), DbError> {
+        // Overview volume is a 24h total, not per bucket, so the point carries none
+        if let Some(price) = overview.median_price.or(overview.lowest_price) {
+            self.db_insert_price_observation(
+                overview.appid,
+                &overview.market_hash_name,
+                Money::new(price, overview.currency),
+                0,
+                "overview",
+            )?;
+        }
+
+        self.connection.execute(
+            "
+            INSERT INTO price_overview
+              (appid, market_hash_name, currency, lowest_price, median_price, volume,
+               lowest_price_text, median_price_text, fetched_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            ON CONFLICT(appid, market_hash_name, currency) DO UPDATE SET
+                lowest_price = excluded.lowest_price,
+                median_price = excluded.median_price,
+                volume = excluded.volume,
+                lowest_price_text = excluded.lowest_price_text,
+                median_price_text = excluded.median_price_text,
+                fetched_at = excluded.fetched_at
+            ",
+            rusqlite::params![
+                overview.appid,
+                overview.market_hash_name,
+                overview.currency,
+                overview.lowest_price,
+                overview.median_price,
+                overview.volume,
+                overview.lowest_price_text,
+                overview.median_price_text,
+                overview.fetched_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    //Price overview cache done
+    //=======================
+
+    //==================
+    //Price history
+    pub fn db_insert_price_observation(
+        &self,
+        appid: u32,
+        market_hash_name: &str,
+        price: Money,
+        volume: i64,
+        source: &str,
+    ) -> Result<(), DbError> {
+        self.connection.execute(
+            "INSERT INTO price_history (appid, market_hash_name, currency, price, volume, source, observed_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            rusqlite::params![appid, market_hash_name, price.currency, price.cents, volume, source, Utc::now().timestamp()],
+        )?;
+        Ok(())
+    }
+
+    /// Raw points and hourly rollups in [from, to), each as a candle.
+    pub fn db_get_price_history(
+        &self,
+        market_hash_name: &str,
+        currency: u32,
+        appid: Option<u32>,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<PriceCandle>, DbError> {
+        let mut raw_stmt = self.connection.prepare(
+            "SELECT observed_at, price, volume
+             FROM price_history
+             WHERE market_hash_name = ?1 AND currency = ?2 AND (?3 IS NULL OR appid = ?3)
+               AND observed_at >= ?4 AND observed_at < ?5"
+        )?;
+
+        let mut candles = raw_stmt.query_map(
+            rusqlite::params![market_hash_name, currency, appid, from, to],
+            |row| Ok(PriceCandle::point(row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?.collect::<Result<Vec<_>, _>>()?;
+
+        let mut hourly_stmt = self.connection.prepare(
+            "SELECT bucket_start, open, high, low, close, volume
+             FROM price_history_hourly
+             WHERE market_hash_name = ?1 AND currency = ?2 AND (?3 IS NULL OR appid = ?3)
+               AND bucket_start >= ?4 AND bucket_start < ?5"
+        )?;
+
+        let hourly = hourly_stmt.query_map(
+            rusqlite::params![market_hash_name, currency, appid, from, to],
+            |row| Ok(PriceCandle {
+                time: row.get(0)?,
+                open: row.get(1)?,
+                high: row.get(2)?,
+                low: row.get(3)?,
+                close: row.get(4)?,
+                volume: row.get(5)?,
+            }),
+        )?;
+
+        for candle in hourly {
+            candles.push(candle?);
+        }
+
+        Ok(candles)
+    }
+
+    /// Compacts raw points older than `cutoff` (rounded down to a whole hour)
+    /// into price_history_hourly and deletes them. Returns how many raw rows were removed.
+    pub fn db_rollup_price_history(&mut self, cutoff: i64) -> Result<usize, DbError> {
+        let cutoff = CandleInterval::Hour.bucket_start(cutoff);
+
+        let tx = self.connection.transaction()?;
+
+        let mut series: HashMap<(u32, String, u32), Vec<PriceCandle>> = HashMap::new();
+        {
+            let mut stmt = tx.prepare(
+                "SELECT appid, market_hash_name, currency, observed_at, price, volume
+                 FROM price_history
+                 WHERE observed_at < ?1"
+            )?;
+
+            let rows = stmt.query_map([cutoff], |row| {
+                Ok((
+                    (row.get::<_, u32>(0)?, row.get::<_, String>(1)?, row.get::<_, u32>(2)?),
+                    PriceCandle::point(row.get(3)?, row.get(4)?, row.get(5)?),
+                ))
+            })?;
+
+            for row in rows {
+                let (key, point) = row?;
+                series.entry(key).or_default().push(point);
+            }
+        }
+
+        {
+            // Merge with an existing bucket in case a rollup already covered part of this hour
+            let mut upsert = tx.prepare(
+                "INSERT INTO price_history_hourly
+                   (appid, market_hash_name, currency, bucket_start, open, high, low, close, volume)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+                 ON CONFLICT(appid, market_hash_name, currency, bucket_start) DO UPDATE SET
+                    high = MAX(high, excluded.high),
+                    low = MIN(low, excluded.low),
+                    close = excluded.close,
+                    volume = volume + excluded.volume"
+            )?;
 
-        let mut generated_uuid = Uuid::new_v4().to_string();
-        loop{
-
-            let mut query = self.connection.prepare("
-             SELECT offer_id
-             FROM offer
-             WHERE offer_id = ?1"
-            ).expect("DB: Can't get uuid while db retrival");
-
-            let result: Result<Vec<_>, _> = query.query_map([&generated_uuid], |row| {
-                Ok(row.get::<_, String>(0).expect("DB: Cant get offer_id from DB"))
-            }).map(|iter| iter.collect());
-            
-            if let Ok(rows) = result {
-                if rows.is_empty() {
-                    break;
+            for ((appid, market_hash_name, currency), points) in series {
+                for candle in build_candles(points, CandleInterval::Hour) {
+                    upsert.execute(rusqlite::params![
+                        appid, market_hash_name, currency, candle.time,
+                        candle.open, candle.high, candle.low, candle.close, candle.volume
+                    ])?;
                 }
-            } else {
-                break;
             }
-            
-            generated_uuid = Uuid::new_v4().to_string();
         }
 
+        let removed = tx.execute("DELETE FROM price_history WHERE observed_at < ?1", [cutoff])?;
+
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    //Price history done
+    //=======================
+
+    //==================
+    //Order book cache
+    pub fn db_get_item_nameid(&self, appid: u32, market_hash_name: &str) -> Result<Option<u64>, DbError> {
+        let mut stmt = self.connection.prepare(
+            "SELECT item_nameid FROM item_nameid WHERE appid = ?1 AND market_hash_name = ?2"
+        )?;
+        let mut rows = stmt.query(rusqlite::params![appid, market_hash_name])?;
+
+        if let Some(row) = rows.next()? {
+            Ok(Some(row.get::<_, i64>(0)? as u64))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn db_insert_item_nameid(&self, appid: u32, market_hash_name: &str, item_nameid: u64) -> Result<(), DbError> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO item_nameid (appid, market_hash_name, item_nameid) VALUES (?1, ?2, ?3)",
+            rusqlite::params![appid, market_hash_name, item_nameid as i64],
+        )?;
+        Ok(())
+    }
+
+    pub fn db_get_order_book(&self, appid: u32, market_hash_name: &str, currency: u32) -> Result<Option<OrderBook>, DbError> {
+        let mut stmt = self.connection.prepare(
+            "SELECT item_nameid, highest_buy_order, lowest_sell_order, buy_orders, sell_orders, fetched_at
+             FROM order_book
+             WHERE appid = ?1 AND market_hash_name = ?2 AND currency = ?3"
+        )?;
+        let mut rows = stmt.query(rusqlite::params![appid, market_hash_name, currency])?;
+
+        let Some(row) = rows.next()? else {
+            return Ok(None);
+        };
+
+        let buy_orders: String = row.get(3)?;
+        let sell_orders: String = row.get(4)?;
+
+        Ok(Some(OrderBook {
+            appid,
+            market_hash_name: market_hash_name.to_string(),
+            currency,
+            item_nameid: row.get::<_, i64>(0)? as u64,
+            highest_buy_order: row.get(1)?,
+            lowest_sell_order: row.get(2)?,
+            buy_orders: serde_json::from_str(&buy_orders).unwrap_or_default(),
+            sell_orders: serde_json::from_str(&sell_orders).unwrap_or_default(),
+            fetched_at: row.get(5)?,
+        }))
+    }
+
+    pub fn db_upsert_order_book(&self, book: &OrderBook) -> Result<(), DbError> {
+        let buy_orders = serde_json::to_string(&book.buy_orders).map_err(|e| DbError::Corrupt(e.to_string()))?;
+        let sell_orders = serde_json::to_string(&book.sell_orders).map_err(|e| DbError::Corrupt(e.to_string()))?;
+
+        self.connection.execute(
+            "
+            INSERT INTO order_book
+              (appid, market_hash_name, currency, item_nameid, highest_buy_order, lowest_sell_order,
+               buy_orders, sell_orders, fetched_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            ON CONFLICT(appid, market_hash_name, currency) DO UPDATE SET
+                item_nameid = excluded.item_nameid,
+                highest_buy_order = excluded.highest_buy_order,
+                lowest_sell_order = excluded.lowest_sell_order,
+                buy_orders = excluded.buy_orders,
+                sell_orders = excluded.sell_orders,
+                fetched_at = excluded.fetched_at
+            ",
+            rusqlite::params![
+                book.appid,
+                book.market_hash_name,
+                book.currency,
+                book.item_nameid as i64,
+                book.highest_buy_order,
+                book.lowest_sell_order,
+                buy_orders,
+                sell_orders,
+                book.fetched_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    //Order book cache done
+    //=======================
+}
+
+//SQLite side of the repository traits in db/repo.rs
+
+impl OfferRepo for DataBase {
+    fn db_offer_exists(&self, offer_id: &str) -> Result<bool, DbError> {
+        let exists = self.connection.query_row(
+            "SELECT EXISTS(SELECT 1 FROM offer WHERE offer_id = ?1)",
+            [offer_id],
+            |row| row.get(0),
+        )?;
+        Ok(exists)
+    }
+
+    fn db_offer_insert(&self, offer_id: &str, buyer: &str, trader: &str, time: &str) -> Result<(), DbError> {
         self.connection.execute(
             "INSERT INTO offer (
                 offer_id, buyer_steamid, trader_steamid, count, price, accepted,
@@ -320,19 +544,19 @@ impl DataBase{
             ON CONFLICT(offer_id) DO NOTHING
             ",
 
-            [
-                &generated_uuid,
-                &buyer,
-                &trader,
-                &"0".to_string(),
-                &"0".to_string(),
-                &false.to_string(),
-                &false.to_string(),
-                &"IN PROCESS".to_string(),
-                &time,
-                &time,
+            rusqlite::params![
+                offer_id,
+                buyer,
+                trader,
+                "0",
+                0i64,
+                false.to_string(),
+                false.to_string(),
+                "IN PROCESS",
+                time,
+                time,
             ],
-        ).expect("DB: Can't upsert offer data");
+        )?;
 
         self.connection.execute(
             "INSERT INTO offer_log (
@@ -340,276 +564,172 @@ impl DataBase{
             ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
             ",
 
-            [
-                &generated_uuid,
-                &"0".to_string(),
-                &"Nope".to_string(),
-                &"Nope".to_string(),
-                &"Nope".to_string(),
-                &"Nope".to_string(),
-                &"Nope".to_string(),
-                &"Nope".to_string(),
-                &"Nope".to_string(),
-                &time,
+            //Round 0 placeholder, no price so item readers skip it
+            rusqlite::params![
+                offer_id,
+                0i64,
+                "Nope",
+                "Nope",
+                "Nope",
+                "Nope",
+                Option::<i64>::None,
+                "Nope",
+                "Nope",
+                time,
             ],
-        ).expect("DB: Can't upsert offer data");
+        )?;
 
-        generated_uuid.to_string()
+        Ok(())
     }
 
-    pub fn db_offer_update_offer(&self, offer_id: String, items: Vec<OfferItems>) -> OfferContentUpdated{
-
-        let mut result = OfferContentUpdated {
-            offer_id: offer_id.clone(),
-            total_price: 0.0,
-            total_count: 0,
-            new_items:  Vec::new(),
-            added_items:  Vec::new(),
-            removed_items:  Vec::new(),
-            updated_items:  Vec::new(),
-        }; 
-        
-        let round: i64 = self.connection.query_row(
+    fn db_offer_latest_round(&self, offer_id: &str) -> Result<i64, DbError> {
+        let round = self.connection.query_row(
             "SELECT COALESCE(MAX(round), 0) FROM offer_log WHERE offer_id = ?1",
-            [&offer_id],
+            [offer_id],
             |row| row.get(0),
-        ).expect("DB: Can't get round from offer_log");        
-
-        //Get previous offer
-        let mut previous_offer_stmt = self.connection.prepare("
-            SELECT * FROM offer_log WHERE offer_id = ?1 AND round = ?2
-            ").expect("DB: tried to get previous_offer from offer_log");
-
-        let previous_offer: Vec<OfferItems> = previous_offer_stmt.query_map([&offer_id, &round.to_string()], |row|{
-            Ok(OfferItems {
-                item_asset_id: row.get(3)?,
-                item_contextid: row.get(4)?,
-                item_appid: row.get(5)?,
-                item_name: row.get(6)?,
-                item_price: row.get(7)?,
-                item_link: row.get(8)?,
-                item_image: row.get(9)?,
-            }
-        )
-        }).expect("DB: query_map previous_offer").collect::<Result<Vec<_>, _>>().expect("DB: failed to collect previous_offer from offer_log");
-
-        //------------------
-
-        //add removed items into result
-
-        let mut this_offer_hashmap: HashMap<String, bool> = HashMap::new();
-
-        for item in &items{
-            this_offer_hashmap.insert(item.item_asset_id.clone(), true);
-        }
-
-        for item in &previous_offer{
-            match this_offer_hashmap.get(&item.item_asset_id){
-                None if item.item_price != "Nope" => result.removed_items.push(item.clone()),
-                Some(_) => {}
-                None => {}
-            }
-        }
+        )?;
+        Ok(round)
+    }
 
-        //------------------
+    fn db_offer_round_items(&self, offer_id: &str, round: i64) -> Result<Vec<OfferItems>, DbError> {
+        let mut stmt = self.connection.prepare(&format!("
+            SELECT {OFFER_LOG_ITEM_COLUMNS} FROM offer_log
+            WHERE offer_id = ?1 AND round = ?2 AND items_price IS NOT NULL
+            "))?;
 
-        let mut total_price: f64 = 0.0;
-        let mut total_count = 0;
+        let items = stmt
+            .query_map(rusqlite::params![offer_id, round], offer_item_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
 
-        let time = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
-        
-        for item in items{
-            total_count = total_count + 1;
-            total_price = total_price + item.item_price.parse::<f64>().unwrap_or(0.0);
+        Ok(items)
+    }
 
-            result = self.db_offer_checking_offer_item(result, &item, &round.to_string(), &offer_id);
+    fn db_offer_log_item(&self, offer_id: &str, round: i64, item: &OfferItems, time: &str) -> Result<(), DbError> {
+        self.connection.execute(
+            "INSERT INTO offer_log (
+                offer_id, round, item_asset_id, item_contextid, item_appid, item_name, items_price, item_link, item_image, time
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+            ",
 
-            self.connection.execute(
-                "INSERT INTO offer_log (
-                    offer_id, round, item_asset_id, item_contextid, item_appid, item_name, items_price, item_link, item_image, time
-                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
-                ",
-    
-                [
-                    &offer_id,
-                    &(round + 1).to_string(),
-                    &item.item_asset_id,
-                    &item.item_contextid,
-                    &item.item_appid,
-                    &item.item_name,
-                    &item.item_price,
-                    &item.item_link,
-                    &item.item_image,
-                    &time,
-                ],
-            ).expect("DB: Can't upsert offer_log data");
-        }
+            rusqlite::params![
+                offer_id,
+                round,
+                &item.item_asset_id,
+                &item.item_contextid,
+                &item.item_appid,
+                &item.item_name,
+                item.item_price.cents,
+                &item.item_link,
+                &item.item_image,
+                time,
+            ],
+        )?;
+        Ok(())
+    }
 
+    fn db_offer_set_totals(&self, offer_id: &str, total_price: Money, total_count: i32) -> Result<(), DbError> {
         self.connection.execute(
             "UPDATE offer
              SET price = ?1,
                  count = ?2
              WHERE offer_id = ?3",
-            [
-                &total_price.to_string(),
-                &total_count.to_string(),
-                &offer_id,
+            rusqlite::params![
+                total_price.cents,
+                total_count.to_string(),
+                offer_id,
             ],
-        ).expect("DB: Can't update offer data");
-        
-        result.total_price = total_price;
-        result.total_count = total_count;
-        // println!("{result:#?}");
-        result
-    }
-
-    fn db_offer_checking_offer_item(&self, mut result: OfferContentUpdated, item: &OfferItems, round: &String, offer_id: &String)-> OfferContentUpdated{
-
-        result.new_items.push(item.clone());
-
-        let item_quary = self.connection.query_one("
-        SELECT * FROM offer_log WHERE item_asset_id = ?1 AND round = ?2 AND offer_id = ?3
-        ", [
-            item.item_asset_id.clone(), 
-            round.clone(), 
-            offer_id.clone()],
-         |row| -> Result<OfferItems, rusqlite::Error> {
-            Ok(OfferItems {
-                item_asset_id: row.get(3)?,
-                item_contextid: row.get(4)?,
-                item_appid: row.get(5)?,
-                item_name: row.get(6)?,
-                item_price: row.get(7)?,
-                item_link: row.get(8)?,
-                item_image: row.get(9)?,
-            })
-         });
-
-        match item_quary {
-            Err(_) => result.added_items.push(item.clone()),
-            Ok(row) if row.item_price != item.item_price => {
-        //         println!("item {item:?}");
-        //  println!("item_quary {row:?}");
-                result.updated_items.push(item.clone())
-            },
-            Ok(_) => {}
-        }
-
-        result
+        )?;
+        Ok(())
     }
 
-    pub fn db_offer_update_status_offer(&self, status_and_offer_id: CurrentStatusOffer){
-
-        let offer_id = status_and_offer_id.offer_id;
-        let status = status_and_offer_id.status;
-
-        let mut accepted = false;
-        let mut paid = false;
-
-        match status.as_str() {
-            "ACCEPTED" => {
-                accepted = true;
-                paid = false;
-            },
-            "IN PROCESS" => {
-                accepted = false;
-                paid = false;
-            },
-            "PAY PROCESS" | "SUCCESS" => {
-                accepted = true;
-                paid = true;
-            }
-            _ => {}
-        }
-
-        self.connection.execute(
+    fn db_offer_set_status(&self, offer_id: &str, status: &str, accepted: bool, paid: bool) -> Result<(), DbError> {
+        let updated = self.connection.execute(
             "UPDATE offer
              SET paid = ?1,
                  accepted = ?2,
                  status = ?3
              WHERE offer_id = ?4",
-            (&paid, &accepted, &status, &offer_id),
-        ).expect("DB: Can't update offer data");
+            (&paid, &accepted, status, offer_id),
+        )?;
 
+        if updated == 0 {
+            return Err(DbError::NotFound("offer"));
+        }
+        Ok(())
     }
 
-    pub fn db_offer_get_offer_price(&self, offer_id: String) -> f64 {
-        let price_text: String = self.connection
+    fn db_offer_get_offer_price(&self, offer_id: String) -> Result<Money, DbError> {
+        self.connection
             .query_row(
-                "SELECT price FROM offer WHERE offer_id = ?1",
+                "SELECT price, currency FROM offer WHERE offer_id = ?1",
                 [&offer_id],
-                |row| row.get::<_, String>(0),
+                |row| Ok(Money::new(row.get(0)?, row.get(1)?)),
             )
-            .expect("DB: Cant get price from offer");
-    
-        // Accept "12", "12.5", "12.50", "$12.50"
-        let cleaned = price_text.trim().trim_start_matches('$');
-    
-        let dollars: f64 = cleaned.parse::<f64>().unwrap_or(0.0);
-    
-        // Convert to cents (rounded)
-        (dollars * 100.0).round()
+            .map_err(DbError::missing("offer"))
     }
 
-    pub fn db_offer_check_offer_to_pay(&self, items_and_offer_id: OfferContentToCheck)-> OfferCheckResult{
-        // println!("items_and_offer_id {items_and_offer_id:#?}");
-        // println!("I'm In!");
-        let offer_id = items_and_offer_id.offer_id;
-        let offer_to_check = items_and_offer_id.special_for_save_offer;
-        let partner_steam_id = items_and_offer_id.partner_steam_id;
-
-        let round: i64 = self.connection.query_row(
-            "SELECT COALESCE(MAX(round), 0) FROM offer_log WHERE offer_id = ?1",
-            [&offer_id],
-            |row| row.get(0),
-        ).expect("DB: Can't get round from offer_log");     
-
-        let mut last_offer = self.connection.prepare("
-            SELECT * FROM offer_log WHERE offer_id = ?1 AND round = ?2
-            ").expect("DB: tried to get previous_offer from offer_log");
-
-        let last_offer: Vec<OfferItems> = last_offer.query_map([&offer_id, &round.to_string()], |row|{
-            Ok(OfferItems {
-                item_asset_id: row.get(3)?,
-                item_contextid: row.get(4)?,
-                item_appid: row.get(5)?,
-                item_name: row.get(6)?,
-                item_price: row.get(7)?,
-                item_link: row.get(8)?,
-                item_image: row.get(9)?,
-            }
-        )
-        }).expect("DB: query_map previous_offer").collect::<Result<Vec<_>, _>>().expect("DB: failed to collect previous_offer from offer_log");
-
-        // println!("last_offer {last_offer:#?}");
-        // println!("offer_to_check {offer_to_check:#?}");
+    fn db_get_buyer_steamid_by_offer(&self, offer_id: &str)-> Result<String, DbError>{
+        let steam_id: String = self.connection.query_one("
+        SELECT buyer_steamid FROM offer WHERE offer_id = ?1
+        ", [&offer_id], |row| 
+            row.get(0),
+        ).map_err(DbError::missing("offer"))?;
 
-        if offer_to_check == last_offer {
+        Ok(steam_id)
+    }
 
-            let partner_trade_url= self.db_account_get_trade_url(partner_steam_id);
+    fn db_get_trader_steamid_by_offer(&self, offer_id: &str) -> Result<String, DbError> {
+        let steam_id: String = self.connection.query_one("
+        SELECT trader_steamid FROM offer WHERE offer_id = ?1
+        ", [&offer_id], |row| 
+            row.get(0),
+        ).map_err(DbError::missing("offer"))?;
 
-            println!("Offers match");
-            OfferCheckResult{
-                offer_id,
-                check_result: true,
-                offer_items: offer_to_check,
-                partner_trade_url,
-            }
-            
-        } else {
-            println!("Offer changed");
-            OfferCheckResult{
-                offer_id,
-                check_result: false,
-                offer_items: Vec::new(),
-                partner_trade_url: String::from(""),
-            }
-        }
+        Ok(steam_id)
     }
+}
 
-    pub fn db_account_post_trade_url(&self, steam_id: &String, trade_url: &String){
+impl UserRepo for DataBase {
+    ///Check whats an excluded meaning
+    fn db_add_steam_user(&self, steam_user: &SteamUser) -> Result<(), DbError> {
 
-        self.connection
+        println!("{steam_user:#?}");
+
+        self.connection.execute(
+            "INSERT OR IGNORE INTO steam_user 
+            (steamid, nickname, avatar_url_small, avatar_url_full, status) 
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT(steamid) DO UPDATE SET
+                steamid = excluded.steamid,
+                nickname = excluded.nickname,
+                avatar_url_small = excluded.avatar_url_small,
+                avatar_url_full = excluded.avatar_url_full,
+                status = excluded.status;
+                ",
+            [
+                &steam_user.steamid, &steam_user.nickname, &steam_user.avatar_url_small, &steam_user.avatar_url_full,
+                &steam_user.status
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn db_change_user_status(&self, session: SteamUser) -> Result<(), DbError> {
+
+        let steamid = session.steamid;
+
+        self.connection.execute(
+            "UPDATE steam_user 
+             SET status = ?1
+             WHERE steamid = ?2",
+            ("offline".to_string(), steamid),
+        )?;
+        Ok(())
+    }
+
+    fn db_account_post_trade_url(&self, steam_id: &str, trade_url: &str) -> Result<(), DbError> {
+
+        let updated = self.connection
         .execute(
             "UPDATE steam_user
              SET trade_url = ?1
@@ -618,12 +738,15 @@ impl DataBase{
                 &trade_url,
                 &steam_id,
             ],
-        )
-        .expect("DB: Can't add trade_url to steam_user");
+        )?;
 
+        if updated == 0 {
+            return Err(DbError::NotFound("steam_user"));
+        }
+        Ok(())
     }
 
-    pub fn db_account_reset_trade_url(&self, steam_id: &String){
+    fn db_account_reset_trade_url(&self, steam_id: &str) -> Result<(), DbError> {
 
         self.connection
         .execute(
@@ -633,14 +756,13 @@ impl DataBase{
             [
                 &steam_id,
             ],
-        )
-        .expect("DB: Can't null trade_url in steam_user");
-
+        )?;
+        Ok(())
     }
 
-    pub fn db_account_get_trade_url(&self, steam_id: String)-> String{
+    fn db_account_get_trade_url(&self, steam_id: String)-> Result<String, DbError>{
 
-        let trade_url = self.connection
+        let trade_url: Option<String> = self.connection
         .query_one(
             "
             SELECT trade_url FROM steam_user
@@ -651,109 +773,32 @@ impl DataBase{
             ],
             |row| row.get(0),
         )
-        .expect("DB: Can't get trade_url from steam_user");
+        .map_err(DbError::missing("steam_user"))?;
 
-    trade_url
+        trade_url.ok_or(DbError::NotFound("trade_url"))
     }
 
-    pub fn db_create_offer_draft(
-        &mut self,
-        offer_id: &str,
-        partner_trade_url: &str,
-        autosend: bool,
-        give_items: Vec<DraftItem>,
-    ) -> Result<String, rusqlite::Error> {
-        let draft_id = Uuid::new_v4().to_string();
-        let created_at = Utc::now().timestamp();
-
-        let tx = self.connection.transaction()?;
-
-        tx.execute(
-            "INSERT INTO trade_offer_drafts (draft_id, offer_id, partner_trade_url, autosend, created_at)
-             VALUES (?1, ?2, ?3, ?4, ?5)",
-            rusqlite::params![
-                draft_id,
-                offer_id,
-                partner_trade_url,
-                if autosend { 1 } else { 0 },
-                created_at
-            ],
+    fn db_account_post_currency(&self, steam_id: &str, currency: u32) -> Result<(), DbError> {
+        self.connection.execute(
+            "UPDATE steam_user SET currency = ?1 WHERE steamid = ?2",
+            rusqlite::params![currency, steam_id],
         )?;
-
-        {
-            let mut stmt = tx.prepare(
-                "INSERT INTO trade_offer_draft_items (draft_id, appid, contextid, assetid, amount, side)
-                 VALUES (?1, ?2, ?3, ?4, ?5, 'give')"
-            )?;
-
-            for it in give_items {
-                stmt.execute(rusqlite::params![
-                    draft_id,
-                    it.appid as i64,
-                    it.contextid,
-                    it.assetid,
-                    it.amount as i64
-                ])?;
-            }
-        }
-
-        tx.commit()?;
-        Ok(draft_id)
+        Ok(())
     }
 
-    pub fn db_get_offer_draft(&self, draft_id: &str) -> Result<OfferDraft, rusqlite::Error> {
-        // read autosend (optional, but useful)
-        let autosend: i64 = self.connection.query_row(
-            "SELECT autosend FROM trade_offer_drafts WHERE draft_id = ?1",
-            rusqlite::params![draft_id],
-            |row| row.get(0),
-        )?;
-
-        let mut stmt = self.connection.prepare(
-            "SELECT appid, contextid, assetid, amount
-             FROM trade_offer_draft_items
-             WHERE draft_id = ?1 AND side = 'give'
-             ORDER BY id ASC"
-        )?;
-
-        let give_iter = stmt.query_map(rusqlite::params![draft_id], |row| {
-            Ok(DraftItem {
-                appid: row.get::<_, i64>(0)? as u32,
-                contextid: row.get(1)?,
-                assetid: row.get(2)?,
-                amount: row.get::<_, i64>(3)? as u32,
-            })
-        })?;
+    fn db_account_get_currency(&self, steam_id: &str) -> Result<Option<u32>, DbError> {
+        let mut stmt = self.connection.prepare("SELECT currency FROM steam_user WHERE steamid = ?1")?;
+        let mut rows = stmt.query([steam_id])?;
 
-        let mut give = Vec::new();
-        for it in give_iter {
-            give.push(it?);
+        match rows.next()? {
+            Some(row) => Ok(row.get(0)?),
+            None => Ok(None),
         }
-
-        Ok(OfferDraft {
-            give,
-            autosend: autosend == 1,
-        })
-    }
-
-    pub fn db_update_game_list(&self)-> Result<Vec<String>, rusqlite::Error>{
-
-        let mut quary = self.connection.prepare("
-        SELECT DISTINCT game FROM item_feed
-        ").expect("DB: Cant get game form item_feed");
-
-        let games = quary.query_map([], |row| {
-            let game: String = row.get(0)?;
-            Ok(game)
-        })?
-        .collect::<Result<Vec<_>, _>>()?;
-
-        Ok(games)
     }
 
-    pub fn db_get_user_params(&self, steam_id: String)-> Result<UserParamsFromDB, rusqlite::Error>{
+    fn db_get_user_params(&self, steam_id: String)-> Result<UserParamsFromDB, DbError>{
 
-        let (user_name, user_trade_url) = self.connection.query_one("
+        let (user_name, user_trade_url): (String, Option<String>) = self.connection.query_one("
         SELECT nickname, trade_url FROM steam_user WHERE steamid = ?1
         ", [&steam_id], |row| 
         {
@@ -762,38 +807,17 @@ impl DataBase{
                 row.get(1)?,
             ))
         },
-        )?;
+        ).map_err(DbError::missing("steam_user"))?;
 
         Ok(UserParamsFromDB{
             user_steam_id: steam_id.clone(),
             user_name,
-            user_trade_url
+            user_trade_url: user_trade_url.ok_or(DbError::NotFound("trade_url"))?,
         })
 
     }
-    //==================
-    //Transaction layout
-    pub fn db_get_buyer_steamid_by_offer(&self, offer_id: &String)-> String{
-        let steam_id: String = self.connection.query_one("
-        SELECT buyer_steamid FROM offer WHERE offer_id = ?1
-        ", [&offer_id], |row| 
-            row.get(0),
-        ).expect("DB: Cant find buyer_steamid in offer");
-
-        steam_id
-    }
-
-    pub fn db_get_trader_steamid_by_offer(&self, offer_id: &String) -> String {
-        let steam_id: String = self.connection.query_one("
-        SELECT trader_steamid FROM offer WHERE offer_id = ?1
-        ", [&offer_id], |row| 
-            row.get(0),
-        ).expect("DB: Cant find trader_steamid in offer");
-
-        steam_id
-    }
 
-    pub fn db_insert_stripe_customer_id(&self, steam_id: &str, stripe_customer_id: &str) -> Result<(), rusqlite::Error> {
+    fn db_insert_stripe_customer_id(&self, steam_id: &str, stripe_customer_id: &str) -> Result<(), DbError> {
         self.connection.execute(
             "INSERT INTO stripe_customer (steamid, stripe_customer_id) VALUES (?1, ?2)
              ON CONFLICT(steamid) DO UPDATE SET stripe_customer_id = excluded.stripe_customer_id",
@@ -802,7 +826,7 @@ impl DataBase{
         Ok(())
     }
 
-    pub fn db_get_stripe_customer_id(&self, steam_id: &str) -> Result<Option<String>, rusqlite::Error> {
+    fn db_get_stripe_customer_id(&self, steam_id: &str) -> Result<Option<String>, DbError> {
         let mut stmt = self.connection.prepare(
             "SELECT stripe_customer_id FROM stripe_customer WHERE steamid = ?1"
         )?;
@@ -816,53 +840,453 @@ impl DataBase{
             Ok(None)
         }
     }
+}
+
+impl FeedRepo for DataBase {
+    fn db_feed_last_id(&self) -> Result<i64, DbError> {
+        let id = self.connection.query_one(
+            "SELECT COALESCE(MAX(id), 0) FROM item_feed",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(id)
+    }
+
+    fn db_in_transaction<T>(&self, f: impl FnOnce(&Self) -> Result<T, DbError>) -> Result<T, DbError> {
+        //Rolled back when dropped uncommitted
+        let tx = self.connection.unchecked_transaction()?;
+        let out = f(self)?;
+        tx.commit()?;
+        Ok(out)
+    }
 
-    pub fn db_insert_buyer_transaction(
+    fn db_insert_feed_item(&self, item: &NewFeedItem) -> Result<bool, DbError> {
+        let inserted = self.connection.execute(
+            "INSERT OR IGNORE INTO item_feed 
+            (listinginfo_id, appid, classid, instanceid, converted_price, currency, created_at) 
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            rusqlite::params![
+                &item.listinginfo_id, item.item.appid, &item.item.classid, &item.item.instanceid,
+                item.price.map(|p| p.cents), item.price.map(|p| p.currency), Utc::now().timestamp(),
+            ],
+        )?;
+
+        //Only new listings count as observations, re-seen ones were recorded already
+        if inserted > 0
+            && let Some(price) = item.price
+        {
+            self.db_insert_price_observation(item.item.appid, &item.item.market_hash_name, price, 1, "feed")?;
+        }
+
+        Ok(inserted > 0)
+    }
+
+    fn db_get_most_recent_items(
+        &self,
+        start_id: i64,
+        end_id: i64
+    ) -> Result<Vec<MostRecent>, DbError> {
+
+        let mut query = self.connection.prepare(&format!(
+            "SELECT {MOST_RECENT_COLUMNS} FROM item_feed f {MOST_RECENT_JOINS}
+             WHERE f.id > ?1 AND f.id <= ?2
+             ORDER BY f.id"
+        ))?;
+
+        let items = query
+            .query_map([start_id, end_id], most_recent_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(items)
+    }
+
+    fn db_scan_feed(&self, query: &FeedQuery, matcher: &FeedMatcher, limit: usize) -> Result<Vec<MostRecent>, DbError> {
+        let order = if query.oldest_first() { "ASC" } else { "DESC" };
+        let filter = matcher.filter();
+        let appids = (!filter.appids.is_empty()).then(|| serde_json::json!(filter.appids).to_string());
+
+        //Bounds as COALESCE so the id range still uses the rowid
+        let mut stmt = self.connection.prepare(&format!(
+            "SELECT {MOST_RECENT_COLUMNS} FROM item_feed f {MOST_RECENT_JOINS}
+             WHERE f.id > COALESCE(?1, -1) AND f.id < COALESCE(?2, 9223372036854775807)
+               AND COALESCE(g.name, '') <> ''
+               AND (?3 IS NULL OR f.appid IN (SELECT value FROM json_each(?3)))
+               AND (?4 IS NULL OR CAST(f.appid AS TEXT) = ?4 OR instr(lower(g.name), ?4) > 0)
+               AND (?5 IS NULL OR instr(lower(c.name), ?5) > 0)
+               AND (?6 = 0 OR c.tradable = 1)
+             ORDER BY f.id {order}
+             LIMIT ?7"
+        ))?;
+
+        let items = stmt
+            .query_map(
+                rusqlite::params![
+                    query.after_id, query.before_id, appids, matcher.game(), matcher.text(), filter.tradable_only,
+                    limit as i64
+                ],
+                most_recent_from_row,
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(items)
+    }
+
+    fn db_search_feed(&self, query: &SearchQuery) -> Result<Vec<SearchHit>, DbError> {
+        let Some(fts_match) = query.fts_match() else {
+            return Ok(Vec::new());
+        };
+
+        //bm25 weighs the item name over market_hash_name over the game
+        let mut stmt = self.connection.prepare(&format!(
+            "SELECT {MOST_RECENT_COLUMNS}, highlight(feed_search, 0, char(2), char(3))
+             FROM feed_search
+             JOIN item_feed f ON f.id = feed_search.rowid
+             {MOST_RECENT_JOINS}
+             WHERE feed_search MATCH ?1
+               AND (?2 IS NULL OR CAST(f.appid AS TEXT) = ?2 OR instr(lower(g.name), lower(?2)) > 0)
+             ORDER BY bm25(feed_search, 10.0, 5.0, 1.0), f.id DESC
+             LIMIT ?3"
+        ))?;
+
+        let hits = stmt
+            .query_map(rusqlite::params![fts_match, query.game(), query.page_limit() as i64], |row| {
+                Ok(SearchHit {
+                    item: most_recent_from_row(row)?,
+                    highlight: highlight_html(&row.get::<_, String>(14)?),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(hits)
+    }
+
+    fn db_feed_price_samples(
+        &self,
+        appid: u32,
+        market_hash_name: &str,
+        currency: u32,
+        before_id: usize,
+        created_after: i64,
+        limit: usize,
+    ) -> Result<Vec<i64>, DbError> {
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT f.converted_price
+             FROM item_catalog c
+             JOIN item_feed f USING (appid, classid, instanceid)
+             WHERE c.appid = ?1 AND c.market_hash_name = ?2
+               AND COALESCE(f.currency, 3) = ?3 AND f.id < ?4 AND f.created_at >= ?5
+               AND f.converted_price IS NOT NULL
+             ORDER BY f.id DESC
+             LIMIT ?6"
+        )?;
+
+        let samples = stmt
+            .query_map(
+                rusqlite::params![appid, market_hash_name, currency, before_id as i64, created_after, limit as i64],
+                |row| row.get(0),
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(samples)
+    }
+
+    fn db_cached_median_price(&self, appid: u32, market_hash_name: &str, currency: u32, fetched_after: i64) -> Result<Option<i64>, DbError> {
+        let median = self.db_get_price_overview(appid, market_hash_name, currency)?
+            .filter(|overview| overview.fetched_at >= fetched_after)
+            .and_then(|overview| overview.median_price);
+
+        Ok(median)
+    }
+
+    fn db_set_reference_prices(&mut self, prices: &[(usize, i64)]) -> Result<(), DbError> {
+        let tx = self.connection.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached("UPDATE item_feed SET reference_price = ?2 WHERE id = ?1")?;
+            for (id, cents) in prices {
+                stmt.execute(rusqlite::params![*id as i64, cents])?;
+            }
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    fn db_upsert_games(&self, games: &[NewGame]) -> Result<(), DbError> {
+        let now = Utc::now().timestamp();
+
+        for game in games {
+            self.connection.execute(
+                "INSERT INTO games (appid, name, icon, link, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT(appid) DO UPDATE SET
+                    name = excluded.name,
+                    icon = excluded.icon,
+                    link = excluded.link,
+                    updated_at = excluded.updated_at",
+                rusqlite::params![game.appid, &game.name, &game.icon, &game.link, now],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn db_get_games(&self) -> Result<Vec<Game>, DbError> {
+        let mut stmt = self.connection.prepare(
+            "SELECT g.appid, g.name, g.icon,
+                    (SELECT COUNT(*) FROM item_feed f WHERE f.appid = g.appid) AS listing_count
+             FROM games g
+             ORDER BY listing_count DESC, g.name"
+        )?;
+
+        let games = stmt.query_map([], |row| {
+            Ok(Game {
+                appid: row.get(0)?,
+                name: row.get(1)?,
+                icon: row.get(2)?,
+                listing_count: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(games)
+    }
+
+    fn db_upsert_currency_rates(&self, rates: &[ObservedRate]) -> Result<(), DbError> {
+        let now = Utc::now().timestamp();
+
+        for rate in rates {
+            self.connection.execute(
+                "INSERT INTO currency_rates (from_currency, to_currency, rate, samples, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT(from_currency, to_currency) DO UPDATE SET
+                    rate = excluded.rate,
+                    samples = excluded.samples,
+                    updated_at = excluded.updated_at",
+                rusqlite::params![rate.from_currency, rate.to_currency, rate.rate, rate.samples, now],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn db_get_currency_rates(&self) -> Result<CurrencyRates, DbError> {
+        let mut stmt = self.connection.prepare(
+            "SELECT from_currency, to_currency, rate FROM currency_rates"
+        )?;
+
+        let mut rates = CurrencyRates::default();
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+
+        for row in rows {
+            let (from, to, rate) = row?;
+            rates.insert(from, to, rate);
+        }
+        Ok(rates)
+    }
+
+    fn db_feed_prune_bound(&self, created_before: Option<i64>, keep_rows: Option<i64>) -> Result<i64, DbError> {
+        let mut bound = 0;
+
+        if let Some(created_before) = created_before {
+            let aged: i64 = self.connection.query_one(
+                "SELECT COALESCE(MAX(id), 0) FROM item_feed WHERE created_at < ?1",
+                [created_before],
+                |row| row.get(0),
+            )?;
+            bound = bound.max(aged);
+        }
+
+        if let Some(keep_rows) = keep_rows {
+            //First row past the newest `keep_rows`, ids have gaps so MAX(id) - n won't do
+            let overflow: i64 = self.connection.query_one(
+                "SELECT COALESCE((SELECT id FROM item_feed ORDER BY id DESC LIMIT 1 OFFSET ?1), 0)",
+                [keep_rows],
+                |row| row.get(0),
+            )?;
+            bound = bound.max(overflow);
+        }
+
+        Ok(bound)
+    }
+
+    fn db_feed_archive_batch(&mut self, up_to_id: i64, limit: usize, archive: bool) -> Result<usize, DbError> {
+        let tx = self.connection.transaction()?;
+
+        //Upper id of this batch, the rows below it go in one move
+        let batch_end: Option<i64> = tx.query_row(
+            "SELECT MAX(id) FROM (SELECT id FROM item_feed WHERE id <= ?1 ORDER BY id LIMIT ?2)",
+            rusqlite::params![up_to_id, limit as i64],
+            |row| row.get(0),
+        )?;
+        let Some(batch_end) = batch_end else {
+            return Ok(0);
+        };
+
+        if archive {
+            tx.execute(
+                "INSERT OR IGNORE INTO item_feed_archive
+                   (id, listinginfo_id, appid, classid, instanceid, converted_price, currency, reference_price,
+                    created_at, archived_at)
+                 SELECT id, listinginfo_id, appid, classid, instanceid, converted_price, currency, reference_price,
+                        created_at, ?2
+                 FROM item_feed
+                 WHERE id <= ?1",
+                rusqlite::params![batch_end, Utc::now().timestamp()],
+            )?;
+        }
+        let removed = tx.execute("DELETE FROM item_feed WHERE id <= ?1", [batch_end])?;
+
+        tx.commit()?;
+        Ok(removed)
+    }
+}
+
+fn catalog_item_from_row(row: &rusqlite::Row) -> Result<CatalogItem, rusqlite::Error> {
+    let descriptions: String = row.get(15)?;
+
+    Ok(CatalogItem {
+        appid: row.get(0)?,
+        classid: row.get(1)?,
+        instanceid: row.get(2)?,
+        name: row.get(3)?,
+        market_hash_name: row.get(4)?,
+        item_type: row.get(5)?,
+        rarity: row.get(6)?,
+        exterior: row.get(7)?,
+        inspect_link: row.get(8)?,
+        icon_url: row.get(9)?,
+        name_color: row.get(10)?,
+        tradable: row.get(11)?,
+        marketable: row.get(12)?,
+        trade_restriction_days: row.get(13)?,
+        market_restriction_days: row.get(14)?,
+        descriptions: serde_json::from_str(&descriptions).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(15, rusqlite::types::Type::Text, Box::new(e))
+        })?,
+    })
+}
+
+impl CatalogRepo for DataBase {
+    fn db_upsert_catalog_items(&self, items: &[CatalogItem]) -> Result<(), DbError> {
+        let now = Utc::now().timestamp();
+        let mut stmt = self.connection.prepare_cached(
+            "INSERT INTO item_catalog
+                (appid, classid, instanceid, name, market_hash_name, item_type, rarity, exterior, inspect_link,
+                 icon_url, name_color, tradable, marketable, trade_restriction_days, market_restriction_days,
+                 descriptions, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)
+             ON CONFLICT(appid, classid, instanceid) DO UPDATE SET
+                name = excluded.name,
+                market_hash_name = excluded.market_hash_name,
+                item_type = COALESCE(excluded.item_type, item_type),
+                rarity = COALESCE(excluded.rarity, rarity),
+                exterior = COALESCE(excluded.exterior, exterior),
+                inspect_link = COALESCE(excluded.inspect_link, inspect_link),
+                icon_url = CASE WHEN excluded.icon_url = '' THEN icon_url ELSE excluded.icon_url END,
+                name_color = COALESCE(excluded.name_color, name_color),
+                tradable = excluded.tradable,
+                marketable = excluded.marketable,
+                trade_restriction_days = COALESCE(excluded.trade_restriction_days, trade_restriction_days),
+                market_restriction_days = COALESCE(excluded.market_restriction_days, market_restriction_days),
+                descriptions = CASE WHEN excluded.descriptions = '[]' THEN descriptions ELSE excluded.descriptions END,
+                updated_at = excluded.updated_at"
+        )?;
+
+        for item in items {
+            let descriptions = serde_json::to_string(&item.descriptions).unwrap_or_else(|_| "[]".to_string());
+            stmt.execute(rusqlite::params![
+                item.appid, &item.classid, &item.instanceid, &item.name, &item.market_hash_name, &item.item_type,
+                &item.rarity, &item.exterior, &item.inspect_link, &item.icon_url, &item.name_color, item.tradable,
+                item.marketable, item.trade_restriction_days, item.market_restriction_days, descriptions, now,
+            ])?;
+        }
+        Ok(())
+    }
+
+    fn db_get_catalog_item(&self, appid: u32, classid: &str, instanceid: &str) -> Result<CatalogItem, DbError> {
+        self.connection
+            .query_row(
+                "SELECT appid, classid, instanceid, name, market_hash_name, item_type, rarity, exterior, inspect_link,
+                        icon_url, name_color, tradable, marketable, trade_restriction_days, market_restriction_days,
+                        descriptions
+                 FROM item_catalog
+                 WHERE appid = ?1 AND classid = ?2 AND instanceid = ?3",
+                rusqlite::params![appid, classid, instanceid],
+                catalog_item_from_row,
+            )
+            .map_err(DbError::missing("item"))
+    }
+}
+
+impl WalletRepo for DataBase {
+    fn db_insert_buyer_transaction(
         &self,
         steamid: String,
         offer_id: String,
-        amount: String,
-        amount_with_fee: String,
+        amount: Money,
+        amount_with_fee: Money,
         method: String,
         pay_method: String,
-    ) -> Result<(), rusqlite::Error> {
+    ) -> Result<(), DbError> {
         let time = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
         let status = "SUCCESS".to_string();
     
         self.connection.execute(
             "
-            INSERT INTO transactions (steamid, offer_id, amount, amount_with_fee, method, pay_method, status, created_at, updated_at)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            INSERT INTO transactions (steamid, offer_id, amount, amount_with_fee, currency, method, pay_method, status, created_at, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
             ",
-            rusqlite::params![steamid, offer_id, amount, amount_with_fee, method, pay_method, status, time, time],
+            rusqlite::params![
+                steamid, offer_id, amount.cents, amount_with_fee.cents, amount.currency,
+                method, pay_method, status, time, time
+            ],
         )?;
         Ok(())
     }  
 
-    pub fn db_check_stripe_wallet_transaction_availability(&self) {
+    fn db_insert_stripe_wallet_transaction(
+        &self,
+        steamid: &str,        // <-- ADD THIS
+        stripe_acct: &str,    // acct_...
+        offer_id: &str,
+        amount_cents: i64,
+        stripe_event_id: &str,
+    ) -> Result<i64, DbError> {
+        let status_locked = "LOCKED";
+        let time = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    
+        self.connection.execute(
+            "
+            INSERT INTO stripe_wallet
+              (steamid, stripe_id, offer_id, amount_cents, status, stripe_event_id, stripe_transfer_id, created_at, updated_at)
+            VALUES
+              (?1, ?2, ?3, ?4, ?5, ?6, NULL, ?7, ?8)
+            ",
+            rusqlite::params![steamid, stripe_acct, offer_id, amount_cents, status_locked, stripe_event_id, time, time],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    fn db_check_stripe_wallet_transaction_availability(&self, now: DateTime<Utc>) -> Result<usize, DbError> {
 
         let status_available = "AVAILABLE";
         let status_locked = "LOCKED";
-        let time_range = "-1 minute";
-        let current_time = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        let current_time = now.format("%Y-%m-%d %H:%M:%S").to_string();
+        let unlock_before = (now - STRIPE_WALLET_HOLD).format("%Y-%m-%d %H:%M:%S").to_string();
 
-        let result = self.connection.execute(
+        let rows = self.connection.execute(
             "
             UPDATE stripe_wallet
             SET status= ?1, updated_at=datetime(?3)
             WHERE status= ?2
-            AND datetime(created_at) <= datetime(?3, ?4);
+            AND datetime(created_at) <= datetime(?4);
             ",
-            rusqlite::params![status_available, status_locked, current_time, time_range],
-        );
-    
-        match result {
-            Ok(rows) => println!("Updated {} expired LOCKED stripe_wallet", rows),
-            Err(e) => eprintln!("DB: Failed to update stripe_wallet: {e}"),
-        }
+            rusqlite::params![status_available, status_locked, current_time, unlock_before],
+        )?;
+
+        println!("Updated {} expired LOCKED stripe_wallet", rows);
+        Ok(rows)
     }
 
-    pub fn db_get_stripe_wallet_available(&self, limit: i64) -> Result<Vec<(i64, String, String, i64)>, rusqlite::Error> {
+    fn db_get_stripe_wallet_available(&self, limit: i64) -> Result<Vec<(i64, String, String, i64)>, DbError> {
         
         let status_available = "AVAILABLE";
         
@@ -890,18 +1314,18 @@ impl DataBase{
         for r in rows { out.push(r?); }
         Ok(out)
     }
-    
-    pub fn db_mark_stripe_wallet_transferred(
+
+    fn db_mark_stripe_wallet_transferred(
         &self,
         row_id: i64,
         transfer_id: &str,
-    ) -> Result<(), rusqlite::Error> {
+    ) -> Result<bool, DbError> {
 
         let status_available = "AVAILABLE";
         let status_transferred = "TRANSFERRED";
         let current_time = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
 
-        self.connection.execute(
+        let updated = self.connection.execute(
             "
             UPDATE stripe_wallet
             SET status=?1,
@@ -911,10 +1335,10 @@ impl DataBase{
             ",
             rusqlite::params![status_transferred, transfer_id, current_time, row_id, status_available],
         )?;
-        Ok(())
+        Ok(updated > 0)
     }    
 
-    pub fn db_get_connected_stripe_trader_acct_for_steamid(&self, steamid: &str) -> Result<Option<String>, rusqlite::Error> {
+    fn db_get_connected_stripe_trader_acct_for_steamid(&self, steamid: &str) -> Result<Option<String>, DbError> {
         let mut stmt = self.connection.prepare(
             "SELECT stripe_id FROM user_wallets WHERE steamid = ?1"
         )?;
@@ -926,204 +1350,230 @@ impl DataBase{
         }
     }
 
-    pub fn db_insert_stripe_wallet_transaction(
-        &self,
-        steamid: &str,        // <-- ADD THIS
-        stripe_acct: &str,    // acct_...
-        offer_id: &str,
-        amount_cents: i64,
-        stripe_event_id: &str,
-    ) -> Result<(), rusqlite::Error> {
-        let status_locked = "LOCKED";
-        let time = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
-    
+    fn db_upsert_user_stripe_id(&self, steamid: &str, acct: &str) -> Result<(), DbError> {
         self.connection.execute(
             "
-            INSERT INTO stripe_wallet
-              (steamid, stripe_id, offer_id, amount_cents, status, stripe_event_id, stripe_transfer_id, created_at, updated_at)
-            VALUES
-              (?1, ?2, ?3, ?4, ?5, ?6, NULL, ?7, ?8)
+            INSERT INTO user_wallets (steamid, stripe_id)
+            VALUES (?1, ?2)
+            ON CONFLICT(steamid) DO UPDATE SET stripe_id=excluded.stripe_id
             ",
-            rusqlite::params![steamid, stripe_acct, offer_id, amount_cents, status_locked, stripe_event_id, time, time],
+            rusqlite::params![steamid, acct],
         )?;
         Ok(())
     }
-    
+}
+
+impl DraftRepo for DataBase {
+    fn db_create_offer_draft(
+        &mut self,
+        offer_id: &str,
+        partner_trade_url: &str,
+        autosend: bool,
+        give_items: Vec<DraftItem>,
+    ) -> Result<String, DbError> {
+        let draft_id = Uuid::new_v4().to_string();
+        let created_at = Utc::now().timestamp();
+
+        let tx = self.connection.transaction()?;
+
+        tx.execute(
+            "INSERT INTO trade_offer_drafts (draft_id, offer_id, partner_trade_url, autosend, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![
+                draft_id,
+                offer_id,
+                partner_trade_url,
+                if autosend { 1 } else { 0 },
+                created_at
+            ],
+        )?;
+
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO trade_offer_draft_items (draft_id, appid, contextid, assetid, amount, side)
+                 VALUES (?1, ?2, ?3, ?4, ?5, 'give')"
+            )?;
+
+            for it in give_items {
+                stmt.execute(rusqlite::params![
+                    draft_id,
+                    it.appid as i64,
+                    it.contextid,
+                    it.assetid,
+                    it.amount as i64
+                ])?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(draft_id)
+    }
+
+    fn db_get_offer_draft(&self, draft_id: &str) -> Result<OfferDraft, DbError> {
+        // read autosend (optional, but useful)
+        let autosend: i64 = self.connection.query_row(
+            "SELECT autosend FROM trade_offer_drafts WHERE draft_id = ?1",
+            rusqlite::params![draft_id],
+            |row| row.get(0),
+        ).map_err(DbError::missing("draft"))?;
+
+        let mut stmt = self.connection.prepare(
+            "SELECT appid, contextid, assetid, amount
+             FROM trade_offer_draft_items
+             WHERE draft_id = ?1 AND side = 'give'
+             ORDER BY id ASC"
+        )?;
 
-    pub fn db_upsert_user_stripe_id(&self, steamid: &str, acct: &str) -> Result<(), rusqlite::Error> {
+        let give_iter = stmt.query_map(rusqlite::params![draft_id], |row| {
+            Ok(DraftItem {
+                appid: row.get::<_, i64>(0)? as u32,
+                contextid: row.get(1)?,
+                assetid: row.get(2)?,
+                amount: row.get::<_, i64>(3)? as u32,
+            })
+        })?;
+
+        let mut give = Vec::new();
+        for it in give_iter {
+            give.push(it?);
+        }
+
+        Ok(OfferDraft {
+            give,
+            autosend: autosend == 1,
+        })
+    }
+}
+
+const SAVED_SEARCH_COLUMNS: &str = "id, steamid, name, appid, price_min, price_max, query, currency, created_at";
+
+fn saved_search_from_row(row: &rusqlite::Row) -> Result<SavedSearch, rusqlite::Error> {
+    Ok(SavedSearch {
+        id: row.get(0)?,
+        steamid: row.get(1)?,
+        name: row.get(2)?,
+        appid: row.get(3)?,
+        price_min: row.get(4)?,
+        price_max: row.get(5)?,
+        query: row.get(6)?,
+        currency: row.get(7)?,
+        created_at: row.get(8)?,
+    })
+}
+
+impl AlertRepo for DataBase {
+    fn db_insert_saved_search(&self, steamid: &str, search: &SavedSearchInput, now: i64) -> Result<SavedSearch, DbError> {
         self.connection.execute(
-            "
-            INSERT INTO user_wallets (steamid, stripe_id)
-            VALUES (?1, ?2)
-            ON CONFLICT(steamid) DO UPDATE SET stripe_id=excluded.stripe_id
-            ",
-            rusqlite::params![steamid, acct],
+            "INSERT INTO saved_searches (steamid, name, appid, price_min, price_max, query, currency, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            rusqlite::params![
+                steamid, search.name.trim(), search.appid.trim(), search.price_min, search.price_max,
+                search.query.trim(), search.currency, now,
+            ],
         )?;
+
+        Ok(self.connection.query_row(
+            &format!("SELECT {SAVED_SEARCH_COLUMNS} FROM saved_searches WHERE id = ?1"),
+            [self.connection.last_insert_rowid()],
+            saved_search_from_row,
+        )?)
+    }
+
+    fn db_get_saved_searches(&self, steamid: &str) -> Result<Vec<SavedSearch>, DbError> {
+        let mut stmt = self.connection.prepare(&format!(
+            "SELECT {SAVED_SEARCH_COLUMNS} FROM saved_searches WHERE steamid = ?1 ORDER BY id"
+        ))?;
+
+        Ok(stmt.query_map([steamid], saved_search_from_row)?.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    fn db_get_all_saved_searches(&self) -> Result<Vec<SavedSearch>, DbError> {
+        let mut stmt = self.connection.prepare_cached(&format!(
+            "SELECT {SAVED_SEARCH_COLUMNS} FROM saved_searches ORDER BY id"
+        ))?;
+
+        Ok(stmt.query_map([], saved_search_from_row)?.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    fn db_delete_saved_search(&self, steamid: &str, id: i64) -> Result<(), DbError> {
+        let deleted = self.connection.execute(
+            "DELETE FROM saved_searches WHERE id = ?1 AND steamid = ?2",
+            rusqlite::params![id, steamid],
+        )?;
+
+        if deleted == 0 {
+            return Err(DbError::NotFound("saved_search"));
+        }
         Ok(())
     }
 
-    //Transaction layout done
-    //=======================
-    
+    fn db_insert_notifications(&mut self, matches: &[(SavedSearch, MostRecent)], now: i64) -> Result<Vec<Notification>, DbError> {
+        let tx = self.connection.transaction()?;
+        let mut stored = Vec::new();
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR IGNORE INTO notifications (steamid, saved_search_id, feed_id, item, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)"
+            )?;
+
+            for (search, item) in matches {
+                let snapshot = serde_json::to_string(item).map_err(|e| DbError::Corrupt(e.to_string()))?;
+                if stmt.execute(rusqlite::params![&search.steamid, search.id, item.id as i64, snapshot, now])? == 0 {
+                    continue;
+                }
+
+                stored.push(Notification {
+                    id: tx.last_insert_rowid(),
+                    steamid: search.steamid.clone(),
+                    saved_search_id: search.id,
+                    search_name: search.name.clone(),
+                    item: item.clone(),
+                    created_at: now,
+                    read: false,
+                });
+            }
+        }
+        tx.commit()?;
+
+        Ok(stored)
+    }
+
+    fn db_get_notifications(&self, steamid: &str, unread_only: bool, limit: usize) -> Result<Vec<Notification>, DbError> {
+        let mut stmt = self.connection.prepare(
+            "SELECT n.id, n.steamid, n.saved_search_id, s.name, n.item, n.created_at, n.read_at IS NOT NULL
+             FROM notifications n
+             JOIN saved_searches s ON s.id = n.saved_search_id
+             WHERE n.steamid = ?1 AND (?2 = 0 OR n.read_at IS NULL)
+             ORDER BY n.id DESC
+             LIMIT ?3"
+        )?;
+
+        let notifications = stmt
+            .query_map(rusqlite::params![steamid, unread_only, limit as i64], |row| {
+                let snapshot: String = row.get(4)?;
+                let item = serde_json::from_str(&snapshot).map_err(|e| {
+                    rusqlite::Error::FromSqlConversionFailure(4, rusqlite::types::Type::Text, Box::new(e))
+                })?;
+
+                Ok(Notification {
+                    id: row.get(0)?,
+                    steamid: row.get(1)?,
+                    saved_search_id: row.get(2)?,
+                    search_name: row.get(3)?,
+                    item,
+                    created_at: row.get(5)?,
+                    read: row.get(6)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(notifications)
+    }
 
-    fn create_tables(&self) {
-        self.connection.execute_batch("
-            CREATE TABLE IF NOT EXISTS item_feed (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                listinginfo_id TEXT UNIQUE,
-                name TEXT,
-                converted_price TEXT,
-                game TEXT,
-                appid TEXT,
-                market_hash_name TEXT,
-                tradable TEXT,
-                icon_url TEXT,
-                game_icon TEXT
-            );
-            CREATE TABLE IF NOT EXISTS steam_user (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                steamid TEXT UNIQUE,
-                nickname TEXT,
-                avatar_url_small TEXT,
-                avatar_url_full TEXT,
-                trade_url TEXT,
-                status TEXT
-            );
-            CREATE TABLE IF NOT EXISTS user_wallets (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                steamid TEXT UNIQUE,     -- 1:1 with steam_user
-                stripe_id TEXT UNIQUE,
-                bitcoin TEXT UNIQUE,
-                FOREIGN KEY (steamid)
-                    REFERENCES steam_user(steamid)
-                    ON UPDATE CASCADE
-                    ON DELETE CASCADE
-                );
-            CREATE TABLE IF NOT EXISTS stripe_wallet (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-
-                steamid TEXT NOT NULL,          -- tie to wallet owner (cascade path)
-                stripe_id TEXT NOT NULL,        -- optional consistency
-                offer_id TEXT NOT NULL,
-                amount_cents INTEGER NOT NULL,
-                status TEXT NOT NULL,
-                stripe_event_id TEXT UNIQUE,
-                stripe_transfer_id TEXT,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL,
-
-                FOREIGN KEY (steamid)
-                    REFERENCES user_wallets(steamid)
-                    ON UPDATE CASCADE
-                    ON DELETE CASCADE,
-
-                -- optional: ensure stripe_id exists in user_wallets (prevents random stripe_id values)
-                FOREIGN KEY (stripe_id)
-                    REFERENCES user_wallets(stripe_id)
-                    ON UPDATE CASCADE
-                    ON DELETE RESTRICT
-                );
-
-            CREATE TABLE IF NOT EXISTS stripe_customer (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                steamid TEXT UNIQUE NOT NULL,
-                stripe_customer_id TEXT UNIQUE NOT NULL,
-                FOREIGN KEY (steamid)
-                    REFERENCES steam_user(steamid)
-                    ON UPDATE CASCADE
-                    ON DELETE CASCADE
-                );
-
-            CREATE TABLE IF NOT EXISTS transactions (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                steamid TEXT NOT NULL,
-                offer_id TEXT NOT NULL,
-                amount TEXT NOT NULL,
-                amount_with_fee TEXT NOT NULL,
-                method TEXT NOT NULL,
-                pay_method TEXT NOT NULL,
-                status TEXT NOT NULL,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL,
-                FOREIGN KEY (steamid)
-                    REFERENCES steam_user(steamid)
-                    ON UPDATE CASCADE
-                    ON DELETE CASCADE
-                );
-
-            CREATE INDEX IF NOT EXISTS idx_user_wallets_steamid ON user_wallets(steamid);
-            CREATE INDEX IF NOT EXISTS idx_stripe_wallet_steamid ON stripe_wallet(steamid);
-            CREATE INDEX IF NOT EXISTS idx_transactions_steamid ON transactions(steamid);
-
-            CREATE TABLE IF NOT EXISTS ad_steam_user (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                steamid TEXT UNIQUE,
-                nickname TEXT,
-                avatar_url_full TEXT,
-                first_item_image TEXT,
-                second_item_image TEXT,
-                third_item_image TEXT,
-                fourth_item_image TEXT
-            );
-            CREATE TABLE IF NOT EXISTS chat_messages (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                chat_id TEXT,
-                buyer_steamid TEXT,
-                trader_steamid TEXT,
-                message_type TEXT,
-                message TEXT,
-                data TEXT
-            );
-            CREATE TABLE IF NOT EXISTS offer (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                offer_id TEXT UNIQUE,
-                buyer_steamid TEXT,
-                trader_steamid TEXT,
-                count TEXT,
-                price TEXT,
-                accepted BOOLEAN,
-                paid BOOLEAN,
-                status TEXT,
-                created TEXT,
-                last_update TEXT
-            );
-            CREATE TABLE IF NOT EXISTS offer_log (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                offer_id TEXT,
-                round INTEGER,
-                item_asset_id TEXT,
-                item_contextid TEXT,
-                item_appid TEXT,
-                item_name TEXT,
-                items_price TEXT,
-                item_link TEXT,
-                item_image TEXT,
-                time TEXT,
-                FOREIGN KEY (offer_id) REFERENCES offer(offer_id) ON DELETE CASCADE
-            );
-
-            CREATE TABLE IF NOT EXISTS trade_offer_drafts (
-                draft_id TEXT PRIMARY KEY,
-                offer_id TEXT NOT NULL,
-                partner_trade_url TEXT NOT NULL,
-                autosend INTEGER NOT NULL DEFAULT 0,
-                created_at INTEGER NOT NULL
-            );
-
-            CREATE TABLE IF NOT EXISTS trade_offer_draft_items (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                draft_id TEXT NOT NULL,
-                appid INTEGER NOT NULL,
-                contextid TEXT NOT NULL,
-                assetid TEXT NOT NULL,
-                amount INTEGER NOT NULL DEFAULT 1,
-                side TEXT NOT NULL DEFAULT 'give',  -- 'give' now, later could add 'receive'
-                FOREIGN KEY(draft_id) REFERENCES trade_offer_drafts(draft_id) ON DELETE CASCADE
-            );
-
-            CREATE INDEX IF NOT EXISTS idx_draft_items_draft_id ON trade_offer_draft_items(draft_id);
-
-
-        ").expect("DB: Failed to create tables");
+    fn db_mark_notifications_read(&self, steamid: &str, up_to_id: i64, now: i64) -> Result<usize, DbError> {
+        Ok(self.connection.execute(
+            "UPDATE notifications SET read_at = ?3 WHERE steamid = ?1 AND id <= ?2 AND read_at IS NULL",
+            rusqlite::params![steamid, up_to_id, now],
+        )?)
     }
 }
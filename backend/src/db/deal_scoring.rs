@@ -0,0 +1,48 @@
+use super::pool::env_parse;
+
+/// Where the reference price for `discount_pct` comes from: the median of the last
+/// `window` listings of the same market_hash_name in the feed, when there are at least
+/// `min_samples` of them, else Steam's cached priceoverview median.
+#[derive(Clone, Debug)]
+pub struct DealScoring {
+    pub window: usize,
+    pub min_samples: usize,
+    /// Feed listings older than this (seconds) don't count towards the median.
+    pub max_sample_age: i64,
+    /// Cached priceoverview rows older than this (seconds) are ignored.
+    pub max_overview_age: i64,
+}
+
+impl Default for DealScoring {
+    fn default() -> Self {
+        DealScoring {
+            window: 50,
+            min_samples: 5,
+            max_sample_age: 14 * 86400,
+            max_overview_age: 86400,
+        }
+    }
+}
+
+impl DealScoring {
+    /// Default scoring with overrides from DEAL_WINDOW, DEAL_MIN_SAMPLES,
+    /// DEAL_MAX_SAMPLE_AGE_SECS and DEAL_MAX_OVERVIEW_AGE_SECS.
+    pub fn from_env() -> Self {
+        let mut scoring = DealScoring::default();
+
+        if let Some(v) = env_parse::<usize>("DEAL_WINDOW") {
+            scoring.window = v.max(1);
+        }
+        if let Some(v) = env_parse::<usize>("DEAL_MIN_SAMPLES") {
+            scoring.min_samples = v.max(1);
+        }
+        if let Some(v) = env_parse("DEAL_MAX_SAMPLE_AGE_SECS") {
+            scoring.max_sample_age = v;
+        }
+        if let Some(v) = env_parse("DEAL_MAX_OVERVIEW_AGE_SECS") {
+            scoring.max_overview_age = v;
+        }
+
+        scoring
+    }
+}
@@ -0,0 +1,135 @@
+use std::fmt;
+use actix_web::{HttpResponse, ResponseError, http::StatusCode};
+use rusqlite::ErrorCode;
+
+/// Everything a `db_*` call can fail with, sorted by what the caller should do about it.
+#[derive(Debug)]
+pub enum DbError {
+    /// The row we asked for isn't there. Names what was missing ("offer", "trade_url").
+    NotFound(&'static str),
+    /// UNIQUE / FOREIGN KEY / CHECK failed, or the request contradicts what's stored.
+    Constraint(String),
+    /// Another connection held the lock for longer than busy_timeout.
+    Busy,
+    /// A stored value doesn't fit the type we read it into, or the file itself is damaged.
+    Corrupt(String),
+    /// Anything else SQLite reported.
+    Sqlite(rusqlite::Error),
+}
+
+impl DbError {
+    /// For `map_err` on lookups: an empty result becomes `NotFound(what)`.
+    pub fn missing(what: &'static str) -> impl FnOnce(rusqlite::Error) -> DbError {
+        move |e| match e {
+            rusqlite::Error::QueryReturnedNoRows => DbError::NotFound(what),
+            e => e.into(),
+        }
+    }
+
+    /// Short machine-readable name, used as the `error` field in JSON responses.
+    pub fn kind(&self) -> String {
+        match self {
+            DbError::NotFound(what) => format!("{what}_not_found"),
+            DbError::Constraint(_) => "conflict".to_string(),
+            DbError::Busy => "database_busy".to_string(),
+            DbError::Corrupt(_) => "database_corrupt".to_string(),
+            DbError::Sqlite(_) => "database_error".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::NotFound(what) => write!(f, "DB: {what} not found"),
+            DbError::Constraint(msg) => write!(f, "DB: constraint failed: {msg}"),
+            DbError::Busy => write!(f, "DB: database is busy"),
+            DbError::Corrupt(msg) => write!(f, "DB: corrupt data: {msg}"),
+            DbError::Sqlite(e) => write!(f, "DB: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for DbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DbError::Sqlite(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for DbError {
+    fn from(e: rusqlite::Error) -> Self {
+        match &e {
+            rusqlite::Error::QueryReturnedNoRows => DbError::NotFound("row"),
+            rusqlite::Error::FromSqlConversionFailure(..)
+            | rusqlite::Error::InvalidColumnType(..)
+            | rusqlite::Error::IntegralValueOutOfRange(..) => DbError::Corrupt(e.to_string()),
+            rusqlite::Error::SqliteFailure(failure, _) => match failure.code {
+                ErrorCode::ConstraintViolation => DbError::Constraint(e.to_string()),
+                ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked => DbError::Busy,
+                ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase => DbError::Corrupt(e.to_string()),
+                _ => DbError::Sqlite(e),
+            },
+            _ => DbError::Sqlite(e),
+        }
+    }
+}
+
+impl ResponseError for DbError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            DbError::NotFound(_) => StatusCode::NOT_FOUND,
+            DbError::Constraint(_) => StatusCode::CONFLICT,
+            DbError::Busy | DbError::Corrupt(_) | DbError::Sqlite(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        //Don't hand SQL details to clients, the log has them
+        if self.status_code().is_server_error() {
+            eprintln!("{self}");
+        }
+
+        let message = match self {
+            DbError::NotFound(_) | DbError::Constraint(_) => self.to_string(),
+            _ => "Database error".to_string(),
+        };
+
+        HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "error": self.kind(),
+            "message": message,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    #[test]
+    fn sqlite_errors_are_sorted_by_cause() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT UNIQUE); INSERT INTO t (name) VALUES ('a');")
+            .unwrap();
+
+        let missing = conn
+            .query_row("SELECT name FROM t WHERE id = 99", [], |row| row.get::<_, String>(0))
+            .map_err(DbError::missing("thing"))
+            .unwrap_err();
+        assert!(matches!(missing, DbError::NotFound("thing")));
+        assert_eq!(missing.status_code(), StatusCode::NOT_FOUND);
+
+        let duplicate: DbError = conn.execute("INSERT INTO t (name) VALUES ('a')", []).unwrap_err().into();
+        assert!(matches!(duplicate, DbError::Constraint(_)));
+        assert_eq!(duplicate.status_code(), StatusCode::CONFLICT);
+
+        let wrong_type: DbError = conn
+            .query_row("SELECT name FROM t", [], |row| row.get::<_, i64>(0))
+            .unwrap_err()
+            .into();
+        assert!(matches!(wrong_type, DbError::Corrupt(_)));
+    }
+}
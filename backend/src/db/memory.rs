@@ -0,0 +1,765 @@
+use std::{collections::HashMap, sync::Mutex};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+use steam_market_parser::{
+    CatalogItem,
+    CurrencyRates,
+    DraftItem,
+    FeedMatcher,
+    FeedQuery,
+    highlight_html,
+    SearchHit,
+    SearchQuery,
+    MARK_END,
+    MARK_START,
+    Game,
+    Money,
+    MostRecent,
+    Notification,
+    ObservedRate,
+    OfferDraft,
+    OfferItems,
+    SavedSearch,
+    SavedSearchInput,
+    SteamUser,
+    UserParamsFromDB,
+    OFFER_CURRENCY
+};
+
+use super::{AlertRepo, CatalogRepo, DbError, DraftRepo, FeedRepo, NewFeedItem, NewGame, OfferRepo, UserRepo, WalletRepo, STRIPE_WALLET_HOLD};
+
+/// Repository traits over plain collections, for tests that shouldn't need steam_items.db.
+#[derive(Default)]
+pub struct MemoryDb {
+    state: Mutex<MemoryState>,
+}
+
+#[derive(Default, Clone)]
+struct MemoryState {
+    users: HashMap<String, MemoryUser>,
+    stripe_customers: HashMap<String, String>,
+    offers: HashMap<String, MemoryOffer>,
+    games: HashMap<u32, NewGame>,
+    catalog: HashMap<(u32, String, String), CatalogItem>,
+    feed: Vec<MemoryFeedItem>,
+    feed_archive: Vec<MemoryFeedItem>,
+    //Last id handed out, ids aren't reused after a prune (AUTOINCREMENT)
+    feed_seq: usize,
+    rates: CurrencyRates,
+    transactions: Vec<(String, String, Money, Money)>,
+    user_wallets: HashMap<String, String>,
+    stripe_wallet: Vec<MemoryWalletRow>,
+    drafts: HashMap<String, (Vec<DraftItem>, bool)>,
+    //(median cents, fetched_at) by (appid, market_hash_name, currency)
+    price_overviews: HashMap<(u32, String, u32), (i64, i64)>,
+    saved_searches: Vec<SavedSearch>,
+    saved_search_seq: i64,
+    //With the item_feed id they matched
+    notifications: Vec<(Notification, usize)>,
+    notification_seq: i64,
+}
+
+#[derive(Clone)]
+struct MemoryUser {
+    nickname: String,
+    trade_url: Option<String>,
+    currency: Option<u32>,
+}
+
+#[derive(Clone)]
+struct MemoryFeedItem {
+    id: usize,
+    item: NewFeedItem,
+    created_at: i64,
+    reference_price: Option<i64>,
+}
+
+#[derive(Clone)]
+struct MemoryOffer {
+    buyer: String,
+    trader: String,
+    price: Money,
+    status: String,
+    //(round, item), round 0 is the empty placeholder
+    log: Vec<(i64, Option<OfferItems>)>,
+}
+
+#[derive(Clone)]
+struct MemoryWalletRow {
+    id: i64,
+    stripe_id: String,
+    offer_id: String,
+    amount_cents: i64,
+    status: String,
+    stripe_event_id: String,
+    stripe_transfer_id: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+impl MemoryDb {
+    fn state(&self) -> std::sync::MutexGuard<'_, MemoryState> {
+        self.state.lock().expect("MemoryDb: lock poisoned")
+    }
+
+    fn with_offer<T>(&self, offer_id: &str, f: impl FnOnce(&mut MemoryOffer) -> T) -> Result<T, DbError> {
+        let mut state = self.state();
+        let offer = state.offers.get_mut(offer_id).ok_or(DbError::NotFound("offer"))?;
+        Ok(f(offer))
+    }
+
+    /// Status as the offer table would show it, for assertions.
+    pub fn offer_status(&self, offer_id: &str) -> Option<String> {
+        self.state().offers.get(offer_id).map(|offer| offer.status.clone())
+    }
+
+    /// Stands in for a cached priceoverview row.
+    pub fn set_cached_median(&self, appid: u32, market_hash_name: &str, currency: u32, median: i64, fetched_at: i64) {
+        self.state().price_overviews.insert((appid, market_hash_name.to_string(), currency), (median, fetched_at));
+    }
+
+    pub fn archived_feed_ids(&self) -> Vec<usize> {
+        self.state().feed_archive.iter().map(|stored| stored.id).collect()
+    }
+}
+
+impl OfferRepo for MemoryDb {
+    fn db_offer_exists(&self, offer_id: &str) -> Result<bool, DbError> {
+        Ok(self.state().offers.contains_key(offer_id))
+    }
+
+    fn db_offer_insert(&self, offer_id: &str, buyer: &str, trader: &str, _time: &str) -> Result<(), DbError> {
+        self.state().offers.entry(offer_id.to_string()).or_insert_with(|| MemoryOffer {
+            buyer: buyer.to_string(),
+            trader: trader.to_string(),
+            price: Money::zero(OFFER_CURRENCY),
+            status: "IN PROCESS".to_string(),
+            log: vec![(0, None)],
+        });
+        Ok(())
+    }
+
+    fn db_offer_latest_round(&self, offer_id: &str) -> Result<i64, DbError> {
+        Ok(self
+            .state()
+            .offers
+            .get(offer_id)
+            .and_then(|offer| offer.log.iter().map(|(round, _)| *round).max())
+            .unwrap_or(0))
+    }
+
+    fn db_offer_round_items(&self, offer_id: &str, round: i64) -> Result<Vec<OfferItems>, DbError> {
+        Ok(self
+            .state()
+            .offers
+            .get(offer_id)
+            .map(|offer| {
+                offer.log
+                    .iter()
+                    .filter(|(r, _)| *r == round)
+                    .filter_map(|(_, item)| item.clone())
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    fn db_offer_log_item(&self, offer_id: &str, round: i64, item: &OfferItems, _time: &str) -> Result<(), DbError> {
+        self.with_offer(offer_id, |offer| offer.log.push((round, Some(item.clone()))))
+    }
+
+    fn db_offer_set_totals(&self, offer_id: &str, total_price: Money, _total_count: i32) -> Result<(), DbError> {
+        self.with_offer(offer_id, |offer| offer.price = total_price)
+    }
+
+    fn db_offer_set_status(&self, offer_id: &str, status: &str, _accepted: bool, _paid: bool) -> Result<(), DbError> {
+        self.with_offer(offer_id, |offer| offer.status = status.to_string())
+    }
+
+    fn db_offer_get_offer_price(&self, offer_id: String) -> Result<Money, DbError> {
+        self.with_offer(&offer_id, |offer| offer.price)
+    }
+
+    fn db_get_buyer_steamid_by_offer(&self, offer_id: &str) -> Result<String, DbError> {
+        self.with_offer(offer_id, |offer| offer.buyer.clone())
+    }
+
+    fn db_get_trader_steamid_by_offer(&self, offer_id: &str) -> Result<String, DbError> {
+        self.with_offer(offer_id, |offer| offer.trader.clone())
+    }
+}
+
+impl UserRepo for MemoryDb {
+    fn db_add_steam_user(&self, steam_user: &SteamUser) -> Result<(), DbError> {
+        let mut state = self.state();
+        let user = state.users.entry(steam_user.steamid.clone()).or_insert_with(|| MemoryUser {
+            nickname: String::new(),
+            trade_url: None,
+            currency: None,
+        });
+        user.nickname = steam_user.nickname.clone();
+        Ok(())
+    }
+
+    //Online status only matters to the store list, which doesn't read from here
+    fn db_change_user_status(&self, _session: SteamUser) -> Result<(), DbError> {
+        Ok(())
+    }
+
+    fn db_account_post_trade_url(&self, steam_id: &str, trade_url: &str) -> Result<(), DbError> {
+        let mut state = self.state();
+        let user = state.users.get_mut(steam_id).ok_or(DbError::NotFound("steam_user"))?;
+        user.trade_url = Some(trade_url.to_string());
+        Ok(())
+    }
+
+    fn db_account_reset_trade_url(&self, steam_id: &str) -> Result<(), DbError> {
+        if let Some(user) = self.state().users.get_mut(steam_id) {
+            user.trade_url = None;
+        }
+        Ok(())
+    }
+
+    fn db_account_get_trade_url(&self, steam_id: String) -> Result<String, DbError> {
+        let state = self.state();
+        let user = state.users.get(&steam_id).ok_or(DbError::NotFound("steam_user"))?;
+        user.trade_url.clone().ok_or(DbError::NotFound("trade_url"))
+    }
+
+    fn db_account_post_currency(&self, steam_id: &str, currency: u32) -> Result<(), DbError> {
+        if let Some(user) = self.state().users.get_mut(steam_id) {
+            user.currency = Some(currency);
+        }
+        Ok(())
+    }
+
+    fn db_account_get_currency(&self, steam_id: &str) -> Result<Option<u32>, DbError> {
+        Ok(self.state().users.get(steam_id).and_then(|user| user.currency))
+    }
+
+    fn db_get_user_params(&self, steam_id: String) -> Result<UserParamsFromDB, DbError> {
+        let state = self.state();
+        let user = state.users.get(&steam_id).ok_or(DbError::NotFound("steam_user"))?;
+
+        Ok(UserParamsFromDB {
+            user_name: user.nickname.clone(),
+            user_trade_url: user.trade_url.clone().ok_or(DbError::NotFound("trade_url"))?,
+            user_steam_id: steam_id,
+        })
+    }
+
+    fn db_insert_stripe_customer_id(&self, steam_id: &str, stripe_customer_id: &str) -> Result<(), DbError> {
+        self.state().stripe_customers.insert(steam_id.to_string(), stripe_customer_id.to_string());
+        Ok(())
+    }
+
+    fn db_get_stripe_customer_id(&self, steam_id: &str) -> Result<Option<String>, DbError> {
+        Ok(self.state().stripe_customers.get(steam_id).cloned())
+    }
+}
+
+impl FeedRepo for MemoryDb {
+    fn db_feed_last_id(&self) -> Result<i64, DbError> {
+        Ok(self.state().feed.last().map_or(0, |stored| stored.id as i64))
+    }
+
+    fn db_in_transaction<T>(&self, f: impl FnOnce(&Self) -> Result<T, DbError>) -> Result<T, DbError> {
+        let snapshot = self.state().clone();
+        let out = f(self);
+        if out.is_err() {
+            *self.state() = snapshot;
+        }
+        out
+    }
+
+    fn db_insert_feed_item(&self, item: &NewFeedItem) -> Result<bool, DbError> {
+        let mut state = self.state();
+
+        if state.feed.iter().any(|stored| stored.item.listinginfo_id == item.listinginfo_id) {
+            return Ok(false);
+        }
+
+        state.feed_seq += 1;
+        let id = state.feed_seq;
+        state.feed.push(MemoryFeedItem { id, item: item.clone(), created_at: Utc::now().timestamp(), reference_price: None });
+        Ok(true)
+    }
+
+    fn db_get_most_recent_items(&self, start_id: i64, end_id: i64) -> Result<Vec<MostRecent>, DbError> {
+        let state = self.state();
+
+        Ok(state
+            .feed
+            .iter()
+            .filter(|stored| (stored.id as i64) > start_id && (stored.id as i64) <= end_id)
+            .filter_map(|stored| {
+                let key = &stored.item.item;
+                let item = state.catalog.get(&(key.appid, key.classid.clone(), key.instanceid.clone()))?;
+                let game = state.games.get(&item.appid);
+                let mut listing = MostRecent {
+                    id: stored.id,
+                    listinginfo_id: stored.item.listinginfo_id.clone(),
+                    name: item.name.clone(),
+                    price: stored.item.price,
+                    appid: item.appid.to_string(),
+                    game: game.map(|game| game.name.clone()).unwrap_or_default(),
+                    market_hash_name: item.market_hash_name.clone(),
+                    tradable: if item.tradable { "1" } else { "0" }.to_string(),
+                    icon: item.icon_url.clone(),
+                    game_icon: game.map(|game| game.icon.clone()).unwrap_or_default(),
+                    rarity: item.rarity.clone(),
+                    exterior: item.exterior.clone(),
+                    reference_price: None,
+                    discount_pct: None,
+                };
+                let currency = stored.item.price.map_or(3, |price| price.currency);
+                listing.score_deal(stored.reference_price.map(|cents| Money::new(cents, currency)));
+                Some(listing)
+            })
+            .collect())
+    }
+
+    fn db_feed_price_samples(
+        &self,
+        appid: u32,
+        market_hash_name: &str,
+        currency: u32,
+        before_id: usize,
+        created_after: i64,
+        limit: usize,
+    ) -> Result<Vec<i64>, DbError> {
+        let state = self.state();
+
+        Ok(state
+            .feed
+            .iter()
+            .rev()
+            .filter(|stored| stored.id < before_id && stored.created_at >= created_after)
+            .filter(|stored| {
+                let key = &stored.item.item;
+                state
+                    .catalog
+                    .get(&(key.appid, key.classid.clone(), key.instanceid.clone()))
+                    .is_some_and(|item| item.appid == appid && item.market_hash_name == market_hash_name)
+            })
+            .filter_map(|stored| stored.item.price.filter(|price| price.currency == currency))
+            .map(|price| price.cents)
+            .take(limit)
+            .collect())
+    }
+
+    fn db_cached_median_price(&self, appid: u32, market_hash_name: &str, currency: u32, fetched_after: i64) -> Result<Option<i64>, DbError> {
+        Ok(self
+            .state()
+            .price_overviews
+            .get(&(appid, market_hash_name.to_string(), currency))
+            .filter(|(_, fetched_at)| *fetched_at >= fetched_after)
+            .map(|(median, _)| *median))
+    }
+
+    fn db_set_reference_prices(&mut self, prices: &[(usize, i64)]) -> Result<(), DbError> {
+        let mut state = self.state();
+
+        for (id, cents) in prices {
+            if let Some(stored) = state.feed.iter_mut().find(|stored| stored.id == *id) {
+                stored.reference_price = Some(*cents);
+            }
+        }
+        Ok(())
+    }
+
+    fn db_scan_feed(&self, query: &FeedQuery, matcher: &FeedMatcher, limit: usize) -> Result<Vec<MostRecent>, DbError> {
+        let start = query.after_id.unwrap_or(-1);
+        let end = query.before_id.map_or(i64::MAX, |before| before - 1);
+        let filter = matcher.filter();
+
+        let mut items: Vec<MostRecent> = self
+            .db_get_most_recent_items(start, end)?
+            .into_iter()
+            .filter(|item| !item.game.is_empty())
+            .filter(|item| filter.appids.is_empty() || item.appid.parse().is_ok_and(|appid| filter.appids.contains(&appid)))
+            .filter(|item| matcher.game().is_none_or(|game| item.appid == game || item.game.to_lowercase().contains(game)))
+            .filter(|item| matcher.text().is_none_or(|text| item.name.to_lowercase().contains(text)))
+            .filter(|item| !filter.tradable_only || item.tradable == "1")
+            .collect();
+
+        if !query.oldest_first() {
+            items.reverse();
+        }
+        items.truncate(limit);
+        Ok(items)
+    }
+
+    fn db_search_feed(&self, query: &SearchQuery) -> Result<Vec<SearchHit>, DbError> {
+        let terms = query.terms();
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+        let game_matches =
+            |item: &MostRecent, game: &str| item.appid == game || item.game.to_lowercase().contains(&game.to_lowercase());
+
+        //Same column weights as the bm25 call, without the term frequencies
+        let mut hits: Vec<(u32, SearchHit)> = self
+            .db_get_most_recent_items(-1, i64::MAX)?
+            .into_iter()
+            .filter(|item| query.game().is_none_or(|game| game_matches(item, game)))
+            .filter_map(|item| {
+                let mut score = 0;
+                for term in &terms {
+                    score += [(&item.name, 10), (&item.market_hash_name, 5), (&item.game, 1)]
+                        .into_iter()
+                        .find(|(text, _)| words(text).any(|(_, word)| word.to_lowercase().starts_with(term.as_str())))
+                        .map(|(_, weight)| weight)?;
+                }
+                let highlight = highlight_html(&mark_terms(&item.name, &terms));
+                Some((score, SearchHit { item, highlight }))
+            })
+            .collect();
+
+        hits.sort_by(|(a_score, a), (b_score, b)| b_score.cmp(a_score).then(b.item.id.cmp(&a.item.id)));
+        hits.truncate(query.page_limit());
+        Ok(hits.into_iter().map(|(_, hit)| hit).collect())
+    }
+
+    fn db_upsert_games(&self, games: &[NewGame]) -> Result<(), DbError> {
+        let mut state = self.state();
+        for game in games {
+            state.games.insert(game.appid, game.clone());
+        }
+        Ok(())
+    }
+
+    fn db_get_games(&self) -> Result<Vec<Game>, DbError> {
+        let state = self.state();
+
+        let mut games: Vec<Game> = state
+            .games
+            .values()
+            .map(|game| Game {
+                appid: game.appid,
+                name: game.name.clone(),
+                icon: game.icon.clone(),
+                listing_count: state.feed.iter().filter(|stored| stored.item.item.appid == game.appid).count() as i64,
+            })
+            .collect();
+        games.sort_by(|a, b| b.listing_count.cmp(&a.listing_count).then_with(|| a.name.cmp(&b.name)));
+        Ok(games)
+    }
+
+    fn db_upsert_currency_rates(&self, rates: &[ObservedRate]) -> Result<(), DbError> {
+        let mut state = self.state();
+        for rate in rates {
+            state.rates.insert(rate.from_currency, rate.to_currency, rate.rate);
+        }
+        Ok(())
+    }
+
+    fn db_get_currency_rates(&self) -> Result<CurrencyRates, DbError> {
+        Ok(self.state().rates.clone())
+    }
+
+    fn db_feed_prune_bound(&self, created_before: Option<i64>, keep_rows: Option<i64>) -> Result<i64, DbError> {
+        let state = self.state();
+
+        let aged = created_before
+            .and_then(|before| state.feed.iter().filter(|stored| stored.created_at < before).map(|stored| stored.id).max())
+            .unwrap_or(0);
+        let overflow = keep_rows
+            .and_then(|keep| state.feed.iter().rev().nth(keep as usize).map(|stored| stored.id))
+            .unwrap_or(0);
+
+        Ok(aged.max(overflow) as i64)
+    }
+
+    fn db_feed_archive_batch(&mut self, up_to_id: i64, limit: usize, archive: bool) -> Result<usize, DbError> {
+        let mut state = self.state();
+
+        let moved = state.feed.iter().take(limit).take_while(|stored| stored.id as i64 <= up_to_id).count();
+        let batch: Vec<MemoryFeedItem> = state.feed.drain(..moved).collect();
+        if archive {
+            state.feed_archive.extend(batch);
+        }
+        Ok(moved)
+    }
+}
+
+impl CatalogRepo for MemoryDb {
+    fn db_upsert_catalog_items(&self, items: &[CatalogItem]) -> Result<(), DbError> {
+        let mut state = self.state();
+
+        for item in items {
+            let key = (item.appid, item.classid.clone(), item.instanceid.clone());
+            let Some(stored) = state.catalog.get_mut(&key) else {
+                state.catalog.insert(key, item.clone());
+                continue;
+            };
+
+            //Same merge as the SQL upsert: what this source doesn't know stays as it was
+            let mut merged = item.clone();
+            merged.item_type = item.item_type.clone().or(stored.item_type.take());
+            merged.rarity = item.rarity.clone().or(stored.rarity.take());
+            merged.exterior = item.exterior.clone().or(stored.exterior.take());
+            merged.inspect_link = item.inspect_link.clone().or(stored.inspect_link.take());
+            merged.name_color = item.name_color.clone().or(stored.name_color.take());
+            merged.trade_restriction_days = item.trade_restriction_days.or(stored.trade_restriction_days);
+            merged.market_restriction_days = item.market_restriction_days.or(stored.market_restriction_days);
+            if merged.icon_url.is_empty() {
+                merged.icon_url = std::mem::take(&mut stored.icon_url);
+            }
+            if merged.descriptions.is_empty() {
+                merged.descriptions = std::mem::take(&mut stored.descriptions);
+            }
+            *stored = merged;
+        }
+        Ok(())
+    }
+
+    fn db_get_catalog_item(&self, appid: u32, classid: &str, instanceid: &str) -> Result<CatalogItem, DbError> {
+        self.state()
+            .catalog
+            .get(&(appid, classid.to_string(), instanceid.to_string()))
+            .cloned()
+            .ok_or(DbError::NotFound("item"))
+    }
+}
+
+impl WalletRepo for MemoryDb {
+    fn db_insert_buyer_transaction(
+        &self,
+        steamid: String,
+        offer_id: String,
+        amount: Money,
+        amount_with_fee: Money,
+        _method: String,
+        _pay_method: String,
+    ) -> Result<(), DbError> {
+        self.state().transactions.push((steamid, offer_id, amount, amount_with_fee));
+        Ok(())
+    }
+
+    fn db_insert_stripe_wallet_transaction(
+        &self,
+        _steamid: &str,
+        stripe_acct: &str,
+        offer_id: &str,
+        amount_cents: i64,
+        stripe_event_id: &str,
+    ) -> Result<i64, DbError> {
+        let mut state = self.state();
+
+        if state.stripe_wallet.iter().any(|row| row.stripe_event_id == stripe_event_id) {
+            return Err(DbError::Constraint(format!("stripe_event_id {stripe_event_id} already booked")));
+        }
+
+        let id = state.stripe_wallet.len() as i64 + 1;
+        state.stripe_wallet.push(MemoryWalletRow {
+            id,
+            stripe_id: stripe_acct.to_string(),
+            offer_id: offer_id.to_string(),
+            amount_cents,
+            status: "LOCKED".to_string(),
+            stripe_event_id: stripe_event_id.to_string(),
+            stripe_transfer_id: None,
+            created_at: Utc::now(),
+        });
+        Ok(id)
+    }
+
+    fn db_check_stripe_wallet_transaction_availability(&self, now: DateTime<Utc>) -> Result<usize, DbError> {
+        let mut released = 0;
+        for row in self.state().stripe_wallet.iter_mut() {
+            if row.status == "LOCKED" && row.created_at <= now - STRIPE_WALLET_HOLD {
+                row.status = "AVAILABLE".to_string();
+                released += 1;
+            }
+        }
+        Ok(released)
+    }
+
+    fn db_get_stripe_wallet_available(&self, limit: i64) -> Result<Vec<(i64, String, String, i64)>, DbError> {
+        Ok(self
+            .state()
+            .stripe_wallet
+            .iter()
+            .filter(|row| row.status == "AVAILABLE" && row.stripe_transfer_id.is_none())
+            .take(limit.max(0) as usize)
+            .map(|row| (row.id, row.stripe_id.clone(), row.offer_id.clone(), row.amount_cents))
+            .collect())
+    }
+
+    fn db_mark_stripe_wallet_transferred(&self, row_id: i64, transfer_id: &str) -> Result<bool, DbError> {
+        let mut state = self.state();
+        let Some(row) = state
+            .stripe_wallet
+            .iter_mut()
+            .find(|row| row.id == row_id && row.status == "AVAILABLE")
+        else {
+            return Ok(false);
+        };
+
+        row.status = "TRANSFERRED".to_string();
+        row.stripe_transfer_id = Some(transfer_id.to_string());
+        Ok(true)
+    }
+
+    fn db_get_connected_stripe_trader_acct_for_steamid(&self, steamid: &str) -> Result<Option<String>, DbError> {
+        Ok(self.state().user_wallets.get(steamid).cloned())
+    }
+
+    fn db_upsert_user_stripe_id(&self, steamid: &str, acct: &str) -> Result<(), DbError> {
+        self.state().user_wallets.insert(steamid.to_string(), acct.to_string());
+        Ok(())
+    }
+}
+
+impl DraftRepo for MemoryDb {
+    fn db_create_offer_draft(
+        &mut self,
+        _offer_id: &str,
+        _partner_trade_url: &str,
+        autosend: bool,
+        give_items: Vec<DraftItem>,
+    ) -> Result<String, DbError> {
+        let draft_id = Uuid::new_v4().to_string();
+        self.state().drafts.insert(draft_id.clone(), (give_items, autosend));
+        Ok(draft_id)
+    }
+
+    fn db_get_offer_draft(&self, draft_id: &str) -> Result<OfferDraft, DbError> {
+        let state = self.state();
+        let (give, autosend) = state.drafts.get(draft_id).ok_or(DbError::NotFound("draft"))?;
+
+        Ok(OfferDraft {
+            give: give.clone(),
+            autosend: *autosend,
+        })
+    }
+}
+
+impl AlertRepo for MemoryDb {
+    fn db_insert_saved_search(&self, steamid: &str, search: &SavedSearchInput, now: i64) -> Result<SavedSearch, DbError> {
+        let mut state = self.state();
+        if !state.users.contains_key(steamid) {
+            return Err(DbError::Constraint(format!("steam user {steamid} doesn't exist")));
+        }
+        let name = search.name.trim();
+        if state.saved_searches.iter().any(|saved| saved.steamid == steamid && saved.name == name) {
+            return Err(DbError::Constraint(format!("saved search {name} already exists")));
+        }
+
+        state.saved_search_seq += 1;
+        let saved = SavedSearch {
+            id: state.saved_search_seq,
+            steamid: steamid.to_string(),
+            name: name.to_string(),
+            appid: search.appid.trim().to_string(),
+            price_min: search.price_min,
+            price_max: search.price_max,
+            query: search.query.trim().to_string(),
+            currency: search.currency,
+            created_at: now,
+        };
+        state.saved_searches.push(saved.clone());
+        Ok(saved)
+    }
+
+    fn db_get_saved_searches(&self, steamid: &str) -> Result<Vec<SavedSearch>, DbError> {
+        Ok(self.state().saved_searches.iter().filter(|saved| saved.steamid == steamid).cloned().collect())
+    }
+
+    fn db_get_all_saved_searches(&self) -> Result<Vec<SavedSearch>, DbError> {
+        Ok(self.state().saved_searches.clone())
+    }
+
+    fn db_delete_saved_search(&self, steamid: &str, id: i64) -> Result<(), DbError> {
+        let mut state = self.state();
+        let before = state.saved_searches.len();
+        state.saved_searches.retain(|saved| !(saved.id == id && saved.steamid == steamid));
+        if state.saved_searches.len() == before {
+            return Err(DbError::NotFound("saved_search"));
+        }
+
+        state.notifications.retain(|(notification, _)| notification.saved_search_id != id);
+        Ok(())
+    }
+
+    fn db_insert_notifications(&mut self, matches: &[(SavedSearch, MostRecent)], now: i64) -> Result<Vec<Notification>, DbError> {
+        let mut state = self.state();
+        let mut stored = Vec::new();
+
+        for (search, item) in matches {
+            let known = state
+                .notifications
+                .iter()
+                .any(|(notification, feed_id)| notification.saved_search_id == search.id && *feed_id == item.id);
+            if known {
+                continue;
+            }
+
+            state.notification_seq += 1;
+            let notification = Notification {
+                id: state.notification_seq,
+                steamid: search.steamid.clone(),
+                saved_search_id: search.id,
+                search_name: search.name.clone(),
+                item: item.clone(),
+                created_at: now,
+                read: false,
+            };
+            state.notifications.push((notification.clone(), item.id));
+            stored.push(notification);
+        }
+
+        Ok(stored)
+    }
+
+    fn db_get_notifications(&self, steamid: &str, unread_only: bool, limit: usize) -> Result<Vec<Notification>, DbError> {
+        Ok(self
+            .state()
+            .notifications
+            .iter()
+            .rev()
+            .map(|(notification, _)| notification)
+            .filter(|notification| notification.steamid == steamid && !(unread_only && notification.read))
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    fn db_mark_notifications_read(&self, steamid: &str, up_to_id: i64, _now: i64) -> Result<usize, DbError> {
+        let mut marked = 0;
+
+        for (notification, _) in self.state().notifications.iter_mut() {
+            if notification.steamid == steamid && notification.id <= up_to_id && !notification.read {
+                notification.read = true;
+                marked += 1;
+            }
+        }
+
+        Ok(marked)
+    }
+}
+
+/// Runs of letters and digits with their byte offset, roughly what unicode61 calls tokens.
+fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(move |word| (word.as_ptr() as usize - text.as_ptr() as usize, word))
+}
+
+/// `text` with every word that starts with one of `terms` marked, like FTS5's highlight().
+fn mark_terms(text: &str, terms: &[String]) -> String {
+    let mut marked = String::with_capacity(text.len() + 8);
+    let mut copied = 0;
+
+    for (start, word) in words(text) {
+        if terms.iter().any(|term| word.to_lowercase().starts_with(term.as_str())) {
+            marked.push_str(&text[copied..start]);
+            marked.push(MARK_START);
+            marked.push_str(word);
+            marked.push(MARK_END);
+            copied = start + word.len();
+        }
+    }
+
+    marked.push_str(&text[copied..]);
+    marked
+}
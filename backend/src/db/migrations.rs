@@ -0,0 +1,1016 @@
+use rusqlite::{Connection, Transaction};
+use chrono::Utc;
+use std::fmt;
+
+use steam_market_parser::{Money, OFFER_CURRENCY};
+
+/// One schema change. Steps run in `version` order, each in its own transaction.
+/// Steps up to 4 predate `schema_version` and must stay idempotent: databases
+/// created by those builds have some of their changes already.
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    up: fn(&Transaction) -> Result<(), rusqlite::Error>,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, name: "initial_schema", up: initial_schema },
+    Migration { version: 2, name: "price_cache_tables", up: price_cache_tables },
+    Migration { version: 3, name: "feed_currency", up: feed_currency },
+    Migration { version: 4, name: "prices_to_cents", up: prices_to_cents },
+    Migration { version: 5, name: "feed_retention", up: feed_retention },
+    Migration { version: 6, name: "games_catalog", up: games_catalog },
+    Migration { version: 7, name: "item_catalog", up: item_catalog },
+    Migration { version: 8, name: "feed_search", up: feed_search },
+    Migration { version: 9, name: "saved_searches", up: saved_searches },
+    Migration { version: 10, name: "deal_reference_price", up: deal_reference_price },
+];
+
+#[derive(Debug)]
+pub struct MigrationError {
+    pub version: u32,
+    pub name: &'static str,
+    pub source: rusqlite::Error,
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DB migration {} ({}) failed: {}", self.version, self.name, self.source)
+    }
+}
+
+impl std::error::Error for MigrationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Highest applied version, 0 for a new or pre-versioning database.
+pub fn current_version(conn: &Connection) -> Result<u32, rusqlite::Error> {
+    ensure_version_table(conn)?;
+    conn.query_row("SELECT COALESCE(MAX(version), 0) FROM schema_version", [], |row| row.get(0))
+}
+
+pub fn pending(conn: &Connection) -> Result<Vec<&'static Migration>, rusqlite::Error> {
+    pending_in(conn, MIGRATIONS)
+}
+
+/// Applies every pending step, returns the ones that ran.
+pub fn migrate(conn: &mut Connection) -> Result<Vec<&'static Migration>, MigrationError> {
+    migrate_with(conn, MIGRATIONS)
+}
+
+/// Prints pending steps without touching the schema.
+pub fn dry_run(conn: &Connection) -> Result<(), rusqlite::Error> {
+    let version = current_version(conn)?;
+    let pending = pending(conn)?;
+
+    if pending.is_empty() {
+        println!("DB: schema is up to date (version {version})");
+    } else {
+        println!("DB: schema version {version}, {} pending migration(s):", pending.len());
+        for step in pending {
+            println!("  {} {}", step.version, step.name);
+        }
+    }
+    Ok(())
+}
+
+fn pending_in<'a>(conn: &Connection, steps: &'a [Migration]) -> Result<Vec<&'a Migration>, rusqlite::Error> {
+    let version = current_version(conn)?;
+    Ok(steps.iter().filter(|step| step.version > version).collect())
+}
+
+fn migrate_with<'a>(conn: &mut Connection, steps: &'a [Migration]) -> Result<Vec<&'a Migration>, MigrationError> {
+    let to_run = pending_in(conn, steps).map_err(|source| MigrationError { version: 0, name: "schema_version", source })?;
+
+    for step in &to_run {
+        let fail = |source| MigrationError { version: step.version, name: step.name, source };
+
+        let tx = conn.transaction().map_err(fail)?;
+        (step.up)(&tx).map_err(fail)?;
+        tx.execute(
+            "INSERT INTO schema_version (version, name, applied_at) VALUES (?1, ?2, ?3)",
+            rusqlite::params![step.version, step.name, Utc::now().timestamp()],
+        ).map_err(fail)?;
+        tx.commit().map_err(fail)?;
+
+        println!("DB: applied migration {} ({})", step.version, step.name);
+    }
+
+    Ok(to_run)
+}
+
+fn ensure_version_table(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute_batch("
+        CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at INTEGER NOT NULL     -- unix seconds
+        );
+    ")
+}
+
+//----------------------------------
+//----------------------------------
+//Steps
+
+fn initial_schema(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute_batch("
+    CREATE TABLE IF NOT EXISTS item_feed (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        listinginfo_id TEXT UNIQUE,
+        name TEXT,
+        converted_price TEXT,
+        game TEXT,
+        appid TEXT,
+        market_hash_name TEXT,
+        tradable TEXT,
+        icon_url TEXT,
+        game_icon TEXT
+    );
+    CREATE TABLE IF NOT EXISTS steam_user (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        steamid TEXT UNIQUE,
+        nickname TEXT,
+        avatar_url_small TEXT,
+        avatar_url_full TEXT,
+        trade_url TEXT,
+        status TEXT
+    );
+    CREATE TABLE IF NOT EXISTS user_wallets (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        steamid TEXT UNIQUE,     -- 1:1 with steam_user
+        stripe_id TEXT UNIQUE,
+        bitcoin TEXT UNIQUE,
+        FOREIGN KEY (steamid)
+            REFERENCES steam_user(steamid)
+            ON UPDATE CASCADE
+            ON DELETE CASCADE
+        );
+    CREATE TABLE IF NOT EXISTS stripe_wallet (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+
+        steamid TEXT NOT NULL,          -- tie to wallet owner (cascade path)
+        stripe_id TEXT NOT NULL,        -- optional consistency
+        offer_id TEXT NOT NULL,
+        amount_cents INTEGER NOT NULL,
+        status TEXT NOT NULL,
+        stripe_event_id TEXT UNIQUE,
+        stripe_transfer_id TEXT,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL,
+
+        FOREIGN KEY (steamid)
+            REFERENCES user_wallets(steamid)
+            ON UPDATE CASCADE
+            ON DELETE CASCADE,
+
+        -- optional: ensure stripe_id exists in user_wallets (prevents random stripe_id values)
+        FOREIGN KEY (stripe_id)
+            REFERENCES user_wallets(stripe_id)
+            ON UPDATE CASCADE
+            ON DELETE RESTRICT
+        );
+
+    CREATE TABLE IF NOT EXISTS stripe_customer (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        steamid TEXT UNIQUE NOT NULL,
+        stripe_customer_id TEXT UNIQUE NOT NULL,
+        FOREIGN KEY (steamid)
+            REFERENCES steam_user(steamid)
+            ON UPDATE CASCADE
+            ON DELETE CASCADE
+        );
+
+    CREATE TABLE IF NOT EXISTS transactions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        steamid TEXT NOT NULL,
+        offer_id TEXT NOT NULL,
+        amount TEXT NOT NULL,
+        amount_with_fee TEXT NOT NULL,
+        method TEXT NOT NULL,
+        pay_method TEXT NOT NULL,
+        status TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL,
+        FOREIGN KEY (steamid)
+            REFERENCES steam_user(steamid)
+            ON UPDATE CASCADE
+            ON DELETE CASCADE
+        );
+
+    CREATE INDEX IF NOT EXISTS idx_user_wallets_steamid ON user_wallets(steamid);
+    CREATE INDEX IF NOT EXISTS idx_stripe_wallet_steamid ON stripe_wallet(steamid);
+    CREATE INDEX IF NOT EXISTS idx_transactions_steamid ON transactions(steamid);
+
+    CREATE TABLE IF NOT EXISTS ad_steam_user (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        steamid TEXT UNIQUE,
+        nickname TEXT,
+        avatar_url_full TEXT,
+        first_item_image TEXT,
+        second_item_image TEXT,
+        third_item_image TEXT,
+        fourth_item_image TEXT
+    );
+    CREATE TABLE IF NOT EXISTS chat_messages (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        chat_id TEXT,
+        buyer_steamid TEXT,
+        trader_steamid TEXT,
+        message_type TEXT,
+        message TEXT,
+        data TEXT
+    );
+    CREATE TABLE IF NOT EXISTS offer (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        offer_id TEXT UNIQUE,
+        buyer_steamid TEXT,
+        trader_steamid TEXT,
+        count TEXT,
+        price TEXT,
+        accepted BOOLEAN,
+        paid BOOLEAN,
+        status TEXT,
+        created TEXT,
+        last_update TEXT
+    );
+    CREATE TABLE IF NOT EXISTS offer_log (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        offer_id TEXT,
+        round INTEGER,
+        item_asset_id TEXT,
+        item_contextid TEXT,
+        item_appid TEXT,
+        item_name TEXT,
+        items_price TEXT,
+        item_link TEXT,
+        item_image TEXT,
+        time TEXT,
+        FOREIGN KEY (offer_id) REFERENCES offer(offer_id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS trade_offer_drafts (
+        draft_id TEXT PRIMARY KEY,
+        offer_id TEXT NOT NULL,
+        partner_trade_url TEXT NOT NULL,
+        autosend INTEGER NOT NULL DEFAULT 0,
+        created_at INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS trade_offer_draft_items (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        draft_id TEXT NOT NULL,
+        appid INTEGER NOT NULL,
+        contextid TEXT NOT NULL,
+        assetid TEXT NOT NULL,
+        amount INTEGER NOT NULL DEFAULT 1,
+        side TEXT NOT NULL DEFAULT 'give',  -- 'give' now, later could add 'receive'
+        FOREIGN KEY(draft_id) REFERENCES trade_offer_drafts(draft_id) ON DELETE CASCADE
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_draft_items_draft_id ON trade_offer_draft_items(draft_id);
+    ")
+}
+
+fn price_cache_tables(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute_batch("
+    CREATE TABLE IF NOT EXISTS price_overview (
+        appid INTEGER NOT NULL,
+        market_hash_name TEXT NOT NULL,
+        currency INTEGER NOT NULL,
+        lowest_price INTEGER,           -- cents
+        median_price INTEGER,           -- cents
+        volume INTEGER,
+        lowest_price_text TEXT,
+        median_price_text TEXT,
+        fetched_at INTEGER NOT NULL,    -- unix seconds
+        PRIMARY KEY (appid, market_hash_name, currency)
+    );
+
+    CREATE TABLE IF NOT EXISTS item_nameid (
+        appid INTEGER NOT NULL,
+        market_hash_name TEXT NOT NULL,
+        item_nameid INTEGER NOT NULL,
+        PRIMARY KEY (appid, market_hash_name)
+    );
+
+    CREATE TABLE IF NOT EXISTS order_book (
+        appid INTEGER NOT NULL,
+        market_hash_name TEXT NOT NULL,
+        currency INTEGER NOT NULL,
+        item_nameid INTEGER NOT NULL,
+        highest_buy_order INTEGER,      -- cents
+        lowest_sell_order INTEGER,      -- cents
+        buy_orders TEXT NOT NULL,       -- JSON [{price, quantity}]
+        sell_orders TEXT NOT NULL,      -- JSON [{price, quantity}]
+        fetched_at INTEGER NOT NULL,    -- unix seconds
+        PRIMARY KEY (appid, market_hash_name, currency)
+    );
+
+    CREATE TABLE IF NOT EXISTS price_history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        appid INTEGER NOT NULL,
+        market_hash_name TEXT NOT NULL,
+        currency INTEGER NOT NULL,
+        price INTEGER NOT NULL,         -- cents
+        volume INTEGER NOT NULL DEFAULT 0,
+        source TEXT NOT NULL,           -- 'feed' | 'overview'
+        observed_at INTEGER NOT NULL    -- unix seconds
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_price_history_name_time ON price_history(market_hash_name, currency, observed_at);
+    CREATE INDEX IF NOT EXISTS idx_price_history_time ON price_history(observed_at);
+
+    CREATE TABLE IF NOT EXISTS price_history_hourly (
+        appid INTEGER NOT NULL,
+        market_hash_name TEXT NOT NULL,
+        currency INTEGER NOT NULL,
+        bucket_start INTEGER NOT NULL,  -- unix seconds, whole hour
+        open INTEGER NOT NULL,
+        high INTEGER NOT NULL,
+        low INTEGER NOT NULL,
+        close INTEGER NOT NULL,
+        volume INTEGER NOT NULL,
+        PRIMARY KEY (appid, market_hash_name, currency, bucket_start)
+    );
+    ")
+}
+
+fn feed_currency(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    add_column_if_missing(tx, "item_feed", "currency", "INTEGER")?;      // Steam currency id of converted_price
+    add_column_if_missing(tx, "steam_user", "currency", "INTEGER")?;     // display currency, NULL = feed currency
+
+    tx.execute_batch("
+    CREATE TABLE IF NOT EXISTS currency_rates (
+        from_currency INTEGER NOT NULL,
+        to_currency INTEGER NOT NULL,
+        rate REAL NOT NULL,             -- cents in to_currency per cent in from_currency
+        samples INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL,    -- unix seconds
+        PRIMARY KEY (from_currency, to_currency)
+    );
+    ")
+}
+
+/// Prices used to be TEXT: feed and transactions in cents, offers in dollars
+/// ("12.5", "$12.50"). Rewrites each column as INTEGER cents.
+fn prices_to_cents(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    add_column_if_missing(tx, "offer", "currency", "INTEGER NOT NULL DEFAULT 1")?;
+    add_column_if_missing(tx, "transactions", "currency", "INTEGER NOT NULL DEFAULT 1")?;
+
+    //(table, column, stored as dollars)
+    let columns = [
+        ("item_feed", "converted_price", false),
+        ("offer", "price", true),
+        ("offer_log", "items_price", true),
+        ("transactions", "amount", false),
+        ("transactions", "amount_with_fee", false),
+    ];
+
+    for (table, column, dollars) in columns {
+        if column_type(tx, table, column)?.as_deref() != Some("TEXT") {
+            continue;
+        }
+
+        let tmp = format!("{column}_cents");
+
+        tx.execute_batch(&format!("ALTER TABLE {table} ADD COLUMN {tmp} INTEGER;"))?;
+
+        let rows: Vec<(i64, Option<String>)> = tx
+            .prepare(&format!("SELECT rowid, {column} FROM {table}"))?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+
+        for (rowid, text) in rows {
+            let cents = text.as_deref().and_then(|text| match dollars {
+                true => Money::parse(text, OFFER_CURRENCY).map(|money| money.cents),
+                false => text.trim().parse::<i64>().ok(),
+            });
+            tx.execute(
+                &format!("UPDATE {table} SET {tmp} = ?1 WHERE rowid = ?2"),
+                rusqlite::params![cents, rowid],
+            )?;
+        }
+
+        tx.execute_batch(&format!(
+            "ALTER TABLE {table} DROP COLUMN {column};
+             ALTER TABLE {table} RENAME COLUMN {tmp} TO {column};"
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// item_feed used to grow forever. created_at lets the retention task age rows
+/// out; rows from before this step count as first seen now.
+fn feed_retention(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    add_column_if_missing(tx, "item_feed", "created_at", "INTEGER")?;   // unix seconds, first seen
+
+    tx.execute("UPDATE item_feed SET created_at = ?1 WHERE created_at IS NULL", [Utc::now().timestamp()])?;
+
+    //id is the rowid, so range scans on it are already indexed
+    tx.execute_batch("
+    CREATE INDEX IF NOT EXISTS idx_item_feed_appid ON item_feed(appid);
+    CREATE INDEX IF NOT EXISTS idx_item_feed_game ON item_feed(game);
+    CREATE INDEX IF NOT EXISTS idx_item_feed_created_at ON item_feed(created_at);
+
+    CREATE TABLE IF NOT EXISTS item_feed_archive (
+        id INTEGER PRIMARY KEY,         -- same id it had in item_feed
+        listinginfo_id TEXT,
+        name TEXT,
+        converted_price INTEGER,        -- cents
+        currency INTEGER,
+        game TEXT,
+        appid TEXT,
+        market_hash_name TEXT,
+        tradable TEXT,
+        icon_url TEXT,
+        game_icon TEXT,
+        created_at INTEGER,             -- unix seconds
+        archived_at INTEGER NOT NULL    -- unix seconds
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_item_feed_archive_created_at ON item_feed_archive(created_at);
+    ")
+}
+
+/// item_feed copied the game name and icon into every listing. They move to a
+/// games table keyed by appid, item_feed and its archive keep the appid as INTEGER.
+fn games_catalog(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    let last_seq = feed_sequence(tx)?;
+
+    tx.execute_batch("
+    CREATE TABLE IF NOT EXISTS games (
+        appid INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        icon TEXT NOT NULL,
+        link TEXT,
+        updated_at INTEGER NOT NULL     -- unix seconds, last poll that listed it
+    );
+    ")?;
+
+    //Bare columns next to MAX(id) come from that row, so each game keeps its newest name and icon
+    for table in ["item_feed", "item_feed_archive"] {
+        tx.execute(
+            &format!(
+                "INSERT OR IGNORE INTO games (appid, name, icon, link, updated_at)
+                 SELECT appid, name, icon, NULL, ?1 FROM (
+                    SELECT CAST(appid AS INTEGER) AS appid, COALESCE(game, '') AS name,
+                           COALESCE(game_icon, '') AS icon, MAX(id)
+                    FROM {table}
+                    WHERE appid GLOB '[0-9]*'
+                    GROUP BY CAST(appid AS INTEGER)
+                 )"
+            ),
+            [Utc::now().timestamp()],
+        )?;
+    }
+
+    tx.execute_batch("
+    DROP INDEX IF EXISTS idx_item_feed_appid;
+    DROP INDEX IF EXISTS idx_item_feed_game;
+    DROP INDEX IF EXISTS idx_item_feed_created_at;
+    DROP INDEX IF EXISTS idx_item_feed_archive_created_at;
+
+    CREATE TABLE item_feed_rebuilt (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        listinginfo_id TEXT UNIQUE,
+        name TEXT,
+        converted_price INTEGER,        -- cents
+        currency INTEGER,
+        appid INTEGER REFERENCES games(appid),
+        market_hash_name TEXT,
+        tradable TEXT,
+        icon_url TEXT,
+        created_at INTEGER              -- unix seconds, first seen
+    );
+    INSERT INTO item_feed_rebuilt
+        (id, listinginfo_id, name, converted_price, currency, appid, market_hash_name, tradable, icon_url, created_at)
+    SELECT id, listinginfo_id, name, converted_price, currency,
+           CASE WHEN appid GLOB '[0-9]*' THEN CAST(appid AS INTEGER) END,
+           market_hash_name, tradable, icon_url, created_at
+    FROM item_feed;
+    DROP TABLE item_feed;
+    ALTER TABLE item_feed_rebuilt RENAME TO item_feed;
+
+    CREATE TABLE item_feed_archive_rebuilt (
+        id INTEGER PRIMARY KEY,         -- same id it had in item_feed
+        listinginfo_id TEXT,
+        name TEXT,
+        converted_price INTEGER,        -- cents
+        currency INTEGER,
+        appid INTEGER,
+        market_hash_name TEXT,
+        tradable TEXT,
+        icon_url TEXT,
+        created_at INTEGER,             -- unix seconds
+        archived_at INTEGER NOT NULL    -- unix seconds
+    );
+    INSERT INTO item_feed_archive_rebuilt
+        (id, listinginfo_id, name, converted_price, currency, appid, market_hash_name, tradable, icon_url, created_at, archived_at)
+    SELECT id, listinginfo_id, name, converted_price, currency,
+           CASE WHEN appid GLOB '[0-9]*' THEN CAST(appid AS INTEGER) END,
+           market_hash_name, tradable, icon_url, created_at, archived_at
+    FROM item_feed_archive;
+    DROP TABLE item_feed_archive;
+    ALTER TABLE item_feed_archive_rebuilt RENAME TO item_feed_archive;
+
+    CREATE INDEX IF NOT EXISTS idx_item_feed_appid ON item_feed(appid);
+    CREATE INDEX IF NOT EXISTS idx_item_feed_created_at ON item_feed(created_at);
+    CREATE INDEX IF NOT EXISTS idx_item_feed_archive_created_at ON item_feed_archive(created_at);
+    ")?;
+
+    restore_feed_sequence(tx, last_seq)
+}
+
+/// Listings repeated the item's name, hash name, icon and tradable flag. Those now
+/// live once per (appid, classid, instanceid) in item_catalog, next to what we parse
+/// out of the asset. Listings from before this step never stored a classid, they get
+/// one catalog entry per item name under classid 'legacy' and a made-up numeric
+/// instanceid, which no Steam asset can collide with. Live listings without an appid
+/// have no game to reference, they move to item_feed_archive under appid 0.
+fn item_catalog(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    let last_seq = feed_sequence(tx)?;
+
+    tx.execute_batch("
+    CREATE TABLE IF NOT EXISTS item_catalog (
+        appid INTEGER NOT NULL,
+        classid TEXT NOT NULL,
+        instanceid TEXT NOT NULL,
+        name TEXT NOT NULL,
+        market_hash_name TEXT NOT NULL,
+        item_type TEXT,
+        rarity TEXT,
+        exterior TEXT,
+        inspect_link TEXT,              -- still has %owner_steamid% / %assetid% placeholders
+        icon_url TEXT NOT NULL,
+        name_color TEXT,
+        tradable INTEGER NOT NULL,
+        marketable INTEGER NOT NULL,
+        trade_restriction_days INTEGER,
+        market_restriction_days INTEGER,
+        descriptions TEXT NOT NULL,     -- JSON array of lines
+        updated_at INTEGER NOT NULL,    -- unix seconds
+        PRIMARY KEY (appid, classid, instanceid)
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_item_catalog_hash_name ON item_catalog(appid, market_hash_name);
+
+    CREATE TEMP TABLE legacy_items (
+        instanceid INTEGER PRIMARY KEY,
+        appid INTEGER NOT NULL,
+        market_hash_name TEXT NOT NULL,
+        name TEXT NOT NULL,
+        icon_url TEXT NOT NULL,
+        tradable INTEGER NOT NULL,
+        UNIQUE (appid, market_hash_name)
+    );
+    ")?;
+
+    //The live feed goes first, so an item keeps its newest name and icon
+    for table in ["item_feed", "item_feed_archive"] {
+        tx.execute_batch(&format!(
+            "INSERT OR IGNORE INTO legacy_items (appid, market_hash_name, name, icon_url, tradable)
+             SELECT appid, market_hash_name, name, icon_url, tradable FROM (
+                SELECT COALESCE(appid, 0) AS appid, COALESCE(market_hash_name, '') AS market_hash_name,
+                       COALESCE(name, '') AS name, COALESCE(icon_url, '') AS icon_url,
+                       tradable = '1' AS tradable, MAX(id)
+                FROM {table}
+                GROUP BY COALESCE(appid, 0), COALESCE(market_hash_name, '')
+             );"
+        ))?;
+    }
+
+    tx.execute(
+        "INSERT OR IGNORE INTO item_catalog
+            (appid, classid, instanceid, name, market_hash_name, icon_url, tradable, marketable, descriptions, updated_at)
+         SELECT appid, 'legacy', CAST(instanceid AS TEXT), name, market_hash_name, icon_url, tradable, 1, '[]', ?1
+         FROM legacy_items",
+        [Utc::now().timestamp()],
+    )?;
+
+    let count = |sql: &str| tx.query_row(sql, [], |row| row.get::<_, i64>(0));
+    let feed_rows = count("SELECT COUNT(*) FROM item_feed")?;
+    let archive_rows = count("SELECT COUNT(*) FROM item_feed_archive")?;
+    let without_appid = count("SELECT COUNT(*) FROM item_feed WHERE appid IS NULL")?;
+
+    tx.execute_batch("
+    DROP INDEX IF EXISTS idx_item_feed_appid;
+    DROP INDEX IF EXISTS idx_item_feed_created_at;
+    DROP INDEX IF EXISTS idx_item_feed_archive_created_at;
+
+    CREATE TABLE item_feed_archive_rebuilt (
+        id INTEGER PRIMARY KEY,         -- same id it had in item_feed
+        listinginfo_id TEXT,
+        appid INTEGER NOT NULL,
+        classid TEXT NOT NULL,
+        instanceid TEXT NOT NULL,
+        converted_price INTEGER,        -- cents
+        currency INTEGER,
+        created_at INTEGER,             -- unix seconds
+        archived_at INTEGER NOT NULL    -- unix seconds
+    );
+    INSERT INTO item_feed_archive_rebuilt
+        (id, listinginfo_id, appid, classid, instanceid, converted_price, currency, created_at, archived_at)
+    SELECT f.id, f.listinginfo_id, l.appid, 'legacy', CAST(l.instanceid AS TEXT), f.converted_price, f.currency, f.created_at, f.archived_at
+    FROM item_feed_archive f
+    JOIN legacy_items l ON l.appid = COALESCE(f.appid, 0) AND l.market_hash_name = COALESCE(f.market_hash_name, '');
+    INSERT INTO item_feed_archive_rebuilt
+        (id, listinginfo_id, appid, classid, instanceid, converted_price, currency, created_at, archived_at)
+    SELECT f.id, f.listinginfo_id, 0, 'legacy', CAST(l.instanceid AS TEXT), f.converted_price, f.currency, f.created_at,
+           CAST(strftime('%s', 'now') AS INTEGER)
+    FROM item_feed f
+    JOIN legacy_items l ON l.appid = 0 AND l.market_hash_name = COALESCE(f.market_hash_name, '')
+    WHERE f.appid IS NULL;
+    DROP TABLE item_feed_archive;
+    ALTER TABLE item_feed_archive_rebuilt RENAME TO item_feed_archive;
+
+    CREATE TABLE item_feed_rebuilt (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        listinginfo_id TEXT UNIQUE,
+        appid INTEGER NOT NULL REFERENCES games(appid),
+        classid TEXT NOT NULL,
+        instanceid TEXT NOT NULL,
+        converted_price INTEGER,        -- cents
+        currency INTEGER,
+        created_at INTEGER,             -- unix seconds, first seen
+        FOREIGN KEY (appid, classid, instanceid) REFERENCES item_catalog(appid, classid, instanceid)
+    );
+    INSERT INTO item_feed_rebuilt (id, listinginfo_id, appid, classid, instanceid, converted_price, currency, created_at)
+    SELECT f.id, f.listinginfo_id, l.appid, 'legacy', CAST(l.instanceid AS TEXT), f.converted_price, f.currency, f.created_at
+    FROM item_feed f
+    JOIN legacy_items l ON l.appid = f.appid AND l.market_hash_name = COALESCE(f.market_hash_name, '')
+    WHERE f.appid IS NOT NULL;
+    DROP TABLE item_feed;
+    ALTER TABLE item_feed_rebuilt RENAME TO item_feed;
+
+    DROP TABLE legacy_items;
+
+    CREATE INDEX IF NOT EXISTS idx_item_feed_appid ON item_feed(appid);
+    CREATE INDEX IF NOT EXISTS idx_item_feed_created_at ON item_feed(created_at);
+    CREATE INDEX IF NOT EXISTS idx_item_feed_catalog ON item_feed(appid, classid, instanceid);
+    CREATE INDEX IF NOT EXISTS idx_item_feed_archive_created_at ON item_feed_archive(created_at);
+    ")?;
+
+    //Every listing has a legacy_items row, the joins above keep them all
+    println!(
+        "DB: item_catalog kept {feed_rows} feed and {archive_rows} archived listing(s) under classid 'legacy', \
+         archived {without_appid} feed listing(s) without an appid under appid 0"
+    );
+
+    restore_feed_sequence(tx, last_seq)
+}
+
+/// Full-text index over what /api/search matches, one row per item_feed row under the same id.
+/// Triggers keep it in step with item_feed, the catalog and games. Rebuilding item_feed
+/// drops its triggers, a migration that does so has to create them again.
+fn feed_search(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute_batch("
+    CREATE VIRTUAL TABLE IF NOT EXISTS feed_search USING fts5(
+        name,
+        market_hash_name,
+        game,
+        tokenize = 'unicode61 remove_diacritics 2',
+        prefix = '2 3'
+    );
+
+    CREATE TRIGGER IF NOT EXISTS feed_search_insert AFTER INSERT ON item_feed BEGIN
+        INSERT INTO feed_search (rowid, name, market_hash_name, game)
+        SELECT NEW.id, c.name, c.market_hash_name, COALESCE(g.name, '')
+        FROM item_catalog c
+        LEFT JOIN games g ON g.appid = c.appid
+        WHERE c.appid = NEW.appid AND c.classid = NEW.classid AND c.instanceid = NEW.instanceid;
+    END;
+
+    CREATE TRIGGER IF NOT EXISTS feed_search_delete AFTER DELETE ON item_feed BEGIN
+        DELETE FROM feed_search WHERE rowid = OLD.id;
+    END;
+
+    -- Upserts rewrite the name on every poll, only actual renames touch the index
+    CREATE TRIGGER IF NOT EXISTS feed_search_catalog_rename AFTER UPDATE OF name, market_hash_name ON item_catalog
+    WHEN OLD.name IS NOT NEW.name OR OLD.market_hash_name IS NOT NEW.market_hash_name BEGIN
+        UPDATE feed_search SET name = NEW.name, market_hash_name = NEW.market_hash_name
+        WHERE rowid IN (
+            SELECT id FROM item_feed
+            WHERE appid = NEW.appid AND classid = NEW.classid AND instanceid = NEW.instanceid
+        );
+    END;
+
+    CREATE TRIGGER IF NOT EXISTS feed_search_game_rename AFTER UPDATE OF name ON games
+    WHEN OLD.name IS NOT NEW.name BEGIN
+        UPDATE feed_search SET game = NEW.name
+        WHERE rowid IN (SELECT id FROM item_feed WHERE appid = NEW.appid);
+    END;
+
+    DELETE FROM feed_search;
+    INSERT INTO feed_search (rowid, name, market_hash_name, game)
+    SELECT f.id, c.name, c.market_hash_name, COALESCE(g.name, '')
+    FROM item_feed f
+    JOIN item_catalog c USING (appid, classid, instanceid)
+    LEFT JOIN games g ON g.appid = f.appid;
+    ")
+}
+
+fn saved_searches(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute_batch("
+    CREATE TABLE IF NOT EXISTS saved_searches (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        steamid TEXT NOT NULL REFERENCES steam_user(steamid) ON DELETE CASCADE,
+        name TEXT NOT NULL,
+        appid TEXT NOT NULL,            -- appid or part of a game name, '' for any game
+        price_min INTEGER,              -- cents in `currency`
+        price_max INTEGER,
+        query TEXT NOT NULL,
+        currency INTEGER,               -- NULL for the feed currency
+        created_at INTEGER NOT NULL,    -- unix seconds
+        UNIQUE (steamid, name)
+    );
+
+    CREATE TABLE IF NOT EXISTS notifications (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        steamid TEXT NOT NULL REFERENCES steam_user(steamid) ON DELETE CASCADE,
+        saved_search_id INTEGER NOT NULL REFERENCES saved_searches(id) ON DELETE CASCADE,
+        feed_id INTEGER NOT NULL,       -- item_feed id, the row may be archived since
+        item TEXT NOT NULL,             -- MostRecent JSON as it matched
+        created_at INTEGER NOT NULL,    -- unix seconds
+        read_at INTEGER,
+        UNIQUE (saved_search_id, feed_id)
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_saved_searches_steamid ON saved_searches(steamid);
+    CREATE INDEX IF NOT EXISTS idx_notifications_steamid ON notifications(steamid, id);
+    ")
+}
+
+/// Reference price each listing was scored against, cents in the row's currency.
+fn deal_reference_price(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    add_column_if_missing(tx, "item_feed", "reference_price", "INTEGER")?;
+    add_column_if_missing(tx, "item_feed_archive", "reference_price", "INTEGER")?;
+
+    //Samples are looked up by name, then by id within one item
+    tx.execute_batch("
+    DROP INDEX IF EXISTS idx_item_feed_catalog;
+    CREATE INDEX IF NOT EXISTS idx_item_feed_catalog ON item_feed(appid, classid, instanceid, id);
+    ")
+}
+
+//----------------------------------
+//----------------------------------
+//Helpers
+
+fn column_type(conn: &Connection, table: &str, column: &str) -> Result<Option<String>, rusqlite::Error> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({table})"))?;
+    let mut rows = stmt.query([])?;
+
+    while let Some(row) = rows.next()? {
+        if row.get::<_, String>(1)? == column {
+            return Ok(Some(row.get(2)?));
+        }
+    }
+    Ok(None)
+}
+
+/// item_feed's AUTOINCREMENT counter. Rebuilding the table must not hand out
+/// ids that were already pruned away, clients page by them.
+fn feed_sequence(conn: &Connection) -> Result<i64, rusqlite::Error> {
+    conn.query_row(
+        "SELECT COALESCE((SELECT seq FROM sqlite_sequence WHERE name = 'item_feed'), 0)",
+        [],
+        |row| row.get(0),
+    )
+}
+
+fn restore_feed_sequence(conn: &Connection, seq: i64) -> Result<(), rusqlite::Error> {
+    conn.execute("UPDATE sqlite_sequence SET seq = MAX(seq, ?1) WHERE name = 'item_feed'", [seq])?;
+    Ok(())
+}
+
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<(), rusqlite::Error> {
+    if column_type(conn, table, column)?.is_none() {
+        conn.execute_batch(&format!("ALTER TABLE {table} ADD COLUMN {column} {definition};"))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASELINE_FIXTURE: &str = include_str!("../../tests/fixtures/steam_items_baseline.sql");
+
+    fn baseline_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(BASELINE_FIXTURE).unwrap();
+        conn
+    }
+
+    fn latest() -> u32 {
+        MIGRATIONS.last().unwrap().version
+    }
+
+    #[test]
+    fn versions_are_ordered_and_unique() {
+        assert!(MIGRATIONS.windows(2).all(|pair| pair[0].version < pair[1].version));
+    }
+
+    #[test]
+    fn upgrades_baseline_fixture() {
+        let mut conn = baseline_db();
+
+        assert_eq!(pending(&conn).unwrap().len(), MIGRATIONS.len());
+
+        let applied = migrate(&mut conn).unwrap();
+        assert_eq!(applied.len(), MIGRATIONS.len());
+        assert_eq!(current_version(&conn).unwrap(), latest());
+
+        let feed: Vec<(String, Option<i64>, Option<u32>)> = conn
+            .prepare("SELECT listinginfo_id, converted_price, currency FROM item_feed ORDER BY listinginfo_id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(feed, vec![("1001".to_string(), Some(1234), None), ("1002".to_string(), None, None)]);
+
+        let offer: (i64, u32) = conn
+            .query_row("SELECT price, currency FROM offer WHERE offer_id = 'offer-1'", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!(offer, (1250, OFFER_CURRENCY));
+
+        let log: Vec<Option<i64>> = conn
+            .prepare("SELECT items_price FROM offer_log ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(log, vec![None, Some(1020), Some(230)]);
+
+        let transaction: (i64, i64, u32) = conn
+            .query_row("SELECT amount, amount_with_fee, currency FROM transactions", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(transaction, (1250, 1369, OFFER_CURRENCY));
+
+        assert_eq!(column_type(&conn, "item_feed", "converted_price").unwrap().as_deref(), Some("INTEGER"));
+        assert!(column_type(&conn, "currency_rates", "rate").unwrap().is_some());
+
+        let unaged: i64 = conn
+            .query_row("SELECT COUNT(*) FROM item_feed WHERE created_at IS NULL", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(unaged, 0);
+
+        let games: Vec<(u32, String)> = conn
+            .prepare("SELECT appid, name FROM games ORDER BY appid")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(games, vec![(440, "Team Fortress 2".to_string()), (730, "Counter-Strike 2".to_string())]);
+        assert_eq!(column_type(&conn, "item_feed", "appid").unwrap().as_deref(), Some("INTEGER"));
+        assert!(column_type(&conn, "item_feed", "game").unwrap().is_none());
+
+        let catalog: Vec<(u32, String, String)> = conn
+            .prepare(
+                "SELECT f.appid, c.name, c.icon_url FROM item_feed f
+                 JOIN item_catalog c USING (appid, classid, instanceid) ORDER BY f.id"
+            )
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(catalog, vec![
+            (730, "AK-47 | Redline".to_string(), "icon".to_string()),
+            (440, "Mann Co. Supply Crate Key".to_string(), "icon".to_string()),
+        ]);
+        assert!(column_type(&conn, "item_feed", "market_hash_name").unwrap().is_none());
+
+        //Rebuilding item_feed keeps the AUTOINCREMENT counter
+        conn.execute(
+            "INSERT INTO item_feed (listinginfo_id, appid, classid, instanceid)
+             SELECT '1003', appid, classid, instanceid FROM item_feed WHERE id = 1",
+            [],
+        ).unwrap();
+        assert_eq!(conn.last_insert_rowid(), 3);
+
+        //Existing listings are indexed, new ones through the trigger
+        let indexed: Vec<i64> = conn
+            .prepare("SELECT rowid FROM feed_search WHERE feed_search MATCH 'red* OR key*' ORDER BY rowid")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(indexed, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn item_catalog_keeps_every_listing() {
+        let mut conn = baseline_db();
+        migrate_with(&mut conn, &MIGRATIONS[..6]).unwrap();
+
+        conn.execute_batch("
+            INSERT INTO item_feed (listinginfo_id, name, converted_price, appid, market_hash_name, tradable, icon_url, created_at)
+            VALUES ('1003', 'AK-47 | Redline', 1100, 730, 'AK-47 | Redline (Field-Tested)', '1', 'newer_icon', 0),
+                   ('1004', 'Mystery Box', 50, NULL, 'Mystery Box', '0', 'box', 0);
+            INSERT INTO item_feed_archive
+                (id, listinginfo_id, name, converted_price, appid, market_hash_name, tradable, icon_url, created_at, archived_at)
+            VALUES (-1, '900', 'Old Box', 10, NULL, NULL, '1', 'old', 0, 0);
+        ").unwrap();
+        let rows = |conn: &Connection, table: &str| {
+            conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| row.get::<_, i64>(0)).unwrap()
+        };
+        assert_eq!((rows(&conn, "item_feed"), rows(&conn, "item_feed_archive")), (4, 1));
+
+        migrate(&mut conn).unwrap();
+
+        //The one without an appid is archived, not dropped
+        assert_eq!((rows(&conn, "item_feed"), rows(&conn, "item_feed_archive")), (3, 2));
+
+        let feed: Vec<(String, u32, String, String, String)> = conn
+            .prepare(
+                "SELECT f.listinginfo_id, f.appid, f.classid, f.instanceid, c.icon_url FROM item_feed f
+                 JOIN item_catalog c USING (appid, classid, instanceid) ORDER BY f.id"
+            )
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert!(feed.iter().all(|(_, _, classid, instanceid, _)| classid == "legacy" && instanceid.parse::<u64>().is_ok()));
+        //Both Redline listings share one entry holding the newest icon
+        assert_eq!(feed[0].3, feed[2].3);
+        assert_ne!(feed[0].3, feed[1].3);
+        let icons: Vec<(&str, u32, &str)> = feed.iter().map(|(id, appid, _, _, icon)| (id.as_str(), *appid, icon.as_str())).collect();
+        assert_eq!(icons, vec![("1001", 730, "newer_icon"), ("1002", 440, "icon"), ("1003", 730, "newer_icon")]);
+
+        let archived: Vec<(String, u32, String, String)> = conn
+            .prepare(
+                "SELECT f.listinginfo_id, f.appid, c.name, c.market_hash_name FROM item_feed_archive f
+                 JOIN item_catalog c USING (appid, classid, instanceid) ORDER BY f.id"
+            )
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(archived, vec![
+            ("900".to_string(), 0, "Old Box".to_string(), String::new()),
+            ("1004".to_string(), 0, "Mystery Box".to_string(), "Mystery Box".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn migrate_is_a_no_op_once_current() {
+        let mut conn = baseline_db();
+        migrate(&mut conn).unwrap();
+
+        assert!(pending(&conn).unwrap().is_empty());
+        assert!(migrate(&mut conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn fresh_database_reaches_latest() {
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        assert_eq!(current_version(&conn).unwrap(), latest());
+        assert_eq!(column_type(&conn, "offer", "price").unwrap().as_deref(), Some("INTEGER"));
+    }
+
+    #[test]
+    fn dry_run_leaves_schema_alone() {
+        let conn = baseline_db();
+
+        dry_run(&conn).unwrap();
+
+        assert_eq!(current_version(&conn).unwrap(), 0);
+        assert_eq!(column_type(&conn, "offer", "price").unwrap().as_deref(), Some("TEXT"));
+    }
+
+    #[test]
+    fn failed_step_is_named_and_rolled_back() {
+        fn broken(tx: &Transaction) -> Result<(), rusqlite::Error> {
+            tx.execute_batch("CREATE TABLE half_done (id INTEGER); SELECT * FROM missing_table;")
+        }
+
+        let steps = [
+            Migration { version: 1, name: "initial_schema", up: initial_schema },
+            Migration { version: 2, name: "broken_step", up: broken },
+        ];
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        let err = migrate_with(&mut conn, &steps).err().unwrap();
+
+        assert_eq!((err.version, err.name), (2, "broken_step"));
+        assert!(err.to_string().starts_with("DB migration 2 (broken_step) failed"));
+        assert_eq!(current_version(&conn).unwrap(), 1);
+        assert!(column_type(&conn, "half_done", "id").unwrap().is_none());
+    }
+}
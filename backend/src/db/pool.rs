@@ -0,0 +1,135 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::Semaphore;
+
+use super::{migrations, DataBase, DbError};
+
+/// Where steam_items.db lives and how many connections may be open at once.
+#[derive(Clone, Debug)]
+pub struct DbConfig {
+    pub path: PathBuf,
+    pub max_connections: usize,
+    pub busy_timeout: Duration,
+}
+
+impl Default for DbConfig {
+    fn default() -> Self {
+        DbConfig {
+            path: PathBuf::from("steam_items.db"),
+            max_connections: 8,
+            busy_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl DbConfig {
+    /// Default config with overrides from DATABASE_PATH, DB_MAX_CONNECTIONS and DB_BUSY_TIMEOUT_MS.
+    pub fn from_env() -> Self {
+        let mut config = DbConfig::default();
+
+        if let Ok(path) = std::env::var("DATABASE_PATH") {
+            config.path = PathBuf::from(path);
+        }
+        if let Some(v) = env_parse("DB_MAX_CONNECTIONS") {
+            config.max_connections = v;
+        }
+        if let Some(v) = env_parse("DB_BUSY_TIMEOUT_MS") {
+            config.busy_timeout = Duration::from_millis(v);
+        }
+
+        config
+    }
+}
+
+pub(super) fn env_parse<T: std::str::FromStr>(key: &str) -> Option<T> {
+    std::env::var(key).ok()?.trim().parse().ok()
+}
+
+/// Shared SQLite connections. Every call runs on tokio's blocking pool, so a slow
+/// query or a busy wait never stalls the actix workers.
+#[derive(Clone)]
+pub struct DbPool {
+    inner: Arc<PoolInner>,
+}
+
+struct PoolInner {
+    config: DbConfig,
+    idle: Mutex<Vec<DataBase>>,
+    permits: Semaphore,
+}
+
+impl DbPool {
+    /// Opens the first connection and brings the schema up to date.
+    pub fn open(config: DbConfig) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut first = DataBase::open(&config)?;
+        migrations::migrate(&mut first.connection)?;
+
+        Ok(DbPool {
+            inner: Arc::new(PoolInner {
+                permits: Semaphore::new(config.max_connections.max(1)),
+                idle: Mutex::new(vec![first]),
+                config,
+            }),
+        })
+    }
+
+    pub fn config(&self) -> &DbConfig {
+        &self.inner.config
+    }
+
+    /// Runs `f` with a pooled connection on a blocking thread. Failing to open a new
+    /// connection comes back as `Busy` or `Sqlite`, like an error from `f`.
+    pub async fn run<T, F>(&self, f: F) -> Result<T, DbError>
+    where
+        F: FnOnce(&mut DataBase) -> Result<T, DbError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let _permit = self.inner.permits.acquire().await.expect("DbPool: semaphore closed");
+
+        let idle = self.inner.idle.lock().expect("DbPool: idle lock poisoned").pop();
+        let inner = self.inner.clone();
+
+        let task = tokio::task::spawn_blocking(move || {
+            let mut db = match idle {
+                Some(db) => db,
+                None => DataBase::open(&inner.config)?,
+            };
+
+            let result = f(&mut db);
+
+            //A connection whose closure panicked is dropped instead of reused
+            inner.idle.lock().expect("DbPool: idle lock poisoned").push(db);
+            result
+        });
+
+        match task.await {
+            Ok(result) => result,
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(e) => panic!("DbPool: task cancelled: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn failed_open_is_an_error() {
+        let config = DbConfig { path: PathBuf::from("/nonexistent/steam_items.db"), ..Default::default() };
+        let pool = DbPool {
+            inner: Arc::new(PoolInner {
+                permits: Semaphore::new(1),
+                idle: Mutex::new(Vec::new()),
+                config,
+            }),
+        };
+
+        assert!(matches!(pool.run(|_| Ok(())).await, Err(DbError::Sqlite(_))));
+        //The permit went back, so the pool isn't stuck
+        assert!(matches!(pool.run(|_| Ok(())).await, Err(DbError::Sqlite(_))));
+    }
+}
@@ -0,0 +1,1319 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+use steam_market_parser::{
+    CatalogItem,
+    CurrencyRates,
+    CurrentStatusOffer,
+    FeedPage,
+    FeedMatcher,
+    FeedQuery,
+    DraftItem,
+    median_cents,
+    Game,
+    Money,
+    MostRecent,
+    Notification,
+    ObservedRate,
+    OfferCheckResult,
+    OfferContentToCheck,
+    OfferContentUpdated,
+    OfferDraft,
+    OfferItems,
+    SavedSearch,
+    SavedSearchInput,
+    SearchHit,
+    SearchQuery,
+    SteamMostRecentResponse,
+    SteamUser,
+    UserParamsFromDB,
+    OFFER_CURRENCY
+};
+
+use super::{DbError, DealScoring, FeedRetention};
+
+/// How long a paid-out credit stays LOCKED before the transfer loop may send it.
+pub const STRIPE_WALLET_HOLD: chrono::Duration = chrono::Duration::minutes(1);
+
+//==================
+//Offers
+
+/// Offers and their item rounds in offer_log. Every update writes a new round,
+/// the round logic itself lives in the provided methods so all stores share it.
+pub trait OfferRepo {
+    fn db_offer_exists(&self, offer_id: &str) -> Result<bool, DbError>;
+
+    /// Inserts the offer row plus the empty round 0.
+    fn db_offer_insert(&self, offer_id: &str, buyer: &str, trader: &str, time: &str) -> Result<(), DbError>;
+
+    fn db_offer_latest_round(&self, offer_id: &str) -> Result<i64, DbError>;
+
+    fn db_offer_round_items(&self, offer_id: &str, round: i64) -> Result<Vec<OfferItems>, DbError>;
+
+    fn db_offer_log_item(&self, offer_id: &str, round: i64, item: &OfferItems, time: &str) -> Result<(), DbError>;
+
+    fn db_offer_set_totals(&self, offer_id: &str, total_price: Money, total_count: i32) -> Result<(), DbError>;
+
+    /// NotFound("offer") when nothing was updated.
+    fn db_offer_set_status(&self, offer_id: &str, status: &str, accepted: bool, paid: bool) -> Result<(), DbError>;
+
+    fn db_offer_get_offer_price(&self, offer_id: String) -> Result<Money, DbError>;
+
+    fn db_get_buyer_steamid_by_offer(&self, offer_id: &str) -> Result<String, DbError>;
+
+    fn db_get_trader_steamid_by_offer(&self, offer_id: &str) -> Result<String, DbError>;
+
+    fn db_offer_make_offer(&self, buyer: String, trader: String) -> Result<String, DbError> {
+        let time = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+
+        let mut generated_uuid = Uuid::new_v4().to_string();
+        while self.db_offer_exists(&generated_uuid)? {
+            generated_uuid = Uuid::new_v4().to_string();
+        }
+
+        self.db_offer_insert(&generated_uuid, &buyer, &trader, &time)?;
+
+        Ok(generated_uuid)
+    }
+
+    /// Stores `items` as the next round and reports what changed against the previous one.
+    fn db_offer_update_offer(&self, offer_id: String, items: Vec<OfferItems>) -> Result<OfferContentUpdated, DbError> {
+        if !self.db_offer_exists(&offer_id)? {
+            return Err(DbError::NotFound("offer"));
+        }
+
+        let round = self.db_offer_latest_round(&offer_id)?;
+        let previous_offer = self.db_offer_round_items(&offer_id, round)?;
+
+        let mut result = OfferContentUpdated {
+            offer_id: offer_id.clone(),
+            total_price: Money::zero(OFFER_CURRENCY),
+            total_count: 0,
+            new_items: Vec::new(),
+            added_items: Vec::new(),
+            removed_items: Vec::new(),
+            updated_items: Vec::new(),
+        };
+
+        let previous_by_asset: HashMap<&str, &OfferItems> = previous_offer
+            .iter()
+            .map(|item| (item.item_asset_id.as_str(), item))
+            .collect();
+
+        for item in &previous_offer {
+            if !items.iter().any(|new| new.item_asset_id == item.item_asset_id) {
+                result.removed_items.push(item.clone());
+            }
+        }
+
+        let time = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+
+        for item in items {
+            result.total_count += 1;
+            result.total_price = result
+                .total_price
+                .checked_add(item.item_price)
+                .ok_or_else(|| DbError::Constraint(format!("offer {offer_id}: items must share the offer currency")))?;
+
+            match previous_by_asset.get(item.item_asset_id.as_str()) {
+                None => result.added_items.push(item.clone()),
+                Some(previous) if previous.item_price != item.item_price => result.updated_items.push(item.clone()),
+                Some(_) => {}
+            }
+
+            self.db_offer_log_item(&offer_id, round + 1, &item, &time)?;
+            result.new_items.push(item);
+        }
+
+        self.db_offer_set_totals(&offer_id, result.total_price, result.total_count)?;
+
+        Ok(result)
+    }
+
+    fn db_offer_update_status_offer(&self, status_and_offer_id: CurrentStatusOffer) -> Result<(), DbError> {
+        let (accepted, paid) = match status_and_offer_id.status.as_str() {
+            "ACCEPTED" => (true, false),
+            "PAY PROCESS" | "SUCCESS" => (true, true),
+            _ => (false, false),
+        };
+
+        self.db_offer_set_status(&status_and_offer_id.offer_id, &status_and_offer_id.status, accepted, paid)
+    }
+
+    /// The buyer may only pay for exactly what the latest round holds.
+    fn db_offer_check_offer_to_pay(&self, items_and_offer_id: OfferContentToCheck) -> Result<OfferCheckResult, DbError>
+    where
+        Self: UserRepo,
+    {
+        let offer_id = items_and_offer_id.offer_id;
+        let offer_to_check = items_and_offer_id.special_for_save_offer;
+
+        let round = self.db_offer_latest_round(&offer_id)?;
+        let last_offer = self.db_offer_round_items(&offer_id, round)?;
+
+        if offer_to_check != last_offer {
+            println!("Offer changed");
+            return Ok(OfferCheckResult {
+                offer_id,
+                check_result: false,
+                offer_items: Vec::new(),
+                partner_trade_url: String::from(""),
+            });
+        }
+
+        let partner_trade_url = self.db_account_get_trade_url(items_and_offer_id.partner_steam_id)?;
+
+        println!("Offers match");
+        Ok(OfferCheckResult {
+            offer_id,
+            check_result: true,
+            offer_items: offer_to_check,
+            partner_trade_url,
+        })
+    }
+}
+
+//Offers done
+//=======================
+
+//==================
+//Users
+
+pub trait UserRepo {
+    fn db_add_steam_user(&self, steam_user: &SteamUser) -> Result<(), DbError>;
+
+    fn db_change_user_status(&self, session: SteamUser) -> Result<(), DbError>;
+
+    /// NotFound("steam_user") for an unknown steamid.
+    fn db_account_post_trade_url(&self, steam_id: &str, trade_url: &str) -> Result<(), DbError>;
+
+    fn db_account_reset_trade_url(&self, steam_id: &str) -> Result<(), DbError>;
+
+    /// NotFound("trade_url") when the user exists but never saved one.
+    fn db_account_get_trade_url(&self, steam_id: String) -> Result<String, DbError>;
+
+    fn db_account_post_currency(&self, steam_id: &str, currency: u32) -> Result<(), DbError>;
+
+    fn db_account_get_currency(&self, steam_id: &str) -> Result<Option<u32>, DbError>;
+
+    fn db_get_user_params(&self, steam_id: String) -> Result<UserParamsFromDB, DbError>;
+
+    fn db_insert_stripe_customer_id(&self, steam_id: &str, stripe_customer_id: &str) -> Result<(), DbError>;
+
+    fn db_get_stripe_customer_id(&self, steam_id: &str) -> Result<Option<String>, DbError>;
+}
+
+//Users done
+//=======================
+
+//==================
+//Feed
+
+/// One listing from /market/recent. item_feed keeps the price and the catalog key,
+/// `item` goes to item_catalog.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NewFeedItem {
+    pub listinginfo_id: String,
+    pub price: Option<Money>,
+    pub item: CatalogItem,
+}
+
+/// Listings with their asset and app data joined in. One odd listing shouldn't
+/// cost us the rest of the batch, so those are logged and skipped.
+pub fn feed_items(data: &SteamMostRecentResponse) -> Vec<NewFeedItem> {
+    let mut items = Vec::with_capacity(data.listinginfo.len());
+
+    for listing in data.listinginfo.values() {
+        let listinginfo_id = listing.listingid.to_string();
+        let appid = listing.asset.appid.to_string();
+        let contextid = listing.asset.contextid.to_string();
+        let assetid = listing.asset.id.to_string();
+
+        let listing_asset = data.assets
+            .get(&appid)
+            .and_then(|contexts| contexts.get(&contextid))
+            .and_then(|assets| assets.get(&assetid));
+
+        //Without app data its game never reaches the games table
+        let known_app = data.app_data.contains_key(&appid);
+
+        let (Some(listing_asset), true) = (listing_asset, known_app) else {
+            eprintln!("DB: Listing {listinginfo_id} has no asset or app data, skipped");
+            continue;
+        };
+        let Some(item) = CatalogItem::from_listing_asset(listing_asset) else {
+            eprintln!("DB: Listing {listinginfo_id} is missing asset fields, skipped");
+            continue;
+        };
+
+        let price = match (listing.converted_price, listing.converted_currency()) {
+            (Some(cents), Some(currency)) => Some(Money::new(cents as i64, currency)),
+            _ => None,
+        };
+
+        items.push(NewFeedItem { listinginfo_id, price, item });
+    }
+
+    items
+}
+
+/// One app_data entry, upserted into games on every poll.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NewGame {
+    pub appid: u32,
+    pub name: String,
+    pub icon: String,
+    pub link: String,
+}
+
+pub fn feed_games(data: &SteamMostRecentResponse) -> Vec<NewGame> {
+    data.app_data
+        .values()
+        .filter_map(|app| {
+            let Ok(appid) = u32::try_from(app.appid) else {
+                eprintln!("DB: app_data appid {} out of range, skipped", app.appid);
+                return None;
+            };
+            Some(NewGame { appid, name: app.name.clone(), icon: app.icon.clone(), link: app.link.clone() })
+        })
+        .collect()
+}
+
+//GET /api/feed looks at no more than FEED_SCAN_ROUNDS * FEED_SCAN_BATCH rows per page
+const FEED_SCAN_BATCH: usize = 200;
+const FEED_SCAN_ROUNDS: usize = 10;
+
+pub trait FeedRepo {
+    /// Highest item_feed id so far, 0 for an empty feed.
+    fn db_feed_last_id(&self) -> Result<i64, DbError>;
+
+    /// Runs `f` so that its writes are all kept or, when it fails, all undone.
+    fn db_in_transaction<T>(&self, f: impl FnOnce(&Self) -> Result<T, DbError>) -> Result<T, DbError>
+    where
+        Self: Sized;
+
+    /// False when the listing is already stored.
+    fn db_insert_feed_item(&self, item: &NewFeedItem) -> Result<bool, DbError>;
+
+    fn db_get_most_recent_items(&self, start_id: i64, end_id: i64) -> Result<Vec<MostRecent>, DbError>;
+
+    /// What a live subscriber that has seen up to `seen_id` missed, oldest first like a
+    /// broadcast batch. Only the newest `limit` listings when it missed more.
+    fn db_feed_since(&self, seen_id: i64, limit: usize) -> Result<Vec<MostRecent>, DbError> {
+        let last_id = self.db_feed_last_id()?;
+        self.db_get_most_recent_items(seen_id.max(last_id - limit as i64), last_id)
+    }
+
+    /// Up to `limit` listings past the cursor of `query`, in its direction, narrowed by what
+    /// of `matcher` the store can check. The caller still runs `matcher.matches` on each,
+    /// prices need the currency conversion first.
+    fn db_scan_feed(&self, query: &FeedQuery, matcher: &FeedMatcher, limit: usize) -> Result<Vec<MostRecent>, DbError>;
+
+    /// GET /api/search: listings whose item name, market_hash_name or game has a word
+    /// starting with each term of `query`, best match first.
+    fn db_search_feed(&self, query: &SearchQuery) -> Result<Vec<SearchHit>, DbError>;
+
+    /// Prices (cents) of up to `limit` listings of the item before `before_id`, newest first.
+    /// Only priced listings in `currency`, first seen at or after `created_after`.
+    fn db_feed_price_samples(
+        &self,
+        appid: u32,
+        market_hash_name: &str,
+        currency: u32,
+        before_id: usize,
+        created_after: i64,
+        limit: usize,
+    ) -> Result<Vec<i64>, DbError>;
+
+    /// Steam's median from the priceoverview cache, if fetched at or after `fetched_after`.
+    fn db_cached_median_price(&self, appid: u32, market_hash_name: &str, currency: u32, fetched_after: i64) -> Result<Option<i64>, DbError>;
+
+    /// Stores (item_feed id, cents) reference prices in one transaction.
+    fn db_set_reference_prices(&mut self, prices: &[(usize, i64)]) -> Result<(), DbError>;
+
+    /// Sets `reference_price` and `discount_pct` on freshly stored listings and keeps
+    /// the reference on their rows, so feed history and search show the same discount.
+    fn db_score_deals(&mut self, items: &mut [MostRecent], scoring: &DealScoring, now: i64) -> Result<(), DbError> {
+        let mut references = Vec::new();
+
+        for item in items.iter_mut() {
+            let (Some(price), Ok(appid)) = (item.price, item.appid.parse::<u32>()) else {
+                continue;
+            };
+
+            let samples = self.db_feed_price_samples(
+                appid, &item.market_hash_name, price.currency, item.id, now - scoring.max_sample_age, scoring.window,
+            )?;
+            let reference = if samples.len() >= scoring.min_samples {
+                median_cents(&samples)
+            } else {
+                self.db_cached_median_price(appid, &item.market_hash_name, price.currency, now - scoring.max_overview_age)?
+            };
+
+            item.score_deal(reference.map(|cents| Money::new(cents, price.currency)));
+            if let Some(cents) = reference {
+                references.push((item.id, cents));
+            }
+        }
+
+        if !references.is_empty() {
+            self.db_set_reference_prices(&references)?;
+        }
+        Ok(())
+    }
+
+    /// Inserts new games and refreshes name, icon and link of known ones.
+    fn db_upsert_games(&self, games: &[NewGame]) -> Result<(), DbError>;
+
+    /// Every known game with its listing count in item_feed, busiest first.
+    fn db_get_games(&self) -> Result<Vec<Game>, DbError>;
+
+    fn db_upsert_currency_rates(&self, rates: &[ObservedRate]) -> Result<(), DbError>;
+
+    fn db_get_currency_rates(&self) -> Result<CurrencyRates, DbError>;
+
+    /// Highest id outside the retention window: first seen before `created_before`,
+    /// or older than the newest `keep_rows` listings. 0 when everything is kept.
+    fn db_feed_prune_bound(&self, created_before: Option<i64>, keep_rows: Option<i64>) -> Result<i64, DbError>;
+
+    /// Moves up to `limit` of the oldest listings with id <= `up_to_id` into
+    /// item_feed_archive, or deletes them when `archive` is false. Returns how many left item_feed.
+    fn db_feed_archive_batch(&mut self, up_to_id: i64, limit: usize, archive: bool) -> Result<usize, DbError>;
+
+    /// Applies `retention` as of `now` (unix seconds) in batches, each its own
+    /// transaction. Returns how many listings left item_feed.
+    fn db_prune_feed(&mut self, retention: &FeedRetention, now: i64) -> Result<usize, DbError> {
+        let bound = self.db_feed_prune_bound(retention.created_before(now), retention.max_rows)?;
+        if bound == 0 {
+            return Ok(0);
+        }
+
+        let batch_size = retention.batch_size.max(1);
+        let mut removed = 0;
+        loop {
+            let moved = self.db_feed_archive_batch(bound, batch_size, retention.archive)?;
+            removed += moved;
+            if moved < batch_size {
+                return Ok(removed);
+            }
+        }
+    }
+
+    /// One page of GET /api/feed with prices in `currency`. A narrow price range can
+    /// skip most rows, so scanning stops after a budget and hands back a cursor to continue from.
+    fn db_get_feed_page(
+        &self,
+        query: &FeedQuery,
+        matcher: &FeedMatcher,
+        currency: Option<u32>,
+        rates: &CurrencyRates,
+    ) -> Result<FeedPage, DbError> {
+        let limit = query.page_limit();
+        let mut scan = query.clone();
+        let mut items = Vec::with_capacity(limit);
+
+        for _ in 0..FEED_SCAN_ROUNDS {
+            let batch = self.db_scan_feed(&scan, matcher, FEED_SCAN_BATCH)?;
+            let exhausted = batch.len() < FEED_SCAN_BATCH;
+
+            for item in batch {
+                scan.advance(item.id as i64);
+
+                let item = item.in_currency(currency, rates);
+                if matcher.matches(&item) {
+                    items.push(item);
+                    if items.len() == limit {
+                        return Ok(FeedPage { items, next_cursor: scan.cursor() });
+                    }
+                }
+            }
+
+            if exhausted {
+                return Ok(FeedPage { items, next_cursor: None });
+            }
+        }
+
+        Ok(FeedPage { items, next_cursor: scan.cursor() })
+    }
+
+    /// Stores a poll and returns the id range `(start, end]` of the listings it added.
+    fn db_post_most_recent_items(&self, data: SteamMostRecentResponse) -> Result<(i64, i64), DbError>
+    where
+        Self: CatalogRepo + Sized,
+    {
+        self.db_in_transaction(|db| {
+            let start_id = db.db_feed_last_id()?;
+            println!("Start ID: {}", start_id);
+
+            //Listings reference their game and catalog entry, so those go first
+            db.db_upsert_games(&feed_games(&data))?;
+
+            let items = feed_items(&data);
+            let catalog: Vec<CatalogItem> = items.iter().map(|listing| listing.item.clone()).collect();
+            db.db_upsert_catalog_items(&catalog)?;
+
+            for item in items {
+                db.db_insert_feed_item(&item)?;
+            }
+
+            Ok((start_id, db.db_feed_last_id()?))
+        })
+    }
+}
+
+//Feed done
+//=======================
+
+//==================
+//Catalog
+
+/// item_catalog, one row per (appid, classid, instanceid). Listings and
+/// inventories each know part of an item, an upsert never forgets what the other added.
+pub trait CatalogRepo {
+    fn db_upsert_catalog_items(&self, items: &[CatalogItem]) -> Result<(), DbError>;
+
+    fn db_get_catalog_item(&self, appid: u32, classid: &str, instanceid: &str) -> Result<CatalogItem, DbError>;
+}
+
+//Catalog done
+//=======================
+
+//==================
+//Wallets
+
+/// Buyer payments and the seller credits they turn into.
+/// A credit goes LOCKED -> AVAILABLE (after STRIPE_WALLET_HOLD) -> TRANSFERRED.
+pub trait WalletRepo {
+    fn db_insert_buyer_transaction(
+        &self,
+        steamid: String,
+        offer_id: String,
+        amount: Money,
+        amount_with_fee: Money,
+        method: String,
+        pay_method: String,
+    ) -> Result<(), DbError>;
+
+    /// Constraint when `stripe_event_id` was booked already. Returns the new row id.
+    fn db_insert_stripe_wallet_transaction(
+        &self,
+        steamid: &str,
+        stripe_acct: &str,
+        offer_id: &str,
+        amount_cents: i64,
+        stripe_event_id: &str,
+    ) -> Result<i64, DbError>;
+
+    /// Unlocks credits that are past the hold at `now`, returns how many.
+    fn db_check_stripe_wallet_transaction_availability(&self, now: DateTime<Utc>) -> Result<usize, DbError>;
+
+    /// (row id, acct_..., offer_id, amount_cents) of credits ready to transfer.
+    fn db_get_stripe_wallet_available(&self, limit: i64) -> Result<Vec<(i64, String, String, i64)>, DbError>;
+
+    /// Only AVAILABLE rows move, so a retried transfer can't be recorded twice.
+    /// False when the row wasn't AVAILABLE.
+    fn db_mark_stripe_wallet_transferred(&self, row_id: i64, transfer_id: &str) -> Result<bool, DbError>;
+
+    fn db_get_connected_stripe_trader_acct_for_steamid(&self, steamid: &str) -> Result<Option<String>, DbError>;
+
+    fn db_upsert_user_stripe_id(&self, steamid: &str, acct: &str) -> Result<(), DbError>;
+}
+
+//Wallets done
+//=======================
+
+//==================
+//Trade offer drafts
+
+pub trait DraftRepo {
+    fn db_create_offer_draft(
+        &mut self,
+        offer_id: &str,
+        partner_trade_url: &str,
+        autosend: bool,
+        give_items: Vec<DraftItem>,
+    ) -> Result<String, DbError>;
+
+    fn db_get_offer_draft(&self, draft_id: &str) -> Result<OfferDraft, DbError>;
+}
+
+//Trade offer drafts done
+//=======================
+
+//==================
+//Saved searches and alerts
+
+//A broad search could match most of a poll, users get the first few
+const ALERTS_PER_SEARCH_BATCH: usize = 20;
+
+pub trait AlertRepo {
+    /// Constraint when the user already has a search by that name.
+    fn db_insert_saved_search(&self, steamid: &str, search: &SavedSearchInput, now: i64) -> Result<SavedSearch, DbError>;
+
+    fn db_get_saved_searches(&self, steamid: &str) -> Result<Vec<SavedSearch>, DbError>;
+
+    /// Every user's searches, what each stored feed batch is checked against.
+    fn db_get_all_saved_searches(&self) -> Result<Vec<SavedSearch>, DbError>;
+
+    /// NotFound("saved_search") unless `steamid` owns it. Its notifications go with it.
+    fn db_delete_saved_search(&self, steamid: &str, id: i64) -> Result<(), DbError>;
+
+    /// Stores the matches in one transaction. A listing already stored for the same
+    /// search is skipped, the returned notifications are only the new ones.
+    fn db_insert_notifications(&mut self, matches: &[(SavedSearch, MostRecent)], now: i64) -> Result<Vec<Notification>, DbError>;
+
+    /// Newest first.
+    fn db_get_notifications(&self, steamid: &str, unread_only: bool, limit: usize) -> Result<Vec<Notification>, DbError>;
+
+    /// Marks the user's notifications with id <= `up_to_id` read, returns how many changed.
+    fn db_mark_notifications_read(&self, steamid: &str, up_to_id: i64, now: i64) -> Result<usize, DbError>;
+
+    /// Checks freshly stored listings against every saved search and stores the matches,
+    /// priced in each search's currency.
+    fn db_notify_saved_searches(&mut self, items: &[MostRecent], rates: &CurrencyRates, now: i64) -> Result<Vec<Notification>, DbError> {
+        let mut matches = Vec::new();
+
+        for search in self.db_get_all_saved_searches()? {
+            let matcher = match search.feed_filter().compile() {
+                Ok(matcher) => matcher,
+                Err(e) => {
+                    eprintln!("DB: Saved search {} doesn't compile, skipped: {e}", search.id);
+                    continue;
+                }
+            };
+
+            let matched: Vec<MostRecent> = items
+                .iter()
+                .filter(|item| search.matches(&matcher, item, rates))
+                .take(ALERTS_PER_SEARCH_BATCH)
+                .map(|item| item.in_currency(search.currency, rates))
+                .collect();
+            matches.extend(matched.into_iter().map(|item| (search.clone(), item)));
+        }
+
+        if matches.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.db_insert_notifications(&matches, now)
+    }
+}
+
+//Saved searches and alerts done
+//=======================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{memory::MemoryDb, DataBase};
+    use steam_market_parser::{FeedFilter, PriceOverview};
+
+    //Every scenario runs against both stores so they can't drift apart
+    fn sqlite() -> DataBase {
+        DataBase::open_in_memory()
+    }
+
+    fn item(asset_id: &str, price: &str) -> OfferItems {
+        OfferItems {
+            item_asset_id: asset_id.to_string(),
+            item_contextid: "2".to_string(),
+            item_appid: "730".to_string(),
+            item_name: format!("Item {asset_id}"),
+            item_price: Money::parse(price, OFFER_CURRENCY).unwrap(),
+            item_link: String::new(),
+            item_image: String::new(),
+        }
+    }
+
+    fn user(steamid: &str) -> SteamUser {
+        SteamUser {
+            steamid: steamid.to_string(),
+            nickname: format!("user {steamid}"),
+            avatar_url_small: String::new(),
+            avatar_url_full: String::new(),
+            status: "online".to_string(),
+        }
+    }
+
+    fn asset_ids(items: &[OfferItems]) -> Vec<&str> {
+        items.iter().map(|item| item.item_asset_id.as_str()).collect()
+    }
+
+    fn game(appid: u32, name: &str) -> NewGame {
+        NewGame {
+            appid,
+            name: name.to_string(),
+            icon: format!("{appid}.jpg"),
+            link: format!("https://steamcommunity.com/app/{appid}"),
+        }
+    }
+
+    fn catalog_item(appid: u32, classid: &str) -> CatalogItem {
+        CatalogItem {
+            appid,
+            classid: classid.to_string(),
+            instanceid: "0".to_string(),
+            name: format!("Item {classid}"),
+            market_hash_name: format!("Item {classid}"),
+            tradable: true,
+            marketable: true,
+            ..Default::default()
+        }
+    }
+
+    fn listing(listinginfo_id: &str, appid: u32) -> NewFeedItem {
+        NewFeedItem {
+            listinginfo_id: listinginfo_id.to_string(),
+            price: Some(Money::new(150, 1)),
+            item: catalog_item(appid, listinginfo_id),
+        }
+    }
+
+    //Listings reference their catalog entry, same order as db_post_most_recent_items
+    fn insert_listing(repo: &(impl FeedRepo + CatalogRepo), listinginfo_id: &str, appid: u32) -> bool {
+        let listing = listing(listinginfo_id, appid);
+        repo.db_upsert_catalog_items(std::slice::from_ref(&listing.item)).unwrap();
+        repo.db_insert_feed_item(&listing).unwrap()
+    }
+
+    fn feed_ids(repo: &impl FeedRepo) -> Vec<usize> {
+        repo.db_get_most_recent_items(0, i64::MAX).unwrap().iter().map(|item| item.id).collect()
+    }
+
+    fn offer_rounds(repo: &(impl OfferRepo + UserRepo)) {
+        let offer_id = repo.db_offer_make_offer("buyer".into(), "trader".into()).unwrap();
+        assert_eq!(repo.db_offer_latest_round(&offer_id).unwrap(), 0);
+        assert!(repo.db_offer_round_items(&offer_id, 0).unwrap().is_empty());
+
+        let first = repo
+            .db_offer_update_offer(offer_id.clone(), vec![item("a", "1.00"), item("b", "2.00")])
+            .unwrap();
+        assert_eq!(asset_ids(&first.added_items), ["a", "b"]);
+        assert!(first.removed_items.is_empty() && first.updated_items.is_empty());
+        assert_eq!(first.total_price, Money::new(300, OFFER_CURRENCY));
+        assert_eq!(first.total_count, 2);
+
+        let second = repo
+            .db_offer_update_offer(offer_id.clone(), vec![item("a", "1.50"), item("c", "0.50")])
+            .unwrap();
+        assert_eq!(asset_ids(&second.new_items), ["a", "c"]);
+        assert_eq!(asset_ids(&second.added_items), ["c"]);
+        assert_eq!(asset_ids(&second.updated_items), ["a"]);
+        assert_eq!(asset_ids(&second.removed_items), ["b"]);
+        assert_eq!(repo.db_offer_latest_round(&offer_id).unwrap(), 2);
+        assert_eq!(repo.db_offer_get_offer_price(offer_id.clone()).unwrap(), Money::new(200, OFFER_CURRENCY));
+
+        //Same items again is a new round with nothing changed
+        let third = repo
+            .db_offer_update_offer(offer_id.clone(), vec![item("a", "1.50"), item("c", "0.50")])
+            .unwrap();
+        assert!(third.added_items.is_empty() && third.updated_items.is_empty() && third.removed_items.is_empty());
+
+        let mut mixed = item("d", "1.00");
+        mixed.item_price.currency = 3;
+        assert!(matches!(
+            repo.db_offer_update_offer(offer_id.clone(), vec![item("a", "1.00"), mixed]),
+            Err(DbError::Constraint(_))
+        ));
+
+        assert!(matches!(
+            repo.db_offer_update_offer("missing".into(), vec![item("a", "1.00")]),
+            Err(DbError::NotFound("offer"))
+        ));
+    }
+
+    fn check_offer_to_pay(repo: &(impl OfferRepo + UserRepo)) {
+        repo.db_add_steam_user(&user("trader")).unwrap();
+
+        let offer_id = repo.db_offer_make_offer("buyer".into(), "trader".into()).unwrap();
+        repo.db_offer_update_offer(offer_id.clone(), vec![item("a", "1.00")]).unwrap();
+        repo.db_offer_update_offer(offer_id.clone(), vec![item("a", "1.25"), item("b", "3.00")]).unwrap();
+
+        let check = |items: Vec<OfferItems>| {
+            repo.db_offer_check_offer_to_pay(OfferContentToCheck {
+                offer_id: offer_id.clone(),
+                special_for_save_offer: items,
+                partner_steam_id: "trader".into(),
+            })
+        };
+
+        //Paying for a stale round is refused before the trade url matters
+        let stale = check(vec![item("a", "1.00")]).unwrap();
+        assert!(!stale.check_result);
+        assert!(stale.offer_items.is_empty());
+
+        assert!(matches!(
+            check(vec![item("a", "1.25"), item("b", "3.00")]),
+            Err(DbError::NotFound("trade_url"))
+        ));
+
+        let trade_url = "https://steamcommunity.com/tradeoffer/new/?partner=1&token=x".to_string();
+        repo.db_account_post_trade_url("trader", &trade_url).unwrap();
+
+        let current = check(vec![item("a", "1.25"), item("b", "3.00")]).unwrap();
+        assert!(current.check_result);
+        assert_eq!(asset_ids(&current.offer_items), ["a", "b"]);
+        assert_eq!(current.partner_trade_url, trade_url);
+    }
+
+    fn stripe_wallet_lifecycle(repo: &(impl WalletRepo + UserRepo)) {
+        repo.db_add_steam_user(&user("trader")).unwrap();
+        repo.db_upsert_user_stripe_id("trader", "acct_1").unwrap();
+        let acct = repo.db_get_connected_stripe_trader_acct_for_steamid("trader").unwrap().unwrap();
+
+        let row_id = repo.db_insert_stripe_wallet_transaction("trader", &acct, "offer-1", 1250, "evt_1").unwrap();
+
+        //Stripe retries webhooks, the second delivery must not book a second credit
+        assert!(matches!(
+            repo.db_insert_stripe_wallet_transaction("trader", &acct, "offer-1", 1250, "evt_1"),
+            Err(DbError::Constraint(_))
+        ));
+
+        //LOCKED: not handed to the transfer loop and can't be marked yet
+        assert_eq!(repo.db_check_stripe_wallet_transaction_availability(Utc::now()).unwrap(), 0);
+        assert!(repo.db_get_stripe_wallet_available(50).unwrap().is_empty());
+        assert!(!repo.db_mark_stripe_wallet_transferred(row_id, "tr_early").unwrap());
+
+        let after_hold = Utc::now() + STRIPE_WALLET_HOLD + chrono::Duration::seconds(5);
+        assert_eq!(repo.db_check_stripe_wallet_transaction_availability(after_hold).unwrap(), 1);
+        assert_eq!(
+            repo.db_get_stripe_wallet_available(50).unwrap(),
+            vec![(row_id, "acct_1".to_string(), "offer-1".to_string(), 1250)]
+        );
+
+        assert!(repo.db_mark_stripe_wallet_transferred(row_id, "tr_1").unwrap());
+        assert!(repo.db_get_stripe_wallet_available(50).unwrap().is_empty());
+
+        //TRANSFERRED rows stay put on the next pass
+        assert_eq!(repo.db_check_stripe_wallet_transaction_availability(after_hold).unwrap(), 0);
+        assert!(!repo.db_mark_stripe_wallet_transferred(row_id, "tr_2").unwrap());
+    }
+
+    fn feed_retention(repo: &mut (impl FeedRepo + CatalogRepo)) {
+        repo.db_upsert_games(&[game(730, "Counter-Strike 2")]).unwrap();
+        for id in 1..=5 {
+            assert!(insert_listing(repo, &id.to_string(), 730));
+        }
+        let now = Utc::now().timestamp();
+
+        let by_rows = FeedRetention { max_age_days: None, max_rows: Some(3), batch_size: 1, ..Default::default() };
+        assert_eq!(repo.db_prune_feed(&by_rows, now).unwrap(), 2);
+        assert_eq!(feed_ids(repo), [3, 4, 5]);
+        assert_eq!(repo.db_prune_feed(&by_rows, now).unwrap(), 0);
+
+        let by_age = FeedRetention { max_age_days: Some(7), max_rows: None, batch_size: 2, ..Default::default() };
+        assert_eq!(repo.db_prune_feed(&by_age, now).unwrap(), 0);
+        assert_eq!(repo.db_prune_feed(&by_age, now + 8 * 86400).unwrap(), 3);
+        assert!(feed_ids(repo).is_empty());
+
+        //Ids keep counting up, clients page by them
+        assert!(insert_listing(repo, "6", 730));
+        assert_eq!(repo.db_feed_last_id().unwrap(), 6);
+    }
+
+    fn games_catalog(repo: &(impl FeedRepo + CatalogRepo)) {
+        repo.db_upsert_games(&[game(730, "Counter-Strike 2"), game(440, "Team Fortress 2")]).unwrap();
+        insert_listing(repo, "1", 440);
+        insert_listing(repo, "2", 440);
+        insert_listing(repo, "3", 730);
+
+        //A later poll renames a game, listings pick the new name up without a rewrite
+        repo.db_upsert_games(&[game(730, "CS2")]).unwrap();
+
+        let games: Vec<(u32, String, i64)> = repo
+            .db_get_games()
+            .unwrap()
+            .into_iter()
+            .map(|game| (game.appid, game.name, game.listing_count))
+            .collect();
+        assert_eq!(games, [(440, "Team Fortress 2".to_string(), 2), (730, "CS2".to_string(), 1)]);
+
+        let items = repo.db_get_most_recent_items(0, 3).unwrap();
+        let joined: Vec<(&str, &str, &str)> = items
+            .iter()
+            .map(|item| (item.appid.as_str(), item.game.as_str(), item.game_icon.as_str()))
+            .collect();
+        assert_eq!(joined, [("440", "Team Fortress 2", "440.jpg"), ("440", "Team Fortress 2", "440.jpg"), ("730", "CS2", "730.jpg")]);
+    }
+
+    fn item_catalog(repo: &(impl FeedRepo + CatalogRepo)) {
+        //Listings know the inspect link and wear, inventories add the rarity tag
+        let from_listing = CatalogItem {
+            exterior: Some("Field-Tested".into()),
+            inspect_link: Some("steam://rungame/730/%assetid%".into()),
+            icon_url: "listing.png".into(),
+            descriptions: vec!["Exterior: Field-Tested".into()],
+            ..catalog_item(730, "310776")
+        };
+        let from_inventory = CatalogItem {
+            rarity: Some("Classified".into()),
+            exterior: Some("Field-Tested".into()),
+            tradable: false,
+            ..catalog_item(730, "310776")
+        };
+        repo.db_upsert_catalog_items(&[from_listing]).unwrap();
+        repo.db_upsert_catalog_items(&[from_inventory]).unwrap();
+
+        let stored = repo.db_get_catalog_item(730, "310776", "0").unwrap();
+        assert_eq!(stored.rarity.as_deref(), Some("Classified"));
+        assert_eq!(stored.inspect_link.as_deref(), Some("steam://rungame/730/%assetid%"));
+        assert_eq!(stored.icon_url, "listing.png");
+        assert_eq!(stored.descriptions, ["Exterior: Field-Tested"]);
+        assert!(!stored.tradable);
+
+        assert!(matches!(repo.db_get_catalog_item(730, "310776", "1"), Err(DbError::NotFound("item"))));
+
+        //Feed rows show what the catalog knows
+        repo.db_upsert_games(&[game(730, "Counter-Strike 2")]).unwrap();
+        repo.db_insert_feed_item(&listing("310776", 730)).unwrap();
+        let shown = repo.db_get_most_recent_items(0, i64::MAX).unwrap();
+        assert_eq!(shown[0].rarity.as_deref(), Some("Classified"));
+        assert_eq!((shown[0].name.as_str(), shown[0].tradable.as_str()), ("Item 310776", "0"));
+    }
+
+    /// A page the way GET /api/feed serves it, filters compiled from the query.
+    fn feed_page(repo: &impl FeedRepo, query: &FeedQuery, currency: Option<u32>, rates: &CurrencyRates) -> FeedPage {
+        let matcher = query.filter().and_then(FeedFilter::compile).unwrap();
+        repo.db_get_feed_page(query, &matcher, currency, rates).unwrap()
+    }
+
+    fn feed_history(repo: &(impl FeedRepo + CatalogRepo)) {
+        repo.db_upsert_games(&[game(730, "Counter-Strike 2"), game(440, "Team Fortress 2")]).unwrap();
+        for id in 1..=30 {
+            let mut listing = listing(&format!("{id}"), if id % 3 == 0 { 440 } else { 730 });
+            listing.price = Some(Money::new(id * 100, 3));
+            listing.item.tradable = id % 2 == 0;
+            repo.db_upsert_catalog_items(std::slice::from_ref(&listing.item)).unwrap();
+            repo.db_insert_feed_item(&listing).unwrap();
+        }
+        let rates = CurrencyRates::default();
+        let ids = |page: &FeedPage| page.items.iter().map(|item| item.id).collect::<Vec<_>>();
+
+        //Newest first, then on from the cursor
+        let first = feed_page(repo, &FeedQuery { limit: Some(3), ..Default::default() }, None, &rates);
+        assert_eq!((ids(&first), first.next_cursor), (vec![30, 29, 28], Some(28)));
+        let second = feed_page(repo, &FeedQuery { limit: Some(3), before_id: first.next_cursor, ..Default::default() }, None, &rates);
+        assert_eq!(ids(&second), [27, 26, 25]);
+
+        //A live subscriber that fell behind catches up on the newest it missed
+        let since = |seen_id, limit| repo.db_feed_since(seen_id, limit).unwrap().iter().map(|item| item.id).collect::<Vec<_>>();
+        assert_eq!(since(27, 10), [28, 29, 30]);
+        assert_eq!(since(0, 3), [28, 29, 30]);
+        assert!(since(30, 10).is_empty());
+
+        //Replay after a reconnect runs oldest first and ends with no cursor
+        let replay = feed_page(repo, &FeedQuery { after_id: Some(27), ..Default::default() }, None, &rates);
+        assert_eq!((ids(&replay), replay.next_cursor), (vec![28, 29, 30], None));
+
+        //Game by appid or name, price in cents, name query, tradable
+        let filtered = FeedQuery {
+            game: Some("team fortress".into()),
+            price_min: Some(600),
+            price_max: Some(2400),
+            tradable: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(ids(&feed_page(repo, &filtered, None, &rates)), [24, 18, 12, 6]);
+
+        let by_appid = FeedQuery { game: Some("440".into()), query: Some("ITEM 1".into()), ..Default::default() };
+        assert_eq!(ids(&feed_page(repo, &by_appid, None, &rates)), [18, 15, 12]);
+
+        //Several appids, a pattern and exclusion keywords
+        let typed = FeedQuery {
+            appids: Some("440, 570".into()),
+            query: Some(r"^item [12]\d$".into()),
+            regex: Some(true),
+            exclude: Some("21,27".into()),
+            ..Default::default()
+        };
+        assert_eq!(ids(&feed_page(repo, &typed, None, &rates)), [24, 18, 15, 12]);
+
+        //Price bounds apply after conversion into the requested currency
+        let mut rates = CurrencyRates::default();
+        rates.insert(3, 1, 2.0);
+        let in_usd = FeedQuery { price_min: Some(5800), ..Default::default() };
+        let page = feed_page(repo, &in_usd, Some(1), &rates);
+        assert_eq!(ids(&page), [30, 29]);
+        assert_eq!(page.items[0].price, Some(Money::new(6000, 1)));
+    }
+
+    fn feed_search<R: FeedRepo + CatalogRepo>(repo: &mut R) {
+        repo.db_upsert_games(&[game(730, "Counter-Strike 2"), game(440, "Team Fortress 2")]).unwrap();
+        let names = [
+            (730, "AK-47 | Redline (Field-Tested)"),
+            (730, "AWP | Asiimov (Battle-Scarred)"),
+            (440, "Mann Co. Supply Crate Key"),
+            (730, "AK-47 | Redline (Minimal Wear)"),
+            (730, "Sticker | <Crown> (Foil)"),
+        ];
+        for (id, (appid, name)) in (1..).zip(names) {
+            let mut listing = listing(&format!("{id}"), appid);
+            listing.item.name = name.to_string();
+            listing.item.market_hash_name = name.to_string();
+            repo.db_upsert_catalog_items(std::slice::from_ref(&listing.item)).unwrap();
+            repo.db_insert_feed_item(&listing).unwrap();
+        }
+        let search = |repo: &R, q: &str, game: Option<&str>| {
+            let query = SearchQuery { q: q.to_string(), game: game.map(str::to_string), ..Default::default() };
+            repo.db_search_feed(&query).unwrap()
+        };
+        let ids = |hits: &[SearchHit]| {
+            let mut ids = hits.iter().map(|hit| hit.item.id).collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+
+        //Prefixes of every term, equal matches newest first
+        let hits = search(repo, "red ak", None);
+        assert_eq!(hits.iter().map(|hit| hit.item.id).collect::<Vec<_>>(), [4, 1]);
+        assert_eq!(hits[0].highlight, "<mark>AK</mark>-47 | <mark>Redline</mark> (Minimal Wear)");
+        assert_eq!(search(repo, "crown", None)[0].highlight, "Sticker | &lt;<mark>Crown</mark>&gt; (Foil)");
+        assert!(search(repo, "redlines", None).is_empty());
+        assert!(search(repo, " | ", None).is_empty());
+
+        //The game name is indexed too, and `game` narrows like on /api/feed
+        assert_eq!(ids(&search(repo, "counter", None)), [1, 2, 4, 5]);
+        assert_eq!(ids(&search(repo, "key", Some("440"))), [3]);
+        assert!(search(repo, "key", Some("counter")).is_empty());
+
+        //Renames reach the index
+        let mut renamed = catalog_item(730, "2");
+        renamed.name = "AWP | Dragon Lore".to_string();
+        renamed.market_hash_name = "AWP | Dragon Lore (Factory New)".to_string();
+        repo.db_upsert_catalog_items(&[renamed]).unwrap();
+        assert_eq!(ids(&search(repo, "dragon", None)), [2]);
+        assert!(search(repo, "asiimov", None).is_empty());
+
+        repo.db_upsert_games(&[game(440, "Team Fortress Classic")]).unwrap();
+        assert_eq!(ids(&search(repo, "classic", None)), [3]);
+
+        //Pruned listings leave the index
+        repo.db_feed_archive_batch(1, 10, true).unwrap();
+        assert_eq!(ids(&search(repo, "redline", None)), [4]);
+    }
+
+    fn deal_scoring<R: FeedRepo + CatalogRepo>(repo: &mut R, set_cached_median: impl Fn(&R, &str, i64, i64)) {
+        repo.db_upsert_games(&[game(730, "Counter-Strike 2")]).unwrap();
+        let prices = [("1", "ak", Some(1000)), ("2", "ak", Some(1100)), ("3", "ak", Some(900)), ("4", "ak", Some(1000)),
+            ("5", "ak", Some(1200)), ("6", "ak", Some(700)), ("7", "key", Some(250)), ("8", "case", None)];
+        for (id, classid, cents) in prices {
+            let mut listing = listing(id, 730);
+            listing.item = catalog_item(730, classid);
+            listing.price = cents.map(|cents| Money::new(cents, 3));
+            repo.db_upsert_catalog_items(std::slice::from_ref(&listing.item)).unwrap();
+            repo.db_insert_feed_item(&listing).unwrap();
+        }
+        let now = Utc::now().timestamp();
+        let scoring = DealScoring::default();
+
+        //Five earlier AK listings give a median of 10.00, the key has only Steam's cached median
+        set_cached_median(repo, "Item key", 500, now);
+        set_cached_median(repo, "Item case", 900, now);
+        let mut items = repo.db_get_most_recent_items(5, 8).unwrap();
+        repo.db_score_deals(&mut items, &scoring, now).unwrap();
+        let scored = items.iter().map(|item| (item.id, item.reference_price.map(|p| p.cents), item.discount_pct)).collect::<Vec<_>>();
+        assert_eq!(scored, [(6, Some(1000), Some(30.0)), (7, Some(500), Some(50.0)), (8, None, None)]);
+
+        //The reference stays on the row, history and filters see the same discount
+        let stored = repo.db_get_most_recent_items(5, 8).unwrap();
+        assert_eq!(stored.iter().map(|item| item.discount_pct).collect::<Vec<_>>(), [Some(30.0), Some(50.0), None]);
+        let deals = FeedQuery { min_discount: Some(40.0), ..Default::default() };
+        let page = feed_page(repo, &deals, None, &CurrencyRates::default());
+        assert_eq!(page.items.iter().map(|item| item.id).collect::<Vec<_>>(), [7]);
+
+        //Too few samples and a stale cache leave it unscored
+        let mut items = repo.db_get_most_recent_items(6, 7).unwrap();
+        repo.db_score_deals(&mut items, &scoring, now + scoring.max_overview_age + 1).unwrap();
+        assert_eq!(items[0].discount_pct, None);
+    }
+
+    fn saved_search_alerts<R: AlertRepo + UserRepo + FeedRepo + CatalogRepo>(repo: &mut R) {
+        repo.db_add_steam_user(&user("alice")).unwrap();
+        repo.db_add_steam_user(&user("bob")).unwrap();
+        repo.db_upsert_games(&[game(730, "Counter-Strike 2"), game(440, "Team Fortress 2")]).unwrap();
+
+        let redlines = SavedSearchInput {
+            name: "redlines".into(),
+            query: "redline".into(),
+            price_max: Some(1000),
+            currency: Some(1),
+            ..Default::default()
+        };
+        let redlines = repo.db_insert_saved_search("alice", &redlines, 10).unwrap();
+        let keys = SavedSearchInput { name: "keys".into(), appid: "440".into(), ..Default::default() };
+        let keys = repo.db_insert_saved_search("bob", &keys, 10).unwrap();
+        assert_eq!(repo.db_get_saved_searches("alice").unwrap(), std::slice::from_ref(&redlines));
+
+        //Names are per user, searches need a user
+        let again = SavedSearchInput { name: "redlines".into(), query: "ak".into(), ..Default::default() };
+        assert!(matches!(repo.db_insert_saved_search("alice", &again, 10), Err(DbError::Constraint(_))));
+        assert!(matches!(repo.db_insert_saved_search("carol", &again, 10), Err(DbError::Constraint(_))));
+
+        for (id, appid, name, cents) in [("1", 730, "AK-47 | Redline", 400), ("2", 440, "Key", 250), ("3", 730, "AK-47 | Redline", 900)] {
+            let mut listing = listing(id, appid);
+            listing.item.name = name.to_string();
+            listing.price = Some(Money::new(cents, 3));
+            repo.db_upsert_catalog_items(std::slice::from_ref(&listing.item)).unwrap();
+            repo.db_insert_feed_item(&listing).unwrap();
+        }
+        let items = repo.db_get_most_recent_items(0, repo.db_feed_last_id().unwrap()).unwrap();
+        let mut rates = CurrencyRates::default();
+        rates.insert(3, 1, 2.0);
+
+        //Listing 3 is 18.00 in the search's currency, over the bound
+        let notifications = repo.db_notify_saved_searches(&items, &rates, 20).unwrap();
+        let matched = notifications.iter().map(|n| (n.steamid.as_str(), n.item.id)).collect::<Vec<_>>();
+        assert_eq!(matched, [("alice", 1), ("bob", 2)]);
+        assert_eq!(notifications[0].item.price, Some(Money::new(800, 1)));
+        assert!(repo.db_notify_saved_searches(&items, &rates, 30).unwrap().is_empty());
+
+        let unread = repo.db_get_notifications("alice", true, 10).unwrap();
+        assert_eq!(unread.len(), 1);
+        assert_eq!((unread[0].search_name.as_str(), unread[0].item.price), ("redlines", Some(Money::new(800, 1))));
+
+        assert_eq!(repo.db_mark_notifications_read("alice", unread[0].id, 40).unwrap(), 1);
+        assert!(repo.db_get_notifications("alice", true, 10).unwrap().is_empty());
+        assert!(repo.db_get_notifications("alice", false, 10).unwrap()[0].read);
+        assert_eq!(repo.db_get_notifications("bob", true, 10).unwrap().len(), 1);
+
+        //Only the owner deletes, notifications go with the search
+        assert!(matches!(repo.db_delete_saved_search("alice", keys.id), Err(DbError::NotFound("saved_search"))));
+        repo.db_delete_saved_search("bob", keys.id).unwrap();
+        assert!(repo.db_get_notifications("bob", false, 10).unwrap().is_empty());
+        assert_eq!(repo.db_get_all_saved_searches().unwrap(), [redlines]);
+    }
+
+    fn drafts(repo: &mut impl DraftRepo) {
+        let give = vec![DraftItem { appid: 730, contextid: "2".into(), assetid: "a".into(), amount: 1 }];
+        let draft_id = repo.db_create_offer_draft("offer-1", "https://trade", false, give.clone()).unwrap();
+
+        let draft = repo.db_get_offer_draft(&draft_id).unwrap();
+        assert_eq!(draft.give, give);
+        assert!(!draft.autosend);
+
+        assert!(matches!(repo.db_get_offer_draft("missing"), Err(DbError::NotFound("draft"))));
+    }
+
+    #[test]
+    fn offer_rounds_memory() {
+        let repo = MemoryDb::default();
+        offer_rounds(&repo);
+    }
+
+    #[test]
+    fn offer_rounds_sqlite() {
+        offer_rounds(&sqlite());
+    }
+
+    #[test]
+    fn check_offer_to_pay_memory() {
+        check_offer_to_pay(&MemoryDb::default());
+    }
+
+    #[test]
+    fn check_offer_to_pay_sqlite() {
+        check_offer_to_pay(&sqlite());
+    }
+
+    #[test]
+    fn stripe_wallet_lifecycle_memory() {
+        stripe_wallet_lifecycle(&MemoryDb::default());
+    }
+
+    #[test]
+    fn stripe_wallet_lifecycle_sqlite() {
+        stripe_wallet_lifecycle(&sqlite());
+    }
+
+    #[test]
+    fn deal_scoring_memory() {
+        deal_scoring(&mut MemoryDb::default(), |repo, name, median, fetched_at| {
+            repo.set_cached_median(730, name, 3, median, fetched_at);
+        });
+    }
+
+    #[test]
+    fn deal_scoring_sqlite() {
+        deal_scoring(&mut sqlite(), |repo, name, median, fetched_at| {
+            repo.db_upsert_price_overview(&PriceOverview {
+                appid: 730,
+                market_hash_name: name.to_string(),
+                currency: 3,
+                lowest_price: None,
+                median_price: Some(median),
+                volume: None,
+                lowest_price_text: None,
+                median_price_text: None,
+                fetched_at,
+            })
+            .unwrap();
+        });
+    }
+
+    #[test]
+    fn saved_search_alerts_memory() {
+        saved_search_alerts(&mut MemoryDb::default());
+    }
+
+    #[test]
+    fn saved_search_alerts_sqlite() {
+        saved_search_alerts(&mut sqlite());
+    }
+
+    #[test]
+    fn drafts_memory() {
+        drafts(&mut MemoryDb::default());
+    }
+
+    #[test]
+    fn drafts_sqlite() {
+        drafts(&mut sqlite());
+    }
+
+    #[test]
+    fn feed_retention_memory() {
+        let mut repo = MemoryDb::default();
+        feed_retention(&mut repo);
+        assert_eq!(repo.archived_feed_ids(), [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn feed_retention_sqlite() {
+        let mut repo = sqlite();
+        feed_retention(&mut repo);
+
+        let archived: Vec<i64> = repo.connection
+            .prepare("SELECT id FROM item_feed_archive ORDER BY id").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(archived, [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn games_catalog_memory() {
+        games_catalog(&MemoryDb::default());
+    }
+
+    #[test]
+    fn games_catalog_sqlite() {
+        let repo = sqlite();
+        games_catalog(&repo);
+
+        //item_feed only keeps the appid, the name lives in games
+        let unknown_game = listing("4", 570);
+        repo.db_upsert_catalog_items(std::slice::from_ref(&unknown_game.item)).unwrap();
+        assert!(matches!(repo.db_insert_feed_item(&unknown_game), Err(DbError::Constraint(_))));
+    }
+
+    #[test]
+    fn item_catalog_memory() {
+        item_catalog(&MemoryDb::default());
+    }
+
+    #[test]
+    fn item_catalog_sqlite() {
+        item_catalog(&sqlite());
+    }
+
+    #[test]
+    fn feed_history_memory() {
+        feed_history(&MemoryDb::default());
+    }
+
+    #[test]
+    fn feed_history_sqlite() {
+        feed_history(&sqlite());
+    }
+
+    #[test]
+    fn feed_search_memory() {
+        feed_search(&mut MemoryDb::default());
+    }
+
+    #[test]
+    fn feed_search_sqlite() {
+        feed_search(&mut sqlite());
+    }
+
+    #[test]
+    fn feed_prune_without_archive() {
+        let mut repo = MemoryDb::default();
+        for id in 1..=3 {
+            insert_listing(&repo, &id.to_string(), 730);
+        }
+
+        let drop_all = FeedRetention { max_age_days: None, max_rows: Some(1), archive: false, ..Default::default() };
+        assert_eq!(repo.db_prune_feed(&drop_all, 0).unwrap(), 2);
+        assert_eq!(feed_ids(&repo), [3]);
+        assert!(repo.archived_feed_ids().is_empty());
+    }
+
+    #[test]
+    fn feed_poll_is_all_or_nothing() {
+        let repo = sqlite();
+        repo.connection
+            .execute_batch(
+                "CREATE TRIGGER fail_insert BEFORE INSERT ON item_feed WHEN NEW.listinginfo_id = '2'
+                 BEGIN SELECT RAISE(ABORT, 'insert failed'); END;",
+            )
+            .unwrap();
+
+        let listings: serde_json::Map<String, serde_json::Value> = ["1", "2"]
+            .iter()
+            .map(|id| {
+                (id.to_string(), serde_json::json!({
+                    "listingid": id,
+                    "price": 100.0,
+                    "fee": 0,
+                    "publisher_fee_app": 730,
+                    "publisher_fee_percent": "0.10",
+                    "currencyid": 2003,
+                    "asset": { "currency": 0, "appid": 730, "contextid": "2", "id": id, "amount": "1" }
+                }))
+            })
+            .collect();
+        let assets: serde_json::Map<String, serde_json::Value> = ["1", "2"]
+            .iter()
+            .map(|id| {
+                (id.to_string(), serde_json::json!({
+                    "currency": 0, "appid": 730, "contextid": "2", "id": id,
+                    "classid": id, "instanceid": "0", "amount": "1", "status": 2, "original_amount": "1",
+                    "market_hash_name": format!("Item {id}")
+                }))
+            })
+            .collect();
+        let data: SteamMostRecentResponse = serde_json::from_value(serde_json::json!({
+            "success": true,
+            "more": false,
+            "results_html": false,
+            "listinginfo": listings,
+            "purchaseinfo": [],
+            "assets": { "730": { "2": assets } },
+            "currency": [],
+            "app_data": { "730": { "appid": 730, "name": "Counter-Strike 2", "icon": "730.jpg", "link": "" } },
+            "last_time": 10,
+            "last_listing": "2",
+        }))
+        .unwrap();
+
+        assert!(repo.db_post_most_recent_items(data).is_err());
+        assert!(repo.db_get_games().unwrap().is_empty());
+        assert!(matches!(repo.db_get_catalog_item(730, "1", "0"), Err(DbError::NotFound(_))));
+        assert!(feed_ids(&repo).is_empty());
+        assert_eq!(repo.db_feed_last_id().unwrap(), 0);
+    }
+
+    #[test]
+    fn offer_status_flags() {
+        let repo = MemoryDb::default();
+        let offer_id = repo.db_offer_make_offer("buyer".into(), "trader".into()).unwrap();
+
+        repo.db_offer_update_status_offer(CurrentStatusOffer { offer_id: offer_id.clone(), status: "ACCEPTED".into() })
+            .unwrap();
+        assert_eq!(repo.offer_status(&offer_id).as_deref(), Some("ACCEPTED"));
+
+        assert!(matches!(
+            sqlite().db_offer_update_status_offer(CurrentStatusOffer { offer_id, status: "SUCCESS".into() }),
+            Err(DbError::NotFound("offer"))
+        ));
+    }
+}
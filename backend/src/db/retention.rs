@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use super::pool::env_parse;
+
+/// How much of item_feed to keep. Listings past either limit move to
+/// item_feed_archive, or are dropped outright when `archive` is off.
+#[derive(Clone, Debug)]
+pub struct FeedRetention {
+    /// Keep listings first seen within this many days. None = no age limit.
+    pub max_age_days: Option<i64>,
+    /// Keep at most this many of the newest listings. None = no row limit.
+    pub max_rows: Option<i64>,
+    pub archive: bool,
+    /// Rows moved per transaction, so the feed writer never waits long on the lock.
+    pub batch_size: usize,
+    pub interval: Duration,
+}
+
+impl Default for FeedRetention {
+    fn default() -> Self {
+        FeedRetention {
+            max_age_days: Some(7),
+            max_rows: Some(500_000),
+            archive: true,
+            batch_size: 5_000,
+            interval: Duration::from_secs(3600),
+        }
+    }
+}
+
+impl FeedRetention {
+    /// Default policy with overrides from FEED_RETENTION_DAYS, FEED_RETENTION_ROWS
+    /// (0 turns either limit off), FEED_ARCHIVE (0 deletes instead of archiving),
+    /// FEED_RETENTION_BATCH and FEED_RETENTION_INTERVAL_SECS.
+    pub fn from_env() -> Self {
+        let mut retention = FeedRetention::default();
+
+        if let Some(days) = env_parse::<i64>("FEED_RETENTION_DAYS") {
+            retention.max_age_days = (days > 0).then_some(days);
+        }
+        if let Some(rows) = env_parse::<i64>("FEED_RETENTION_ROWS") {
+            retention.max_rows = (rows > 0).then_some(rows);
+        }
+        if let Some(archive) = env_parse::<u8>("FEED_ARCHIVE") {
+            retention.archive = archive != 0;
+        }
+        if let Some(v) = env_parse::<usize>("FEED_RETENTION_BATCH") {
+            retention.batch_size = v.max(1);
+        }
+        if let Some(v) = env_parse::<u64>("FEED_RETENTION_INTERVAL_SECS") {
+            retention.interval = Duration::from_secs(v.max(1));
+        }
+
+        retention
+    }
+
+    /// Listings first seen before this unix time are out of the age window.
+    pub fn created_before(&self, now: i64) -> Option<i64> {
+        self.max_age_days.map(|days| now - days * 86400)
+    }
+}
@@ -0,0 +1,51 @@
+use crate::{Money, MostRecent};
+
+/// Median of `cents`, the lower middle for an even count. None when empty.
+pub fn median_cents(cents: &[i64]) -> Option<i64> {
+    if cents.is_empty() {
+        return None;
+    }
+
+    let mut sorted = cents.to_vec();
+    sorted.sort_unstable();
+    Some(sorted[(sorted.len() - 1) / 2])
+}
+
+/// How far `price` is under `reference` in percent, one decimal. Negative when above it.
+/// None when the currencies differ or the reference is no price at all.
+pub fn discount_pct(price: Money, reference: Money) -> Option<f64> {
+    if price.currency != reference.currency || reference.cents <= 0 {
+        return None;
+    }
+
+    let pct = (reference.cents - price.cents) as f64 * 100.0 / reference.cents as f64;
+    Some((pct * 10.0).round() / 10.0)
+}
+
+impl MostRecent {
+    /// Sets the reference price and the discount against it.
+    pub fn score_deal(&mut self, reference: Option<Money>) {
+        self.reference_price = reference;
+        self.discount_pct = match (self.price, reference) {
+            (Some(price), Some(reference)) => discount_pct(price, reference),
+            _ => None,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn median_and_discount() {
+        assert_eq!(median_cents(&[500, 100, 300]), Some(300));
+        assert_eq!(median_cents(&[400, 100, 300, 200]), Some(200));
+        assert_eq!(median_cents(&[]), None);
+
+        assert_eq!(discount_pct(Money::new(700, 3), Money::new(1000, 3)), Some(30.0));
+        assert_eq!(discount_pct(Money::new(1001, 3), Money::new(3000, 3)), Some(66.6));
+        assert_eq!(discount_pct(Money::new(1200, 3), Money::new(1000, 3)), Some(-20.0));
+        assert_eq!(discount_pct(Money::new(700, 3), Money::new(1000, 1)), None);
+    }
+}
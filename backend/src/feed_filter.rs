@@ -0,0 +1,263 @@
+use std::fmt;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::{MostRecent, MostRecentItemsFilter};
+
+const FILTER_MAX_APPIDS: usize = 32;
+const FILTER_MAX_EXCLUDES: usize = 20;
+const FILTER_MAX_TEXT: usize = 200;
+//Compiled program size, keeps a hostile pattern from eating memory per connection
+const FILTER_REGEX_SIZE_LIMIT: usize = 1 << 16;
+
+/// What a feed subscriber wants to see, the same on /ws and GET /api/feed.
+/// Prices are cents in the subscriber's display currency. Empty fields don't filter.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct FeedFilter {
+    /// Any of these apps
+    pub appids: Vec<u32>,
+    /// An appid or part of a game name, what the side panel's game box sends
+    pub game: Option<String>,
+    pub tradable_only: bool,
+    pub price_min: Option<i64>,
+    pub price_max: Option<i64>,
+    /// Part of the item name, or a pattern when `regex` is set. Case-insensitive either way
+    pub query: Option<String>,
+    pub regex: bool,
+    /// Items whose name contains any of these are left out
+    pub exclude: Vec<String>,
+    /// Percent under the reference price
+    pub min_discount: Option<f64>,
+}
+
+/// Why a filter was turned down, sent back to the client as is.
+#[derive(Debug, PartialEq)]
+pub enum FilterError {
+    Appid(String),
+    TooManyAppids,
+    TooManyExcludes,
+    TooLong(&'static str),
+    PriceRange,
+    Discount,
+    Regex(String),
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterError::Appid(text) => write!(f, "not an appid: {text}"),
+            FilterError::TooManyAppids => write!(f, "at most {FILTER_MAX_APPIDS} appids"),
+            FilterError::TooManyExcludes => write!(f, "at most {FILTER_MAX_EXCLUDES} exclusion keywords"),
+            FilterError::TooLong(field) => write!(f, "{field} is longer than {FILTER_MAX_TEXT} characters"),
+            FilterError::PriceRange => write!(f, "prices must be positive with price_min at most price_max"),
+            FilterError::Discount => write!(f, "min_discount must be between -100 and 100"),
+            FilterError::Regex(e) => write!(f, "invalid regex: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+/// A validated FeedFilter with its pattern compiled, ready to test listings.
+#[derive(Debug, Clone, Default)]
+pub struct FeedMatcher {
+    filter: FeedFilter,
+    game: Option<String>,
+    query: Option<String>,
+    pattern: Option<Regex>,
+    exclude: Vec<String>,
+}
+
+impl FeedFilter {
+    /// The session's string filters. A game box holding a number is taken as an appid or a name, like before.
+    pub fn from_legacy(legacy: &MostRecentItemsFilter) -> FeedFilter {
+        FeedFilter {
+            game: non_blank(Some(&legacy.appid)),
+            price_min: legacy.price_min.trim().parse().ok(),
+            price_max: legacy.price_max.trim().parse().ok(),
+            query: non_blank(Some(&legacy.query)),
+            min_discount: legacy.min_discount.trim().parse().ok(),
+            ..Default::default()
+        }
+    }
+
+    pub fn compile(self) -> Result<FeedMatcher, FilterError> {
+        if self.appids.len() > FILTER_MAX_APPIDS {
+            return Err(FilterError::TooManyAppids);
+        }
+        if self.exclude.len() > FILTER_MAX_EXCLUDES {
+            return Err(FilterError::TooManyExcludes);
+        }
+        for (field, text) in [("game", self.game.as_deref()), ("query", self.query.as_deref())] {
+            if text.is_some_and(|text| text.chars().count() > FILTER_MAX_TEXT) {
+                return Err(FilterError::TooLong(field));
+            }
+        }
+        if self.exclude.iter().any(|word| word.chars().count() > FILTER_MAX_TEXT) {
+            return Err(FilterError::TooLong("exclude"));
+        }
+        if self.price_min.is_some_and(|min| min < 0)
+            || self.price_max.is_some_and(|max| max < 0)
+            || self.price_min.zip(self.price_max).is_some_and(|(min, max)| min > max)
+        {
+            return Err(FilterError::PriceRange);
+        }
+        if self.min_discount.is_some_and(|pct| !(-100.0..=100.0).contains(&pct)) {
+            return Err(FilterError::Discount);
+        }
+
+        let query = non_blank(self.query.as_deref());
+        let pattern = match (&query, self.regex) {
+            (Some(query), true) => Some(
+                RegexBuilder::new(query)
+                    .case_insensitive(true)
+                    .size_limit(FILTER_REGEX_SIZE_LIMIT)
+                    .build()
+                    .map_err(|e| FilterError::Regex(e.to_string()))?,
+            ),
+            _ => None,
+        };
+
+        Ok(FeedMatcher {
+            game: non_blank(self.game.as_deref()).map(|game| game.to_lowercase()),
+            query: query.filter(|_| !self.regex).map(|query| query.to_lowercase()),
+            exclude: self.exclude.iter().filter_map(|word| non_blank(Some(word))).map(|word| word.to_lowercase()).collect(),
+            pattern,
+            filter: self,
+        })
+    }
+}
+
+impl FeedMatcher {
+    pub fn filter(&self) -> &FeedFilter {
+        &self.filter
+    }
+
+    /// Plain substring to narrow on in SQL, None for a pattern or no query.
+    pub fn text(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    /// Game box text, lowercased.
+    pub fn game(&self) -> Option<&str> {
+        self.game.as_deref()
+    }
+
+    /// Tests `item` already priced in the subscriber's currency.
+    /// Listings without a game never match, unpriced ones count as 0.
+    pub fn matches(&self, item: &MostRecent) -> bool {
+        let filter = &self.filter;
+        if item.game.trim().is_empty() {
+            return false;
+        }
+
+        if !filter.appids.is_empty() && !item.appid.parse().is_ok_and(|appid: u32| filter.appids.contains(&appid)) {
+            return false;
+        }
+        if let Some(game) = &self.game
+            && item.appid != *game
+            && !item.game.to_lowercase().contains(game)
+        {
+            return false;
+        }
+        if filter.tradable_only && item.tradable != "1" {
+            return false;
+        }
+
+        let cents = item.price.map(|price| price.cents).unwrap_or(0);
+        if cents < filter.price_min.unwrap_or(0) || cents > filter.price_max.unwrap_or(i64::MAX) {
+            return false;
+        }
+        if let Some(min) = filter.min_discount
+            && !item.discount_pct.is_some_and(|pct| pct >= min)
+        {
+            return false;
+        }
+
+        let name = item.name.to_lowercase();
+        if let Some(query) = &self.query
+            && !name.contains(query.as_str())
+        {
+            return false;
+        }
+        if let Some(pattern) = &self.pattern
+            && !pattern.is_match(&item.name)
+        {
+            return false;
+        }
+        !self.exclude.iter().any(|word| name.contains(word.as_str()))
+    }
+}
+
+fn non_blank(text: Option<&str>) -> Option<String> {
+    text.map(str::trim).filter(|text| !text.is_empty()).map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listing(appid: &str, name: &str, cents: i64, tradable: bool) -> MostRecent {
+        MostRecent {
+            appid: appid.into(),
+            game: if appid == "730" { "Counter-Strike 2" } else { "Team Fortress 2" }.into(),
+            tradable: if tradable { "1" } else { "0" }.into(),
+            ..MostRecent::sample(name, Some(cents))
+        }
+    }
+
+    #[test]
+    fn every_field_narrows() {
+        let matcher = FeedFilter {
+            appids: vec![730, 570],
+            tradable_only: true,
+            price_max: Some(5000),
+            query: Some(r"^ak-47 \| (red|blue)".into()),
+            regex: true,
+            exclude: vec!["StatTrak".into()],
+            ..Default::default()
+        }
+        .compile()
+        .unwrap();
+
+        assert!(matcher.matches(&listing("730", "AK-47 | Redline", 3000, true)));
+        assert!(!matcher.matches(&listing("440", "AK-47 | Redline", 3000, true)));
+        assert!(!matcher.matches(&listing("730", "AK-47 | Redline", 3000, false)));
+        assert!(!matcher.matches(&listing("730", "AK-47 | Redline", 6000, true)));
+        assert!(!matcher.matches(&listing("730", "AK-47 | Asiimov", 3000, true)));
+        assert!(!matcher.matches(&listing("730", "AK-47 | Redline StatTrak™", 3000, true)));
+    }
+
+    #[test]
+    fn legacy_game_box_matches_appid_or_name() {
+        let legacy = MostRecentItemsFilter {
+            appid: "team".into(),
+            price_min: "0".into(),
+            price_max: "99999".into(),
+            query: "".into(),
+            min_discount: "".into(),
+        };
+        let matcher = FeedFilter::from_legacy(&legacy).compile().unwrap();
+        assert!(matcher.matches(&listing("440", "Key", 250, false)));
+        assert!(!matcher.matches(&listing("730", "Key", 250, false)));
+
+        let by_appid = FeedFilter { game: Some("730".into()), ..Default::default() }.compile().unwrap();
+        assert!(by_appid.matches(&listing("730", "Key", 250, false)));
+    }
+
+    #[test]
+    fn bad_filters_are_rejected() {
+        let regex = FeedFilter { query: Some("(unclosed".into()), regex: true, ..Default::default() };
+        assert!(matches!(regex.compile(), Err(FilterError::Regex(_))));
+
+        let range = FeedFilter { price_min: Some(10), price_max: Some(5), ..Default::default() };
+        assert_eq!(range.compile().unwrap_err(), FilterError::PriceRange);
+
+        let appids = FeedFilter { appids: (0..40).collect(), ..Default::default() };
+        assert_eq!(appids.compile().unwrap_err(), FilterError::TooManyAppids);
+
+        //The same text without `regex` is just a substring
+        assert!(FeedFilter { query: Some("(unclosed".into()), ..Default::default() }.compile().is_ok());
+    }
+}
@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{CurrencyRates, FeedFilter, FilterError, MostRecent};
+
+const FEED_PAGE_DEFAULT_LIMIT: u32 = 50;
+const FEED_PAGE_MAX_LIMIT: u32 = 200;
+
+/// GET /api/feed. Without `after_id` pages run newest first from `before_id`,
+/// with it they run oldest first so a reconnecting client can replay what it missed.
+/// The rest is a FeedFilter in query-string form: `appids` and `exclude` are comma
+/// separated, prices are cents in `currency` (default: the user's display currency).
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct FeedQuery {
+    pub before_id: Option<i64>,
+    pub after_id: Option<i64>,
+    pub limit: Option<u32>,
+    pub currency: Option<u32>,
+    pub appids: Option<String>,
+    pub game: Option<String>,
+    pub tradable: Option<bool>,
+    pub price_min: Option<i64>,
+    pub price_max: Option<i64>,
+    pub query: Option<String>,
+    pub regex: Option<bool>,
+    pub exclude: Option<String>,
+    pub min_discount: Option<f64>,
+}
+
+/// One page of feed history.
+#[derive(Serialize, Debug)]
+pub struct FeedPage {
+    pub items: Vec<MostRecent>,
+    /// Send back as the same cursor (`before_id` or `after_id`) for the next page.
+    /// None once there is nothing left in that direction.
+    pub next_cursor: Option<i64>,
+}
+
+impl FeedQuery {
+    pub fn oldest_first(&self) -> bool {
+        self.after_id.is_some()
+    }
+
+    pub fn page_limit(&self) -> usize {
+        self.limit.unwrap_or(FEED_PAGE_DEFAULT_LIMIT).clamp(1, FEED_PAGE_MAX_LIMIT) as usize
+    }
+
+    /// Moves the cursor past an item that was looked at, matched or not.
+    pub fn advance(&mut self, id: i64) {
+        if self.oldest_first() {
+            self.after_id = Some(id);
+        } else {
+            self.before_id = Some(id);
+        }
+    }
+
+    pub fn cursor(&self) -> Option<i64> {
+        if self.oldest_first() { self.after_id } else { self.before_id }
+    }
+
+    /// The filter part, still to be compiled.
+    pub fn filter(&self) -> Result<FeedFilter, FilterError> {
+        let appids = comma_list(self.appids.as_deref())
+            .map(|appid| appid.parse().map_err(|_| FilterError::Appid(appid.to_string())))
+            .collect::<Result<Vec<u32>, _>>()?;
+
+        Ok(FeedFilter {
+            appids,
+            game: self.game.clone(),
+            tradable_only: self.tradable.unwrap_or(false),
+            price_min: self.price_min,
+            price_max: self.price_max,
+            query: self.query.clone(),
+            regex: self.regex.unwrap_or(false),
+            exclude: comma_list(self.exclude.as_deref()).map(str::to_string).collect(),
+            min_discount: self.min_discount,
+        })
+    }
+}
+
+fn comma_list(text: Option<&str>) -> impl Iterator<Item = &str> {
+    text.unwrap_or_default().split(',').map(str::trim).filter(|part| !part.is_empty())
+}
+
+impl MostRecent {
+    /// Same listing priced in `currency`. Stays as is without a target or a rate.
+    pub fn in_currency(&self, currency: Option<u32>, rates: &CurrencyRates) -> MostRecent {
+        let mut item = self.clone();
+
+        if let Some(target) = currency
+            && let Some(price) = item.price
+            && let Some(converted) = price.convert(target, rates)
+        {
+            item.price = Some(converted);
+            //Same rate for both, the discount stays as it was
+            item.reference_price = item.reference_price.and_then(|reference| reference.convert(target, rates));
+        }
+
+        item
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_follows_direction() {
+        let mut newest_first = FeedQuery { before_id: Some(100), ..Default::default() };
+        newest_first.advance(90);
+        assert_eq!((newest_first.before_id, newest_first.cursor()), (Some(90), Some(90)));
+
+        let mut replay = FeedQuery { after_id: Some(10), ..Default::default() };
+        replay.advance(20);
+        assert_eq!((replay.after_id, replay.cursor()), (Some(20), Some(20)));
+
+        assert_eq!(FeedQuery { limit: Some(10_000), ..Default::default() }.page_limit(), 200);
+
+        let query = FeedQuery { appids: Some("730, 440,".into()), exclude: Some("souvenir,,StatTrak".into()), ..Default::default() };
+        let filter = query.filter().unwrap();
+        assert_eq!((filter.appids, filter.exclude), (vec![730, 440], vec!["souvenir".to_string(), "StatTrak".to_string()]));
+        assert_eq!(FeedQuery { appids: Some("cs2".into()), ..Default::default() }.filter(), Err(FilterError::Appid("cs2".into())));
+    }
+}
@@ -0,0 +1,212 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    time::Duration,
+};
+use serde::Serialize;
+
+use crate::SteamMostRecentResponse;
+
+//A poll returns ~100 listings, remember a few polls worth
+const SEEN_LISTINGS_CAP: usize = 1000;
+
+/// Bounds for the adaptive /market/recent poll interval.
+#[derive(Clone, Debug)]
+pub struct FeedPollConfig {
+    pub min_interval: Duration,
+    pub max_interval: Duration,
+}
+
+impl Default for FeedPollConfig {
+    fn default() -> Self {
+        FeedPollConfig {
+            min_interval: Duration::from_secs(15),
+            max_interval: Duration::from_secs(120),
+        }
+    }
+}
+
+impl FeedPollConfig {
+    /// Default config with overrides from FEED_MIN_INTERVAL_SECS and FEED_MAX_INTERVAL_SECS.
+    pub fn from_env() -> Self {
+        let mut config = FeedPollConfig::default();
+
+        if let Some(v) = env_secs("FEED_MIN_INTERVAL_SECS") {
+            config.min_interval = v;
+        }
+        if let Some(v) = env_secs("FEED_MAX_INTERVAL_SECS") {
+            config.max_interval = v;
+        }
+        config.max_interval = config.max_interval.max(config.min_interval);
+
+        config
+    }
+
+    /// Polls that are mostly new listings mean we're falling behind, so poll faster.
+    /// Polls that are mostly repeats mean we're wasting requests, so slow down.
+    pub fn next_interval(&self, current: Duration, outcome: &PollOutcome) -> Duration {
+        let next = if outcome.gap {
+            self.min_interval
+        } else if outcome.new * 4 >= outcome.returned * 3 && outcome.returned > 0 {
+            current / 2
+        } else if outcome.new * 4 <= outcome.returned {
+            current.mul_f64(1.5)
+        } else {
+            current
+        };
+
+        next.clamp(self.min_interval, self.max_interval)
+    }
+}
+
+fn env_secs(key: &str) -> Option<Duration> {
+    std::env::var(key).ok()?.trim().parse().ok().map(Duration::from_secs)
+}
+
+/// What one poll brought compared to the ones before it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PollOutcome {
+    pub returned: usize,
+    pub new: usize,
+    /// Nothing overlapped with earlier polls, so listings between them were probably missed.
+    pub gap: bool,
+}
+
+/// Monitoring counters for the feed poller, served by /api/feed/stats.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct FeedPollStats {
+    pub polls: u64,
+    pub errors: u64,
+    pub rate_limited: u64,
+    pub gaps: u64,
+    pub total_new: u64,
+    pub last_returned: usize,
+    pub last_new: usize,
+    pub last_listing: Option<String>,
+    pub last_time: Option<u64>,
+    pub last_poll_at: Option<i64>,
+    pub interval_secs: u64,
+}
+
+/// Cursor and recently seen listing ids across polls.
+#[derive(Debug, Default)]
+pub struct FeedCursor {
+    pub last_listing: Option<String>,
+    pub last_time: Option<u64>,
+    seen: HashSet<String>,
+    seen_order: VecDeque<String>,
+}
+
+impl FeedCursor {
+    /// Drops listings we already passed on and moves the cursor forward.
+    pub fn advance(&mut self, response: &mut SteamMostRecentResponse) -> PollOutcome {
+        let returned = response.listinginfo.len();
+        let first_poll = self.last_time.is_none();
+
+        //Same cursor as last time means Steam has nothing new for us
+        let unchanged = self.last_time == Some(response.last_time as u64)
+            && self.last_listing.as_deref() == Some(response.last_listing.as_str());
+
+        if unchanged {
+            response.listinginfo.clear();
+        } else {
+            response.listinginfo.retain(|id, _| !self.seen.contains(id));
+        }
+
+        let new = response.listinginfo.len();
+
+        for id in response.listinginfo.keys() {
+            self.remember(id.clone());
+        }
+
+        self.last_listing = Some(response.last_listing.clone());
+        self.last_time = Some(response.last_time as u64);
+
+        PollOutcome {
+            returned,
+            new,
+            gap: !first_poll && returned > 0 && new == returned,
+        }
+    }
+
+    fn remember(&mut self, id: String) {
+        if self.seen.insert(id.clone()) {
+            self.seen_order.push_back(id);
+        }
+
+        while self.seen_order.len() > SEEN_LISTINGS_CAP {
+            if let Some(old) = self.seen_order.pop_front() {
+                self.seen.remove(&old);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(ids: &[&str], last_time: u64) -> SteamMostRecentResponse {
+        let listinginfo: serde_json::Map<String, serde_json::Value> = ids
+            .iter()
+            .map(|id| {
+                (id.to_string(), serde_json::json!({
+                    "listingid": id,
+                    "price": 100.0,
+                    "fee": 0,
+                    "publisher_fee_app": 730,
+                    "publisher_fee_percent": "0.10",
+                    "currencyid": 2003,
+                    "asset": { "currency": 0, "appid": 730, "contextid": "2", "id": id, "amount": "1" }
+                }))
+            })
+            .collect();
+
+        serde_json::from_value(serde_json::json!({
+            "success": true,
+            "more": false,
+            "results_html": false,
+            "listinginfo": listinginfo,
+            "purchaseinfo": [],
+            "assets": {},
+            "currency": [],
+            "app_data": {},
+            "last_time": last_time,
+            "last_listing": ids.first().copied().unwrap_or_default(),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn cursor_drops_seen_listings_and_flags_gaps() {
+        let mut cursor = FeedCursor::default();
+
+        let mut first = response(&["1", "2", "3"], 10);
+        assert_eq!(cursor.advance(&mut first), PollOutcome { returned: 3, new: 3, gap: false });
+
+        let mut overlap = response(&["4", "3", "2"], 20);
+        assert_eq!(cursor.advance(&mut overlap), PollOutcome { returned: 3, new: 1, gap: false });
+        assert!(overlap.listinginfo.contains_key("4"));
+
+        let mut repeat = response(&["4", "3", "2"], 20);
+        assert_eq!(cursor.advance(&mut repeat), PollOutcome { returned: 3, new: 0, gap: false });
+
+        let mut jump = response(&["9", "8", "7"], 90);
+        assert_eq!(cursor.advance(&mut jump), PollOutcome { returned: 3, new: 3, gap: true });
+    }
+
+    #[test]
+    fn interval_adapts_within_bounds() {
+        let config = FeedPollConfig {
+            min_interval: Duration::from_secs(10),
+            max_interval: Duration::from_secs(60),
+        };
+        let now = Duration::from_secs(40);
+        let outcome = |returned, new, gap| PollOutcome { returned, new, gap };
+
+        assert_eq!(config.next_interval(now, &outcome(100, 90, false)), Duration::from_secs(20));
+        assert_eq!(config.next_interval(now, &outcome(100, 50, false)), now);
+        assert_eq!(config.next_interval(now, &outcome(100, 5, false)), Duration::from_secs(60));
+        assert_eq!(config.next_interval(now, &outcome(0, 0, false)), Duration::from_secs(60));
+        assert_eq!(config.next_interval(now, &outcome(100, 100, true)), Duration::from_secs(10));
+    }
+}
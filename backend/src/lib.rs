@@ -1,15 +1,108 @@
-use std::{collections::{HashMap, VecDeque}, time::Duration};
+use std::{collections::{HashMap, VecDeque}, sync::Arc};
 use tokio::sync::{mpsc, Mutex};
+use futures_util::stream::{self, Stream};
 use actix_web::{Responder, HttpResponse, HttpRequest, body::BoxBody, http::header::ContentType};
 
-use serde::{Deserialize, Serialize, Deserializer};
+use serde::{Deserialize, Serialize};
 
 mod steam_request;
 use steam_request::{ProcessSteamRequest, SteamRequest};
 
-type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
-
-#[derive(Clone, Copy)]
+mod steam_client;
+pub use steam_client::{SteamClient, SteamClientConfig};
+
+mod steam_error;
+pub use steam_error::SteamError;
+
+mod price_history;
+pub use price_history::{
+    build_candles,
+    CandleInterval,
+    PriceCandle,
+    PriceHistoryQuery,
+    PriceHistoryResponse
+};
+
+mod feed_poll;
+pub use feed_poll::{
+    FeedCursor,
+    FeedPollConfig,
+    FeedPollStats,
+    PollOutcome
+};
+
+mod money;
+pub use money::{
+    offer_price,
+    Money,
+    OFFER_CURRENCY
+};
+
+mod catalog;
+pub use catalog::CatalogItem;
+
+mod deals;
+pub use deals::{
+    discount_pct,
+    median_cents
+};
+
+mod feed_filter;
+pub use feed_filter::{
+    FeedFilter,
+    FeedMatcher,
+    FilterError
+};
+
+mod feed_page;
+pub use feed_page::{
+    FeedPage,
+    FeedQuery
+};
+
+mod search;
+pub use search::{
+    highlight_html,
+    SearchHit,
+    SearchQuery,
+    MARK_END,
+    MARK_START
+};
+
+mod saved_search;
+pub use saved_search::{
+    Notification,
+    NotificationQuery,
+    NotificationsRead,
+    SavedSearch,
+    SavedSearchInput
+};
+
+mod currency;
+pub use currency::{
+    currency_code,
+    observed_rates,
+    CurrencyRates,
+    ObservedRate,
+    STEAM_CURRENCIES
+};
+
+mod ws_protocol;
+pub use ws_protocol::{
+    ChatLine,
+    ClientMessage,
+    FilterSpec,
+    FiltersMessage,
+    OfferStatus,
+    OfferStepKind,
+    ServerMessage,
+    WsErrorCode,
+    WS_PROTOCOL_VERSION
+};
+
+type Result<T> = std::result::Result<T, SteamError>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Sort{
     Name,
     Price,
@@ -17,11 +110,31 @@ pub enum Sort{
     Popular,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum SortDirection{
     Asc,
     Desc,
 }
+
+impl Sort{
+    fn as_param(&self) -> &'static str {
+        match self {
+            Sort::Name => "name",
+            Sort::Price => "price",
+            Sort::Quantity => "quantity",
+            Sort::Popular => "popular",
+        }
+    }
+}
+
+impl SortDirection{
+    fn as_param(&self) -> &'static str {
+        match self {
+            SortDirection::Asc => "asc",
+            SortDirection::Desc => "desc",
+        }
+    }
+}
 //----------------------------------
 //----------------------------------
 //HTTP Playloads
@@ -46,7 +159,8 @@ impl Responder for OfferMakingPlayload{
 #[derive(Deserialize, Serialize, Debug)]
 pub struct OfferContentUpdated{
     pub offer_id: String,
-    pub total_price: f64,
+    #[serde(with = "offer_price")]
+    pub total_price: Money,
     pub total_count: i32,
     pub new_items: Vec<OfferItems>,
     pub removed_items: Vec<OfferItems>,
@@ -158,7 +272,8 @@ pub struct OfferItems{
     pub item_contextid: String,
     pub item_appid: String,
     pub item_name: String,
-    pub item_price: String,
+    #[serde(with = "offer_price")]
+    pub item_price: Money,
     pub item_link: String,
     pub item_image: String,
 }
@@ -183,7 +298,7 @@ pub struct OfferCheckResult{
 //----------------------------------
 //----------------------------------
 //Trade offer draft
-#[derive(Serialize)]
+#[derive(Serialize, Clone, Debug, PartialEq)]
 pub struct DraftItem {
     pub appid: u32,
     pub contextid: String,
@@ -210,6 +325,11 @@ pub struct ProfileTradeUrl{
     pub trade_url: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProfileCurrency{
+    pub currency: u32,
+}
+
 //----------------------------------
 //----------------------------------
 
@@ -356,17 +476,20 @@ pub struct UserAdsQueue{
 //----------------------------------
 //----------------------------------
 //Requests
-#[derive(Deserialize, Serialize, Debug)]
-pub struct MarketRequest{
-    game: u32,
-    page: u32,
-    count: u32,
+/// Builder for market/search/render. Build it, then call `fetch()`.
+/// Prices are in cents, like everywhere else Steam talks about money.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MarketSearch{
+    appid: u32,
     query: String,
-    sort: String,
-    sort_dir: String,
+    sort: Option<(Sort, SortDirection)>,
+    price_min: Option<u32>,
+    price_max: Option<u32>,
     search_descriptions: bool,
-    price_min: u32,
-    price_max: u32,
+    start: u32,
+    count: u32,
+    //(category, tag) -> category_{appid}_{category}[]={tag}
+    tags: Vec<(String, String)>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -376,6 +499,25 @@ pub struct MostRecentItemsRequest{
     currency: String,
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PriceOverviewRequest{
+    pub appid: u32,
+    pub market_hash_name: String,
+    pub currency: u32,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PriceOverviewQuery{
+    pub currency: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct OrderBookRequest{
+    pub appid: u32,
+    pub market_hash_name: String,
+    pub currency: u32,
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct TradeOfferRequest{
     // partner_steam_id: String,
@@ -384,12 +526,18 @@ pub struct TradeOfferRequest{
 //----------------------------------
 //----------------------------------
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct MostRecentItemsFilter{
     pub appid: String,
+    #[serde(default)]
     pub price_min: String,
+    #[serde(default)]
     pub price_max: String,
+    #[serde(default)]
     pub query: String,
+    //Percent under the reference price, "" for any
+    #[serde(default)]
+    pub min_discount: String,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -403,6 +551,8 @@ pub struct FilterInput {
     pub price_min: String,
     pub price_max: String,
     pub query: String,
+    #[serde(default)]
+    pub min_discount: String,
     pub card_appearing: String,
 }
 
@@ -502,6 +652,9 @@ pub struct Assets{
     descriptions: Option<Vec<AssetDescription>>,
     pub tradable: Option<usize>,
     owner_actions: Option<Vec<OwnerActions>>,
+    market_actions: Option<Vec<OwnerActions>>,
+    #[serde(rename = "type")]
+    item_type: Option<String>,
     name: Option<String>,
     name_color: Option<String>,
     pub market_name: Option<String>,
@@ -533,12 +686,32 @@ pub struct Purchaseinfo{}
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Currency{}
 
+#[derive(Deserialize, Serialize, Debug)]
+struct SteamPriceOverviewResponse {
+    success: bool,
+    lowest_price: Option<String>,
+    volume: Option<String>,
+    median_price: Option<String>,
+}
+
+//graph entries are [price in currency units, cumulative quantity, label]
+#[derive(Deserialize, Serialize, Debug)]
+struct SteamOrderHistogramResponse {
+    success: i32,
+    highest_buy_order: Option<String>,
+    lowest_sell_order: Option<String>,
+    #[serde(default)]
+    buy_order_graph: Vec<(f64, i64, String)>,
+    #[serde(default)]
+    sell_order_graph: Vec<(f64, i64, String)>,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct AppData{
-    appid: usize,
+    pub appid: usize,
     pub name: String,
     pub icon: String,
-    link: String,
+    pub link: String,
 }
 //----------------------------------
 //----------------------------------
@@ -593,18 +766,59 @@ pub struct Description {
 //----------------------------------
 
 //DB
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MostRecent{
     pub id: usize,
     pub listinginfo_id: String,
     pub name: String,
-    pub converted_price: String,
+    /// None when Steam didn't convert the listing price
+    pub price: Option<Money>,
     pub appid: String,
     pub game: String,
     pub market_hash_name: String,
     pub tradable: String,
     pub icon: String,
     pub game_icon: String,
+    pub rarity: Option<String>,
+    pub exterior: Option<String>,
+    /// What the item usually goes for, in the currency of `price`. See DealScoring
+    #[serde(default)]
+    pub reference_price: Option<Money>,
+    /// How far `price` is under `reference_price`, negative when above it
+    #[serde(default)]
+    pub discount_pct: Option<f64>,
+}
+
+#[cfg(test)]
+impl MostRecent {
+    /// A tradable CS2 listing of `name`, priced in currency 3. Tests override the rest.
+    pub(crate) fn sample(name: &str, cents: Option<i64>) -> Self {
+        MostRecent {
+            id: 1,
+            listinginfo_id: "1".into(),
+            name: name.into(),
+            price: cents.map(|cents| Money::new(cents, 3)),
+            appid: "730".into(),
+            game: "Counter-Strike 2".into(),
+            market_hash_name: name.into(),
+            tradable: "1".into(),
+            icon: String::new(),
+            game_icon: String::new(),
+            rarity: None,
+            exterior: None,
+            reference_price: None,
+            discount_pct: None,
+        }
+    }
+}
+
+/// A row of the games table with how many of its listings are in the feed right now.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct Game{
+    pub appid: u32,
+    pub name: String,
+    pub icon: String,
+    pub listing_count: i64,
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -612,6 +826,41 @@ pub struct DBFiltersMostRecentItems{
     pub filters: String,
 }
 
+//One price level of the order book, quantity is at this price only
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct OrderLevel{
+    pub price: i64,
+    pub quantity: i64,
+}
+
+//Buy ladder is best (highest) first, sell ladder is best (lowest) first
+#[derive(Clone, Debug, Serialize)]
+pub struct OrderBook{
+    pub appid: u32,
+    pub market_hash_name: String,
+    pub currency: u32,
+    pub item_nameid: u64,
+    pub highest_buy_order: Option<i64>,
+    pub lowest_sell_order: Option<i64>,
+    pub buy_orders: Vec<OrderLevel>,
+    pub sell_orders: Vec<OrderLevel>,
+    pub fetched_at: i64,
+}
+
+//Prices are cents of `currency`, texts are what Steam showed
+#[derive(Clone, Debug, Serialize)]
+pub struct PriceOverview{
+    pub appid: u32,
+    pub market_hash_name: String,
+    pub currency: u32,
+    pub lowest_price: Option<i64>,
+    pub median_price: Option<i64>,
+    pub volume: Option<i64>,
+    pub lowest_price_text: Option<String>,
+    pub median_price_text: Option<String>,
+    pub fetched_at: i64,
+}
+
 //----------------------------------
 //----------------------------------
 //Customer creating
@@ -644,17 +893,49 @@ pub enum PaymentMethodFeeEnum{
 //Trait bounds
 
 //Allow to send requests and get access to ProcessSteamRequest methods
-impl SteamRequest for SteamMarketResponse{}
-impl SteamRequest for SteamMostRecentResponse{}
+impl SteamRequest for SteamMarketResponse{
+    fn steam_success(&self) -> bool {
+        self.success
+    }
+}
+impl SteamRequest for SteamMostRecentResponse{
+    fn steam_success(&self) -> bool {
+        self.success
+    }
+}
 impl SteamRequest for TradeOfferRequest{}
+impl SteamRequest for SteamOrderHistogramResponse{
+    fn steam_success(&self) -> bool {
+        self.success == 1
+    }
+}
+impl SteamRequest for SteamPriceOverviewResponse{
+    fn steam_success(&self) -> bool {
+        self.success
+    }
+}
 
 //Allow to use methods of ProcessSteamRequest trait
-impl ProcessSteamRequest for MarketRequest{}
+impl ProcessSteamRequest for MarketSearch{}
 impl ProcessSteamRequest for MostRecentItemsRequest{}
 impl ProcessSteamRequest for TradeOfferRequest{}
+impl ProcessSteamRequest for PriceOverviewRequest{}
+impl ProcessSteamRequest for OrderBookRequest{}
 //----------------------------------
 //----------------------------------
 
+impl Listinginfo{
+    /// Currency of `converted_price`. Steam sends 2000 + ECurrencyCode here.
+    pub fn converted_currency(&self) -> Option<u32> {
+        self.converted_currencyid.map(|id| (id % 1000) as u32)
+    }
+
+    /// Currency the seller listed `price` in.
+    pub fn listing_currency(&self) -> u32 {
+        (self.currencyid % 1000) as u32
+    }
+}
+
 impl StoreQueueHashmap{
     pub fn new()->Self {
         StoreQueueHashmap { 
@@ -663,21 +944,15 @@ impl StoreQueueHashmap{
     }
 }
 
-impl CustomItems{
-
-    pub async fn get_items_query(game: Option<u32>, count: Option<u32>, page: Option<u32>, query: Option<String>,
-        sort: Option<Sort>, sort_dir: Option<SortDirection>, search_descriptions: Option<bool>, price_min: Option<u32>,
-        price_max: Option<u32>)-> Result<Self>{
-
-        let response_result = MarketRequest::request_paramenters(game, count, page, query, sort, sort_dir, search_descriptions, price_min, price_max).await?;
-
-        Ok(CustomItems { vec: response_result.results })
+impl Default for StoreQueueHashmap{
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl MostRecentItems{
     pub async fn get_most_recent_items(country: Option<String>, language: Option<String>, currency: Option<String>, 
-        tx: mpsc::Sender<SteamMostRecentResponse>) -> Result<Self>{
+        config: FeedPollConfig, stats: Arc<Mutex<FeedPollStats>>, tx: mpsc::Sender<SteamMostRecentResponse>) -> Result<Self>{
         
         //Making request struct
         let most_recent_struct = MostRecentItemsRequest::request_paramenters(country, language, currency);
@@ -690,7 +965,7 @@ impl MostRecentItems{
 
         //Spawn background task for loop
         tokio::spawn(async move {
-            Self::fetch_items_loop(url, tx).await;
+            Self::fetch_items_loop(url, config, stats, tx).await;
         });
 
         match response_result {
@@ -710,22 +985,57 @@ impl MostRecentItems{
     ///Dont use more than 1 async thread for MostRecentItems for request!!!
     ///fetch_items_loop is enough
     ///---------------------------------------------------------------------
+    async fn fetch_items_loop(url: String, config: FeedPollConfig, stats: Arc<Mutex<FeedPollStats>>,
+        tx: mpsc::Sender<SteamMostRecentResponse>) {
 
-    async fn fetch_items_loop(url: String, tx: mpsc::Sender<SteamMostRecentResponse>) {
+        let mut cursor = FeedCursor::default();
+        let mut interval = config.max_interval;
 
         loop {
             let response: Result<SteamMostRecentResponse> = MostRecentItemsRequest::process_request(url.clone()).await;
 
+            let mut sleep_for = interval;
+
             match response {
-                Ok(items) => {
-                    tx.send(items.clone()).await.expect("Cant send SteamMostResponse via channel")
+                Ok(mut items) => {
+                    let outcome = cursor.advance(&mut items);
+                    interval = config.next_interval(interval, &outcome);
+                    sleep_for = interval;
+
+                    if outcome.gap {
+                        eprintln!("Most recent items: no overlap with the previous poll, listings were probably missed");
+                    }
+
+                    {
+                        let mut stats = stats.lock().await;
+                        stats.polls += 1;
+                        stats.gaps += outcome.gap as u64;
+                        stats.total_new += outcome.new as u64;
+                        stats.last_returned = outcome.returned;
+                        stats.last_new = outcome.new;
+                        stats.last_listing = cursor.last_listing.clone();
+                        stats.last_time = cursor.last_time;
+                        stats.last_poll_at = Some(chrono::Utc::now().timestamp());
+                    }
+
+                    if outcome.new > 0 {
+                        tx.send(items).await.expect("Cant send SteamMostResponse via channel")
+                    }
+                }
+                Err(SteamError::RateLimited { retry_after }) => {
+                    stats.lock().await.rate_limited += 1;
+                    //Client already retried, so back off for the whole poll
+                    sleep_for = retry_after.unwrap_or_default().max(config.max_interval * 5);
+                    eprintln!("Steam rate limited most recent items, next poll in {sleep_for:?}")
                 }
                 Err(e) => {
+                    stats.lock().await.errors += 1;
                     eprintln!("Error fetching items: {e}")
                 }
             };
 
-            tokio::time::sleep(Duration::from_secs(60)).await;
+            stats.lock().await.interval_secs = sleep_for.as_secs();
+            tokio::time::sleep(sleep_for).await;
         }
     }
 }
@@ -746,89 +1056,288 @@ impl TradeOfferRequest{
     }
 }
 
-impl MarketRequest{
-    async fn request_paramenters(game: Option<u32>, count: Option<u32>, page: Option<u32>, query: Option<String>,
-        sort: Option<Sort>, sort_dir: Option<SortDirection>, search_descriptions: Option<bool>, price_min: Option<u32>,
-        price_max: Option<u32>)-> Result<SteamMarketResponse>{
+impl MarketSearch{
+    //Steam ignores anything above 100
+    pub const MAX_COUNT: u32 = 100;
+
+    pub fn new(appid: u32) -> Self {
+        MarketSearch {
+            appid,
+            query: String::new(),
+            sort: None,
+            price_min: None,
+            price_max: None,
+            search_descriptions: false,
+            start: 0,
+            count: 10,
+            tags: Vec::new(),
+        }
+    }
 
-        let game = match game{
-            Some(appid) => appid,
-            None => 730,
-        };
+    pub fn query(mut self, query: impl Into<String>) -> Self {
+        self.query = query.into();
+        self
+    }
 
-        //---------------------
-        //In case you will find how to work with items counting 
-        let count = match count{
-            Some(count) => count,
-            None => 10,
-        };
-        //---------------------
-        
-        let page = match page{
-            Some(page) => page,
-            None => 0,
-        };
+    pub fn sort(mut self, sort: Sort, direction: SortDirection) -> Self {
+        self.sort = Some((sort, direction));
+        self
+    }
 
-        let query = match query{
-            Some(query) => query.to_string(),
-            None => "".to_string(),
-        };
-        
-        let sort = match sort{
-            Some(sort) => {
-                match sort {
-                    Sort::Name => "name".to_string(),
-                    Sort::Popular => "popular".to_string(),
-                    Sort::Quantity => "quantity".to_string(),
-                    Sort::Price => "price".to_string(),
-                }
-            }
-            None => "".to_string(),
-        };
+    pub fn price_min(mut self, cents: u32) -> Self {
+        self.price_min = Some(cents);
+        self
+    }
 
-        let sort_dir = match sort_dir {
-            Some(dir) => {
-                match dir {
-                    SortDirection::Asc => "asc".to_string(),
-                    SortDirection::Desc => "desc".to_string(),
+    pub fn price_max(mut self, cents: u32) -> Self {
+        self.price_max = Some(cents);
+        self
+    }
+
+    pub fn price_range(self, min_cents: u32, max_cents: u32) -> Self {
+        self.price_min(min_cents).price_max(max_cents)
+    }
+
+    pub fn search_descriptions(mut self, search_descriptions: bool) -> Self {
+        self.search_descriptions = search_descriptions;
+        self
+    }
+
+    pub fn start(mut self, start: u32) -> Self {
+        self.start = start;
+        self
+    }
+
+    pub fn count(mut self, count: u32) -> Self {
+        self.count = count.clamp(1, Self::MAX_COUNT);
+        self
+    }
+
+    /// Tag filter as shown in the market sidebar, e.g. `tag("Exterior", "tag_WearCategory0")`
+    /// becomes `category_730_Exterior[]=tag_WearCategory0`. Repeat for several tags.
+    pub fn tag(mut self, category: impl Into<String>, tag: impl Into<String>) -> Self {
+        self.tags.push((category.into(), tag.into()));
+        self
+    }
+
+    pub fn url(&self) -> String {
+        Self::custom_market_url(self)
+    }
+
+    pub async fn fetch(&self) -> Result<CustomItems> {
+        let response: SteamMarketResponse = Self::process_request(self.url()).await?;
+
+        Ok(CustomItems { vec: response.results })
+    }
+
+    /// Walks every result page from `start`, `count` items per request, and yields
+    /// items one by one until `limit` items, `total_count` or an empty page.
+    /// Requests go through the shared SteamClient, so a long crawl is paced by its limiter.
+    /// A failed page is yielded as the last element, so a cut-short crawl can be told
+    /// from a finished one.
+    pub fn stream(&self, limit: usize) -> impl Stream<Item = Result<Item>> + Send + 'static {
+        stream::unfold(MarketSearchPager::new(self.clone(), limit), |mut pager| async move {
+            let item = pager.next_item().await?;
+            Some((item, pager))
+        })
+    }
+}
+
+struct MarketSearchPager{
+    search: MarketSearch,
+    next_start: u32,
+    total_count: Option<u32>,
+    remaining: usize,
+    buffer: VecDeque<Item>,
+    done: bool,
+}
+
+impl MarketSearchPager{
+    fn new(search: MarketSearch, limit: usize) -> Self {
+        MarketSearchPager {
+            next_start: search.start,
+            search,
+            total_count: None,
+            remaining: limit,
+            buffer: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    async fn next_item(&mut self) -> Option<Result<Item>> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        if self.buffer.is_empty() && !self.done {
+            let url = self.next_page_url()?;
+
+            match MarketSearch::process_request::<SteamMarketResponse>(url).await {
+                Ok(page) => self.take_page(page),
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
                 }
             }
-            None => "".to_string(),
-        };        
+        }
 
-        let search_descriptions = match search_descriptions {
-            Some(bool) => bool,
-            None => false,
-        };
+        let item = self.buffer.pop_front()?;
+        self.remaining -= 1;
+        Some(Ok(item))
+    }
 
-        let price_min = match price_min{
-            Some(price) => price * 100,
-            None => 0,
-        };
+    /// URL of the page at `next_start`, None once `total_count` is reached.
+    fn next_page_url(&mut self) -> Option<String> {
+        if self.total_count.is_some_and(|total| self.next_start >= total) {
+            self.done = true;
+            return None;
+        }
 
-        let price_max = match price_max{
-            Some(price) => price * 100,
-            None => 1e12 as u32,
-        };
+        Some(self.search.clone().start(self.next_start).url())
+    }
 
-        let request = MarketRequest{
-            game,
-            page,
-            count,
-            query,
-            sort,
-            sort_dir,
-            search_descriptions,
-            price_min,
-            price_max,
-        };
+    fn take_page(&mut self, page: SteamMarketResponse) {
+        //pagesize echoes what we asked for, so advance by what actually came back
+        let received = page.results.len() as u32;
 
-        let url = Self::custom_market_url(request);
+        self.total_count = Some(page.total_count);
+        self.next_start = page.start + received;
+        self.done = received == 0 || self.next_start >= page.total_count;
+        self.buffer.extend(page.results);
+    }
+}
 
-        let response_result = Self::process_request(url).await?;
+impl PriceOverviewRequest{
+    pub fn new(appid: u32, market_hash_name: impl Into<String>, currency: u32) -> Self {
+        PriceOverviewRequest {
+            appid,
+            market_hash_name: market_hash_name.into(),
+            currency,
+        }
+    }
 
-        Ok(response_result)
+    pub async fn fetch(&self) -> Result<PriceOverview> {
+        let url = Self::price_overview_url(self);
+        let response: SteamPriceOverviewResponse = Self::process_request(url).await?;
+
+        Ok(PriceOverview {
+            appid: self.appid,
+            market_hash_name: self.market_hash_name.clone(),
+            currency: self.currency,
+            lowest_price: response.lowest_price.as_deref().and_then(parse_price_text),
+            median_price: response.median_price.as_deref().and_then(parse_price_text),
+            volume: response.volume.as_deref().and_then(parse_count_text),
+            lowest_price_text: response.lowest_price,
+            median_price_text: response.median_price,
+            fetched_at: chrono::Utc::now().timestamp(),
+        })
+    }
+}
+
+impl OrderBookRequest{
+    pub fn new(appid: u32, market_hash_name: impl Into<String>, currency: u32) -> Self {
+        OrderBookRequest {
+            appid,
+            market_hash_name: market_hash_name.into(),
+            currency,
+        }
+    }
+
+    /// Scrapes item_nameid from the listing page, where Steam passes it to
+    /// `Market_LoadOrderSpread( 176000000 )`. It never changes, so cache it.
+    pub async fn resolve_item_nameid(&self) -> Result<u64> {
+        let client = SteamClient::shared();
+        let html = client
+            .send_text(client.get(&Self::listing_page_url(self)))
+            .await?;
+
+        //No order spread on the page means Steam has no market listing for this name
+        parse_item_nameid(&html).ok_or(SteamError::SteamReportedFailure)
+    }
+
+    pub async fn fetch(&self, item_nameid: u64) -> Result<OrderBook> {
+        let url = Self::order_histogram_url(self, item_nameid);
+        let response: SteamOrderHistogramResponse = Self::process_request(url).await?;
+
+        Ok(self.order_book(item_nameid, response))
+    }
+
+    fn order_book(&self, item_nameid: u64, response: SteamOrderHistogramResponse) -> OrderBook {
+        OrderBook {
+            appid: self.appid,
+            market_hash_name: self.market_hash_name.clone(),
+            currency: self.currency,
+            item_nameid,
+            highest_buy_order: response.highest_buy_order.and_then(|p| p.parse().ok()),
+            lowest_sell_order: response.lowest_sell_order.and_then(|p| p.parse().ok()),
+            buy_orders: order_levels(&response.buy_order_graph),
+            sell_orders: order_levels(&response.sell_order_graph),
+            fetched_at: chrono::Utc::now().timestamp(),
+        }
+    }
+}
+
+fn parse_item_nameid(html: &str) -> Option<u64> {
+    let re = regex::Regex::new(r#"Market_LoadOrderSpread\(\s*(\d+)\s*\)"#)
+        .expect("item_nameid regex is valid");
+
+    re.captures(html).and_then(|caps| caps[1].parse().ok())
+}
+
+/// Steam's graph is cumulative, turn it back into quantity per price.
+fn order_levels(graph: &[(f64, i64, String)]) -> Vec<OrderLevel> {
+    let mut previous = 0;
+
+    graph
+        .iter()
+        .map(|(price, cumulative, _)| {
+            let level = OrderLevel {
+                price: (price * 100.0).round() as i64,
+                quantity: cumulative - previous,
+            };
+            previous = *cumulative;
+            level
+        })
+        .collect()
+}
+
+/// "$1,234.56", "1.234,56€", "12,34 pуб." -> cents.
+/// The last '.' or ',' followed by one or two digits is the decimal separator,
+/// any other separator is a thousands separator.
+pub fn parse_price_text(text: &str) -> Option<i64> {
+    let number: String = text
+        .chars()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(|c| c.is_ascii_digit() || *c == '.' || *c == ',' || c.is_whitespace())
+        .filter(|c| !c.is_whitespace())
+        .collect();
+
+    let number = number.trim_end_matches(['.', ',']);
+
+    if number.is_empty() {
+        return None;
     }
+
+    let (whole, fraction) = match number.rfind(['.', ',']) {
+        Some(idx) if number.len() - idx - 1 <= 2 => (&number[..idx], &number[idx + 1..]),
+        _ => (number, ""),
+    };
+
+    let whole: String = whole.chars().filter(|c| c.is_ascii_digit()).collect();
+    let whole: i64 = if whole.is_empty() { 0 } else { whole.parse().ok()? };
+    let fraction: i64 = match fraction.len() {
+        0 => 0,
+        1 => fraction.parse::<i64>().ok()? * 10,
+        _ => fraction.parse().ok()?,
+    };
+
+    Some(whole * 100 + fraction)
+}
+
+/// "12,345" -> 12345
+fn parse_count_text(text: &str) -> Option<i64> {
+    let digits: String = text.chars().filter(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
 }
 
 impl MostRecentItemsRequest {
@@ -916,3 +1425,162 @@ impl MostRecentItemsRequest {
 //     };
 //     Ok(out)
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search_page(start: u32, total_count: u32, received: u32) -> SteamMarketResponse {
+        let results: Vec<serde_json::Value> = (start..start + received)
+            .map(|n| serde_json::json!({
+                "name": format!("Item {n}"),
+                "hash_name": format!("Item {n}"),
+                "sell_listings": 1,
+                "sell_price": 100,
+                "sell_price_text": "$1.00",
+                "app_icon": "",
+                "app_name": "Counter-Strike 2",
+                "asset_description": {
+                    "appid": 730, "classid": n.to_string(), "instanceid": "0", "background_color": null,
+                    "icon_url": "", "tradable": 1, "name": format!("Item {n}"), "name_color": null,
+                    "type": "", "market_name": format!("Item {n}"), "market_hash_name": format!("Item {n}"),
+                    "commodity": 0
+                },
+                "sale_price_text": "$0.98"
+            }))
+            .collect();
+
+        serde_json::from_value(serde_json::json!({
+            "success": true,
+            "start": start,
+            "pagesize": 100,
+            "total_count": total_count,
+            "searchdata": {
+                "query": "", "search_descriptions": false, "total_count": total_count, "pagesize": 100,
+                "prefix": "searchResults", "class_prefix": "market"
+            },
+            "results": results
+        }))
+        .unwrap()
+    }
+
+    fn start_param(url: &str) -> String {
+        reqwest::Url::parse(url).unwrap().query_pairs().find(|(key, _)| key == "start").unwrap().1.into_owned()
+    }
+
+    #[test]
+    fn pager_advances_by_what_came_back() {
+        let mut pager = MarketSearchPager::new(MarketSearch::new(730).start(10).count(100), usize::MAX);
+        assert_eq!(start_param(&pager.next_page_url().unwrap()), "10");
+
+        //Asked for 100, Steam sent 40
+        pager.take_page(search_page(10, 95, 40));
+        assert_eq!((pager.next_start, pager.total_count, pager.done), (50, Some(95), false));
+        assert_eq!(start_param(&pager.next_page_url().unwrap()), "50");
+
+        pager.take_page(search_page(50, 95, 45));
+        assert_eq!((pager.next_start, pager.done), (95, true));
+        assert_eq!(pager.next_page_url(), None);
+        assert_eq!(pager.buffer.len(), 85);
+    }
+
+    #[test]
+    fn pager_stops_on_an_empty_page() {
+        let mut pager = MarketSearchPager::new(MarketSearch::new(730), usize::MAX);
+
+        //total_count shrank while crawling
+        pager.take_page(search_page(0, 500, 0));
+
+        assert!(pager.done);
+        assert!(pager.buffer.is_empty());
+    }
+
+    #[tokio::test]
+    async fn pager_stops_at_the_limit() {
+        let mut pager = MarketSearchPager::new(MarketSearch::new(730), 3);
+        pager.take_page(search_page(0, 1000, 10));
+
+        let mut names = Vec::new();
+        while let Some(item) = pager.next_item().await {
+            names.push(item.unwrap().name);
+        }
+
+        assert_eq!(names, vec!["Item 0", "Item 1", "Item 2"]);
+        assert_eq!(pager.buffer.len(), 7);
+    }
+
+    #[test]
+    fn item_nameid_comes_from_the_order_spread_call() {
+        //Trimmed from a listing page's inline script
+        let html = r#"
+            <script type="text/javascript">
+                var g_rgAssets = {"730":{"2":{}}};
+                $J(function() {
+                    var bShowBuyOrders = true;
+                    ItemActivityTicker.Start( 176096390 );
+                    Market_LoadOrderSpread( 176096390 );	// initial load
+                    PollOnUserActionAfterInterval( 'ItemActivityTicker', 10000 );
+                });
+            </script>"#;
+
+        assert_eq!(parse_item_nameid(html), Some(176096390));
+        assert_eq!(parse_item_nameid("Market_LoadOrderSpread(42)"), Some(42));
+        assert_eq!(parse_item_nameid("<div>There are no listings for this item.</div>"), None);
+        assert_eq!(parse_item_nameid("Market_LoadOrderSpread( item_nameid )"), None);
+    }
+
+    #[test]
+    fn histogram_ladders_are_per_price() {
+        //Trimmed itemordershistogram answer, graphs are cumulative from the best price
+        let response: SteamOrderHistogramResponse = serde_json::from_value(serde_json::json!({
+            "success": 1,
+            "sell_order_table": "<table></table>",
+            "sell_order_summary": "<span>512</span> for sale starting at <span>$2.31</span>",
+            "buy_order_table": "<table></table>",
+            "buy_order_summary": "<span>2961</span> requests to buy at <span>$2.12</span> or lower",
+            "highest_buy_order": "212",
+            "lowest_sell_order": "231",
+            "buy_order_graph": [
+                [2.12, 15, "15 buy orders at $2.12 or higher"],
+                [2.11, 40, "40 buy orders at $2.11 or higher"],
+                [2.1, 41, "41 buy orders at $2.10 or higher"]
+            ],
+            "sell_order_graph": [
+                [2.31, 3, "3 sell orders at $2.31 or lower"],
+                [2.32, 10, "10 sell orders at $2.32 or lower"]
+            ],
+            "graph_max_y": 100,
+            "graph_min_x": 1.9,
+            "graph_max_x": 2.5,
+            "price_prefix": "$",
+            "price_suffix": ""
+        }))
+        .unwrap();
+
+        let book = OrderBookRequest::new(730, "Revolution Case", 1).order_book(176096390, response);
+
+        assert_eq!((book.highest_buy_order, book.lowest_sell_order), (Some(212), Some(231)));
+        assert_eq!(book.buy_orders, vec![
+            OrderLevel { price: 212, quantity: 15 },
+            OrderLevel { price: 211, quantity: 25 },
+            OrderLevel { price: 210, quantity: 1 },
+        ]);
+        assert_eq!(book.sell_orders, vec![
+            OrderLevel { price: 231, quantity: 3 },
+            OrderLevel { price: 232, quantity: 7 },
+        ]);
+        assert_eq!((book.item_nameid, book.currency), (176096390, 1));
+    }
+
+    #[test]
+    fn empty_histogram_has_no_ladders() {
+        let response: SteamOrderHistogramResponse =
+            serde_json::from_value(serde_json::json!({ "success": 1, "highest_buy_order": null, "lowest_sell_order": null }))
+                .unwrap();
+
+        let book = OrderBookRequest::new(730, "Revolution Case", 1).order_book(1, response);
+
+        assert_eq!((book.highest_buy_order, book.lowest_sell_order), (None, None));
+        assert!(book.buy_orders.is_empty() && book.sell_orders.is_empty());
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 use actix::Actor;
 use actix_web::{App, HttpServer, web, cookie::Key};
@@ -9,6 +9,9 @@ use tokio::sync::{mpsc, Mutex, broadcast};
 
 use steam_market_parser::{
     ChatSessionPlayload, 
+    FeedPollConfig,
+    FeedPollStats,
+    Game,
     Inventory, 
     MostRecent, 
     MostRecentItems, 
@@ -19,14 +22,16 @@ use steam_market_parser::{
 };
 
 mod db;
-use db::DataBase;
+use db::{DataBase, DbConfig, DbPool, DealScoring, FeedRepo, FeedRetention};
 
 mod websocket;
 use websocket::{
     ws_handler, 
     ws_ad_handler, 
+    ws_alerts_handler,
     BroadcastPayload, 
-    AdsBroadcastPayload
+    AdsBroadcastPayload,
+    AlertsBroadcastPayload
 };
 
 mod steam_login;
@@ -38,6 +43,7 @@ use steam_login::{
 mod routes;
 use routes::{
     tera_update_data, 
+    tera_decimal_price,
     store_rating,
     steam_logout, 
     load_inventory, 
@@ -52,19 +58,38 @@ use routes::{
     offer_update_status_offer,
     offer_check_offer_to_pay,
     account_post_trade_url,
+    account_post_currency,
+    account_get_currency,
     offer_get_draft,
     api_me,
-    account_reset_trade_url
+    account_reset_trade_url,
+    get_price_overview,
+    get_catalog_item,
+    get_price_history,
+    get_order_book,
+    get_feed_stats,
+    get_feed,
+    get_search,
+    get_saved_searches,
+    post_saved_search,
+    delete_saved_search,
+    get_notifications,
+    post_notifications_read,
+    get_ws_connections
 };
 
 mod background_tasks;
 use background_tasks::{
     tokio_user_ad_loop,
     tokio_receiver_most_recent_items_request,
-    tokio_db_update_game_list,
-    tokio_db_check_transaction_availability
+    tokio_db_check_transaction_availability,
+    tokio_price_history_rollup,
+    tokio_feed_retention
 };
 
+mod ws_connections;
+use ws_connections::{WsConfig, WsConnections};
+
 mod store_chat_websocket;
 use store_chat_websocket::{
     ws_chat_handler,
@@ -88,12 +113,14 @@ struct AppState {
 struct FeedItemsState{
     items: Mutex<Vec<MostRecent>>,
     broadcaster: broadcast::Sender<BroadcastPayload>,
+    poll_stats: Arc<Mutex<FeedPollStats>>,
 }
 
 struct UserAdState{
     user_ads: Mutex<UserAdsQueue>,
     ads_broadcaster: broadcast::Sender<AdsBroadcastPayload>,
 }
+#[allow(dead_code)]
 struct UserInventoryState{
     inventory: Mutex<Inventory>,
 }
@@ -102,10 +129,17 @@ struct StoreHashMapState{
     store_hashmap_state: StoreQueueHashmap,
 }
 
+/// Saved search matches of every user, each /ws/alerts connection keeps its own.
+struct AlertState{
+    broadcaster: broadcast::Sender<AlertsBroadcastPayload>,
+}
+
+/// Games seen in app_data with their live listing counts, refreshed after every poll.
 struct GameListState{
-    game_list: Mutex<Vec<String>>,
+    game_list: Mutex<Vec<Game>>,
 }
 
+#[allow(dead_code)]
 struct StoreWebsocketListState{
     websocket_list: Mutex<HashMap<String, ChatSessionPlayload>>,
 }
@@ -115,23 +149,34 @@ async fn main()-> std::io::Result<()> {
 
     dotenv::dotenv().ok();
 
+    //`--migrate-dry-run` lists pending schema migrations and exits
+    if std::env::args().any(|arg| arg == "--migrate-dry-run") {
+        return DataBase::migrate_dry_run(&DbConfig::from_env()).map_err(std::io::Error::other);
+    }
+
     let secret_key = std::env::var("SESSION_SECRET_KEY")
         .expect("SESSION_SECRET_KEY must be set in .env file");
     
     let key = Key::from(secret_key.as_bytes());
 
-    let db = DataBase::connect_to_db();
+    let db_pool = DbPool::open(DbConfig::from_env()).map_err(std::io::Error::other)?;
+    println!("Database: {} (max {} connections)", db_pool.config().path.display(), db_pool.config().max_connections);
 
-    let country = Some("US".to_string());
-    let language = Some("english".to_string());
-    let currency = Some("3".to_string());
+    //Currency every feed price is converted to. Users pick their own display currency on top
+    let country = Some(std::env::var("FEED_COUNTRY").unwrap_or_else(|_| "US".to_string()));
+    let language = Some(std::env::var("FEED_LANGUAGE").unwrap_or_else(|_| "english".to_string()));
+    let currency = Some(std::env::var("FEED_CURRENCY").unwrap_or_else(|_| "3".to_string()));
 
     let (request_sender, response_receiver) = mpsc::channel(100);
     let (broadcast_sender_most_recent_items, _broadcast_reciever_most_recent_items) = broadcast::channel(32);
     let (broadcast_sender_user_ad, _broadcast_reciever_user_ad) = broadcast::channel(10);
+    let (broadcast_sender_alerts, _broadcast_reciever_alerts) = broadcast::channel(32);
 
     let empty_store_hashmap: StoreQueueHashmap = StoreQueueHashmap::new();
-    let filled_store_hashmap = db.db_fill_store_hashmap(empty_store_hashmap).unwrap();
+    let filled_store_hashmap = db_pool
+        .run(move |db| db.db_fill_store_hashmap(empty_store_hashmap))
+        .await
+        .map_err(std::io::Error::other)?;
 
     let chat_hub = ChatHub::new().start();
     let chat_hub = web::Data::new(chat_hub);
@@ -153,19 +198,25 @@ async fn main()-> std::io::Result<()> {
         }),
     });
 
-    let state = web::Data::new(AppState {
-        tera: Tera::new("front/**/*").expect("Tera init failed"),
-    });
+    let mut tera = Tera::new("front/**/*").expect("Tera init failed");
+    tera.register_filter("decimal_price", tera_decimal_price);
+    let state = web::Data::new(AppState { tera });
+
+    let feed_poll_stats = Arc::new(Mutex::new(FeedPollStats::default()));
 
     let feed_state = web::Data::new(FeedItemsState{
         items: Mutex::new(Vec::new()),
         broadcaster: broadcast_sender_most_recent_items,
+        poll_stats: feed_poll_stats.clone(),
     });
 
     let user_ad_state = web::Data::new(UserAdState{
         ads_broadcaster: broadcast_sender_user_ad,
         user_ads: Mutex::new(UserAdsQueue { 
-            queue: db.db_get_ad_steam_user(),
+            queue: db_pool
+                .run(|db| db.db_get_ad_steam_user())
+                .await
+                .map_err(std::io::Error::other)?,
         })
     });
 
@@ -174,30 +225,58 @@ async fn main()-> std::io::Result<()> {
     });
 
     let game_list_state = web::Data::new(GameListState{
-        game_list: Mutex::new(Vec::new()),
+        game_list: Mutex::new(
+            db_pool
+                .run(|db| db.db_get_games())
+                .await
+                .map_err(std::io::Error::other)?
+        ),
+    });
+
+    let alert_state = web::Data::new(AlertState{
+        broadcaster: broadcast_sender_alerts,
     });
 
+    let ws_config = WsConfig::from_env();
+    println!("Websockets: {ws_config:?}");
+    let ws_connections = web::Data::from(WsConnections::new(ws_config));
+
     let user_ad_state_for_ads = user_ad_state.clone();
-    let game_list_state_for_background = game_list_state.clone();
+    let alert_state_for_feed = alert_state.clone();
+    let game_list_state_for_feed = game_list_state.clone();
     let feed_state_for_ws = feed_state.clone();
+    let db_pool_for_transactions = db_pool.clone();
+    let db_pool_for_feed = db_pool.clone();
+    let db_pool_for_rollup = db_pool.clone();
+    let db_pool_for_retention = db_pool.clone();
+    let db_pool = web::Data::new(db_pool);
 
     tokio::spawn(async move {
-        tokio_db_check_transaction_availability().await;
+        tokio_db_check_transaction_availability(db_pool_for_transactions).await;
     });
 
     tokio::spawn(async move {
-        tokio_db_update_game_list(game_list_state_for_background).await;
+        tokio_receiver_most_recent_items_request(
+            response_receiver, db_pool_for_feed, feed_state_for_ws, game_list_state_for_feed, alert_state_for_feed,
+            DealScoring::from_env(),
+        ).await;
     });
 
     tokio::spawn(async move {
-        tokio_receiver_most_recent_items_request(response_receiver, db, feed_state_for_ws).await;
+        tokio_user_ad_loop(user_ad_state_for_ads).await; 
     });
 
     tokio::spawn(async move {
-        tokio_user_ad_loop(user_ad_state_for_ads).await; 
+        tokio_price_history_rollup(db_pool_for_rollup).await;
     });
 
-    let _ = MostRecentItems::get_most_recent_items(country, language, currency, request_sender).await;
+    tokio::spawn(async move {
+        tokio_feed_retention(db_pool_for_retention, FeedRetention::from_env()).await;
+    });
+
+    let _ = MostRecentItems::get_most_recent_items(
+        country, language, currency, FeedPollConfig::from_env(), feed_poll_stats, request_sender
+    ).await;
 
     println!("http://127.0.0.1:8080");
     
@@ -206,13 +285,16 @@ async fn main()-> std::io::Result<()> {
         App::new()
             .wrap(SessionMiddleware::new(CookieSessionStore::default(), key.clone()))
             .app_data(state.clone())
+            .app_data(db_pool.clone())
             .app_data(user_inventory.clone())
             .app_data(user_ad_state.clone())
             .app_data(feed_state.clone())
+            .app_data(alert_state.clone())
             .app_data(game_list_state.clone())
             .app_data(store_hashmap.clone())
             .app_data(chat_hub.clone())
             .app_data(websocket_list_state.clone())
+            .app_data(ws_connections.clone())
             .service(Files::new("/front", "./front"))
             .route("/", web::get().to(tera_update_data))
             .service(web::scope("/api")
@@ -227,8 +309,25 @@ async fn main()-> std::io::Result<()> {
                 .route("/remove_from_store_queue", web::post().to(remove_from_store_queue))
                 .route("/auth/steam", web::get().to(steam_login))
                 .route("/auth/steam/return", web::get().to(steam_return))
+                .route("/catalog/{appid}/{classid}/{instanceid}", web::get().to(get_catalog_item))
+                .route("/price/{appid}/{market_hash_name}", web::get().to(get_price_overview))
+                .route("/price_history/{market_hash_name}", web::get().to(get_price_history))
+                .route("/order_book/{appid}/{market_hash_name}", web::get().to(get_order_book))
+                .route("/feed", web::get().to(get_feed))
+                .route("/feed/stats", web::get().to(get_feed_stats))
+                .route("/search", web::get().to(get_search))
+                .service(web::scope("/saved_searches")
+                    .route("", web::get().to(get_saved_searches))
+                    .route("", web::post().to(post_saved_search))
+                    .route("/{id}", web::delete().to(delete_saved_search))
+                )
+                .route("/notifications", web::get().to(get_notifications))
+                .route("/notifications/read", web::post().to(post_notifications_read))
+                .route("/admin/ws_connections", web::get().to(get_ws_connections))
                 .service(web::scope("/account")
                     .route("/post_trade_url", web::post().to(account_post_trade_url))
+                    .route("/post_currency", web::post().to(account_post_currency))
+                    .route("/currency", web::get().to(account_get_currency))
                     .route("/post_reset_url", web::post().to(account_reset_trade_url))
                 )
                 .service(web::scope("/offer")
@@ -253,6 +352,7 @@ async fn main()-> std::io::Result<()> {
             .route("", web::get().to(ws_handler)) 
             .route("/ads", web::get().to(ws_ad_handler)) 
             .route("/chat", web::get().to(ws_chat_handler)) 
+            .route("/alerts", web::get().to(ws_alerts_handler))
         )
             .service(web::scope("/web")
             .route("/store_rating", web::get().to(store_rating))
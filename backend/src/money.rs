@@ -0,0 +1,126 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+use crate::{currency_code, parse_price_text, CurrencyRates};
+
+/// Offers, checkout and payouts all run in USD for now.
+pub const OFFER_CURRENCY: u32 = 1;
+
+/// An amount in the smallest unit of a Steam currency (ECurrencyCode).
+/// Sums stay exact, unlike the f64 dollars we used to keep in TEXT columns.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Money {
+    pub cents: i64,
+    pub currency: u32,
+}
+
+impl Money {
+    pub fn new(cents: i64, currency: u32) -> Self {
+        Money { cents, currency }
+    }
+
+    pub fn zero(currency: u32) -> Self {
+        Money::new(0, currency)
+    }
+
+    /// "12", "12.5", "$12.50", "1.234,56€" -> cents. None for "?" and other junk.
+    pub fn parse(text: &str, currency: u32) -> Option<Self> {
+        parse_price_text(text).map(|cents| Money::new(cents, currency))
+    }
+
+    /// None when the currencies differ, we never add EUR to USD silently.
+    pub fn checked_add(self, other: Money) -> Option<Money> {
+        if self.currency != other.currency {
+            return None;
+        }
+        Some(Money::new(self.cents.checked_add(other.cents)?, self.currency))
+    }
+
+    pub fn convert(self, to: u32, rates: &CurrencyRates) -> Option<Money> {
+        rates.convert(self.cents, self.currency, to).map(|cents| Money::new(cents, to))
+    }
+
+    /// "12.50", no symbol.
+    pub fn to_decimal_string(&self) -> String {
+        let sign = if self.cents < 0 { "-" } else { "" };
+        let abs = self.cents.unsigned_abs();
+        format!("{sign}{}.{:02}", abs / 100, abs % 100)
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match currency_code(self.currency) {
+            Some(code) => write!(f, "{} {code}", self.to_decimal_string()),
+            None => write!(f, "{}", self.to_decimal_string()),
+        }
+    }
+}
+
+/// Offer prices go over the wire as decimal strings ("12.50") because that's what
+/// the store UI types and prints. Use with `#[serde(with = "offer_price")]`.
+pub mod offer_price {
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    use super::{Money, OFFER_CURRENCY};
+
+    pub fn serialize<S: Serializer>(money: &Money, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&money.to_decimal_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Money, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Text(String),
+            Number(f64),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Text(text) => Money::parse(&text, OFFER_CURRENCY)
+                .ok_or_else(|| D::Error::custom(format!("invalid price: {text:?}"))),
+            Raw::Number(n) => Ok(Money::new((n * 100.0).round() as i64, OFFER_CURRENCY)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sums_are_exact() {
+        let total = ["0.10", "0.20", "$12.5"]
+            .iter()
+            .map(|text| Money::parse(text, OFFER_CURRENCY).unwrap())
+            .try_fold(Money::zero(OFFER_CURRENCY), Money::checked_add)
+            .unwrap();
+
+        assert_eq!(total, Money::new(1280, OFFER_CURRENCY));
+        assert_eq!(total.to_decimal_string(), "12.80");
+        assert_eq!(total.to_string(), "12.80 USD");
+        assert_eq!(Money::new(-5, 3).to_decimal_string(), "-0.05");
+    }
+
+    #[test]
+    fn mixed_currencies_do_not_add() {
+        assert_eq!(Money::new(100, 1).checked_add(Money::new(100, 3)), None);
+        assert_eq!(Money::parse("?", 1), None);
+    }
+
+    #[test]
+    fn offer_price_round_trips_as_text() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Item {
+            #[serde(with = "offer_price")]
+            price: Money,
+        }
+
+        let item: Item = serde_json::from_str(r#"{"price":"12.5"}"#).unwrap();
+        assert_eq!(item.price, Money::new(1250, OFFER_CURRENCY));
+        assert_eq!(serde_json::to_string(&item).unwrap(), r#"{"price":"12.50"}"#);
+
+        let item: Item = serde_json::from_str(r#"{"price":3.3}"#).unwrap();
+        assert_eq!(item.price.cents, 330);
+    }
+}
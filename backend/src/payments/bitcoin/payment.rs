@@ -100,7 +100,7 @@ pub async fn btcpay_webhook(_http_req: HttpRequest, payload: web::Json<WebhookEv
     // (exact header names depend on BTCPay webhook settings)
 
     // Example: update your offer status when settled/complete-ish event arrives:
-    let event = &payload.event_type;
+    let _event = &payload.event_type;
 
     // Common events include ones like "InvoiceCreated", "InvoiceReceivedPayment", "InvoiceSettled"
     // Exact strings can vary by BTCPay version/config, so log first.
@@ -1,12 +1,8 @@
+//BTCPay routes are not mounted yet
+#[allow(dead_code)]
 pub mod bitcoin;
 pub mod stripe;
 
-pub use bitcoin::payment::{
-    btc_create_invoice,
-    btc_invoice_status,
-    btcpay_webhook
-};
-
 pub use stripe::payment::{
     stripe_create_checkout,
     stripe_webhook,
@@ -4,7 +4,7 @@ use actix_web::{HttpResponse, HttpRequest, web};
 use actix_session::{Session};
 use actix::Addr;
 
-use crate::db::DataBase;
+use crate::db::{DbError, DbPool, OfferRepo, UserRepo, WalletRepo};
 use crate::AppState;
 
 use crate::store_chat_websocket::{
@@ -14,7 +14,9 @@ use crate::store_chat_websocket::{
 };
 
 use steam_market_parser::{
-    SteamUser
+    Money,
+    SteamUser,
+    OFFER_CURRENCY
 };
 
 const STRIPE_FEE: f64 = 1.03;
@@ -27,6 +29,7 @@ pub struct CreateCheckoutReq {
 
 pub async fn stripe_create_checkout(
     req: web::Json<CreateCheckoutReq>,
+    db_pool: web::Data<DbPool>,
 ) -> actix_web::Result<HttpResponse> {
     let stripe_key = std::env::var("STRIPE_SECRET_KEY")
         .map_err(actix_web::error::ErrorInternalServerError)?;
@@ -35,14 +38,20 @@ pub async fn stripe_create_checkout(
 
     let client = stripe::Client::new(stripe_key);
 
-    let db = DataBase::connect_to_db();
     let offer_id = req.offer_id.clone();
-    let price: f64 = db.db_offer_get_offer_price(offer_id.clone());
-    let price_with_fee = (price * STRIPE_FEE * TASTYROCK_FEE + 30.0) as i64;
+    let lookup_id = offer_id.clone();
+    let (price, buyer_steamid, trader_steamid): (Money, String, String) = db_pool
+        .run(move |db| Ok::<_, DbError>((
+            db.db_offer_get_offer_price(lookup_id.clone())?,
+            db.db_get_buyer_steamid_by_offer(&lookup_id)?,
+            db.db_get_trader_steamid_by_offer(&lookup_id)?,
+        )))
+        .await?;
+    let price_with_fee = (price.cents as f64 * STRIPE_FEE * TASTYROCK_FEE + 30.0) as i64;
 
     // 1) Create Customer (with name)
 
-    let stripe_customer_id = get_or_create_stripe_customer(&client, &db, &offer_id).await?;
+    let stripe_customer_id = get_or_create_stripe_customer(&client, &db_pool, buyer_steamid.clone()).await?;
 
     // 2) Create Checkout Session with that customer
     let mut params = stripe::CreateCheckoutSession::new();
@@ -72,14 +81,11 @@ pub async fn stripe_create_checkout(
         }
     ]);
 
-    let buyer_steamid = db.db_get_buyer_steamid_by_offer(&offer_id);
-    let trader_steamid = db.db_get_trader_steamid_by_offer(&offer_id);
-
     let mut md = std::collections::HashMap::new();
     md.insert("Offer".to_string(), offer_id.clone());
     md.insert("BuyerSteamID".to_string(), buyer_steamid);
     md.insert("TraderSteamID".to_string(), trader_steamid);
-    md.insert("AmountCents".to_string(), price.to_string());
+    md.insert("AmountCents".to_string(), price.cents.to_string());
     md.insert("AmountCentsWithFee".to_string(), price_with_fee.to_string());
     params.metadata = Some(md);
 
@@ -106,16 +112,14 @@ pub async fn stripe_create_checkout(
 
 async fn get_or_create_stripe_customer(
     client: &stripe::Client,
-    db: &DataBase,
-    offer_id: &String,
+    db_pool: &DbPool,
+    steam_id: String,
 ) -> actix_web::Result<stripe::CustomerId> {
-    // Get steam_id from offer
-    let steam_id = db.db_get_buyer_steamid_by_offer(offer_id); // you return steamid here
-
     // 1) If we already stored Stripe customer id, reuse it
-    if let Some(existing) = db
-        .db_get_stripe_customer_id(&steam_id)
-        .map_err(actix_web::error::ErrorInternalServerError)?
+    let lookup_id = steam_id.clone();
+    if let Some(existing) = db_pool
+        .run(move |db| db.db_get_stripe_customer_id(&lookup_id))
+        .await?
     {
         let customer_id = existing
             .parse()
@@ -124,9 +128,10 @@ async fn get_or_create_stripe_customer(
     }
 
     // 2) Otherwise create a new Stripe customer
-    let db_customer_params = db
-        .db_get_user_params(steam_id.clone())
-        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let lookup_id = steam_id.clone();
+    let db_customer_params = db_pool
+        .run(move |db| db.db_get_user_params(lookup_id))
+        .await?;
 
     let mut customer_params = stripe::CreateCustomer::new();
 
@@ -143,13 +148,15 @@ async fn get_or_create_stripe_customer(
         .map_err(actix_web::error::ErrorBadGateway)?;
 
     // 3) Save it to DB so next time you don’t create duplicates
-    db.db_insert_stripe_customer_id(&steam_id, customer.id.as_str())
-        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let customer_id = customer.id.to_string();
+    db_pool
+        .run(move |db| db.db_insert_stripe_customer_id(&steam_id, &customer_id))
+        .await?;
 
     Ok(customer.id)
 }
 
-pub async fn stripe_webhook(hub: web::Data<Addr<ChatHub>>, req: HttpRequest, body: web::Bytes) -> HttpResponse {
+pub async fn stripe_webhook(hub: web::Data<Addr<ChatHub>>, db_pool: web::Data<DbPool>, req: HttpRequest, body: web::Bytes) -> HttpResponse {
     let webhook_secret = match std::env::var("STRIPE_WEBHOOK_SECRET") {
         Ok(v) => v,
         Err(_) => return HttpResponse::InternalServerError().finish(),
@@ -203,48 +210,69 @@ pub async fn stripe_webhook(hub: web::Data<Addr<ChatHub>>, req: HttpRequest, bod
                         .and_then(|s| s.parse::<i64>().ok())
                         .unwrap_or_else(|| session.amount_total.unwrap_or(0));            
 
-                    let db = DataBase::connect_to_db();
-                    // insert buyer transaction
-                    if let Err(e) = db.db_insert_buyer_transaction(
+                    let (buyer, trader, offer, event_id) = (
                         buyer_steamid.clone(),
+                        trader_steamid.clone(),
                         offer_id.clone(),
-                        price.to_string(),
-                        price_with_fee.to_string(),
-                        "STRIPE".to_string(),
-                        "checkout".to_string(),
-                    ) {
-                        eprintln!("db_insert_buyer_transaction failed: {e}");
-                        return HttpResponse::InternalServerError().finish();
-                    }
+                        event.id.to_string(),
+                    );
+
+                    // Ok(false): acknowledged, but nothing new to announce
+                    let recorded = db_pool.run(move |db| {
+                        // insert buyer transaction
+                        if let Err(e) = db.db_insert_buyer_transaction(
+                            buyer,
+                            offer.clone(),
+                            Money::new(price, OFFER_CURRENCY),
+                            Money::new(price_with_fee, OFFER_CURRENCY),
+                            "STRIPE".to_string(),
+                            "checkout".to_string(),
+                        ) {
+                            eprintln!("db_insert_buyer_transaction failed: {e}");
+                            return Err(e);
+                        }
 
-                    // get seller connected acct_...
-                    let seller_acct = match db.db_get_connected_stripe_trader_acct_for_steamid(&trader_steamid) {
-                        Ok(Some(acct)) => acct,
-                        Ok(None) => {
-                            eprintln!("Seller has no connected Stripe account steamid={trader_steamid}");
-                            // still ACK ok so Stripe doesn’t retry forever
-                            return HttpResponse::Ok().finish();
+                        // get seller connected acct_...
+                        let seller_acct = match db.db_get_connected_stripe_trader_acct_for_steamid(&trader) {
+                            Ok(Some(acct)) => acct,
+                            Ok(None) => {
+                                eprintln!("Seller has no connected Stripe account steamid={trader}");
+                                // still ACK ok so Stripe doesn’t retry forever
+                                return Ok(false);
+                            }
+                            Err(e) => {
+                                eprintln!("db_get_connected_stripe_trader_acct_for_steamid failed: {e}");
+                                return Err(e);
+                            }
+                        };
+
+                        if let Err(e) = db.db_insert_stripe_wallet_transaction(
+                            &trader,          // owner steamid
+                            &seller_acct,     // acct_...
+                            &offer,
+                            price,
+                            &event_id,
+                        ) {
+                            //Stripe resent an event we already booked
+                            if let DbError::Constraint(_) = e {
+                                return Ok(false);
+                            }
+                            eprintln!("db_insert_stripe_wallet_locked failed: {e}");
+                            return Err(e);
                         }
+
+                        Ok(true)
+                    }).await;
+
+                    match recorded {
+                        Ok(true) => {}
+                        Ok(false) => return HttpResponse::Ok().finish(),
                         Err(e) => {
-                            eprintln!("db_get_connected_stripe_trader_acct_for_steamid failed: {e}");
+                            eprintln!("Stripe checkout not recorded offer_id={offer_id}: {e}");
                             return HttpResponse::InternalServerError().finish();
                         }
-                    };
-
-                    if let Err(e) = db.db_insert_stripe_wallet_transaction(
-                        &trader_steamid,  // owner steamid
-                        &seller_acct,     // acct_...
-                        &offer_id,
-                        price,
-                        event.id.as_str(),
-                    ) {
-                        if e.sqlite_error_code() == Some(rusqlite::ErrorCode::ConstraintViolation) {
-                            return HttpResponse::Ok().finish();
-                        }
-                        eprintln!("db_insert_stripe_wallet_locked failed: {e}");
-                        return HttpResponse::InternalServerError().finish();
                     }
-                    
+
 
                     println!("PAID offer_id={offer_id} buyer={buyer_steamid} seller={trader_steamid} price={price}");
 
@@ -384,10 +412,11 @@ pub async fn stripe_connect_start() -> actix_web::Result<HttpResponse> {
 #[derive(Deserialize)]
 pub struct ConnectCallbackQuery {
     pub code: String,
+    #[allow(dead_code)]
     pub state: Option<String>, // use for CSRF + steamid binding
 }
 
-pub async fn stripe_connect_callback(q: web::Query<ConnectCallbackQuery>, session: Session,) -> actix_web::Result<HttpResponse> {
+pub async fn stripe_connect_callback(q: web::Query<ConnectCallbackQuery>, session: Session, db_pool: web::Data<DbPool>) -> actix_web::Result<HttpResponse> {
     let secret = std::env::var("STRIPE_SECRET_KEY")
         .map_err(actix_web::error::ErrorInternalServerError)?;
     let client_id = std::env::var("STRIPE_CONNECT_CLIENT_ID")
@@ -443,9 +472,10 @@ pub async fn stripe_connect_callback(q: web::Query<ConnectCallbackQuery>, sessio
 
     let steamid = steam_user.steamid;
 
-    let db = DataBase::connect_to_db();
-    db.db_upsert_user_stripe_id(&steamid, &acct)
-        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let stripe_id = acct.clone();
+    db_pool
+        .run(move |db| db.db_upsert_user_stripe_id(&steamid, &stripe_id))
+        .await?;
 
     Ok(HttpResponse::Ok().body(format!("Stripe connected: {acct}")))
 }
\ No newline at end of file
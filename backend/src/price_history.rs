@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+
+//Unix epoch is a Thursday, weeks start on Monday
+const WEEK_OFFSET_SECS: i64 = 4 * 86400;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CandleInterval {
+    #[default]
+    Hour,
+    Day,
+    Week,
+}
+
+impl CandleInterval {
+    pub fn seconds(&self) -> i64 {
+        match self {
+            CandleInterval::Hour => 3600,
+            CandleInterval::Day => 86400,
+            CandleInterval::Week => 7 * 86400,
+        }
+    }
+
+    /// Start of the bucket `time` falls into, in unix seconds (UTC).
+    pub fn bucket_start(&self, time: i64) -> i64 {
+        match self {
+            CandleInterval::Week => {
+                (time - WEEK_OFFSET_SECS).div_euclid(self.seconds()) * self.seconds() + WEEK_OFFSET_SECS
+            }
+            _ => time.div_euclid(self.seconds()) * self.seconds(),
+        }
+    }
+}
+
+/// One OHLC bucket. Prices are cents, `time` is the bucket start.
+/// A raw observation is a candle with open = high = low = close.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PriceCandle {
+    pub time: i64,
+    pub open: i64,
+    pub high: i64,
+    pub low: i64,
+    pub close: i64,
+    pub volume: i64,
+}
+
+impl PriceCandle {
+    pub fn point(time: i64, price: i64, volume: i64) -> Self {
+        PriceCandle {
+            time,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume,
+        }
+    }
+
+    /// Folds a later candle into this one.
+    pub fn merge(&mut self, later: &PriceCandle) {
+        self.high = self.high.max(later.high);
+        self.low = self.low.min(later.low);
+        self.close = later.close;
+        self.volume += later.volume;
+    }
+}
+
+/// Groups raw points and already rolled up candles into `interval` buckets.
+/// Input order doesn't matter, output is sorted by bucket start.
+pub fn build_candles(mut candles: Vec<PriceCandle>, interval: CandleInterval) -> Vec<PriceCandle> {
+    candles.sort_by_key(|c| c.time);
+
+    let mut out: Vec<PriceCandle> = Vec::new();
+
+    for candle in candles {
+        let bucket = interval.bucket_start(candle.time);
+
+        match out.last_mut() {
+            Some(last) if last.time == bucket => last.merge(&candle),
+            _ => out.push(PriceCandle { time: bucket, ..candle }),
+        }
+    }
+
+    out
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PriceHistoryQuery {
+    pub interval: Option<CandleInterval>,
+    pub currency: Option<u32>,
+    pub appid: Option<u32>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PriceHistoryResponse {
+    pub market_hash_name: String,
+    pub currency: u32,
+    pub interval: CandleInterval,
+    pub candles: Vec<PriceCandle>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn candles_group_by_hour() {
+        let points = vec![
+            PriceCandle::point(3600 + 50, 120, 1),
+            PriceCandle::point(3600 + 10, 100, 1),
+            PriceCandle::point(3600 + 30, 90, 1),
+            PriceCandle::point(7200 + 5, 200, 1),
+        ];
+
+        assert_eq!(
+            build_candles(points, CandleInterval::Hour),
+            vec![
+                PriceCandle { time: 3600, open: 100, high: 120, low: 90, close: 120, volume: 3 },
+                PriceCandle { time: 7200, open: 200, high: 200, low: 200, close: 200, volume: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn candles_merge_rollups_into_days() {
+        let hourly = PriceCandle { time: 86400, open: 10, high: 40, low: 5, close: 30, volume: 7 };
+        let raw = PriceCandle::point(86400 + 7200, 50, 1);
+
+        assert_eq!(
+            build_candles(vec![raw, hourly], CandleInterval::Day),
+            vec![PriceCandle { time: 86400, open: 10, high: 50, low: 5, close: 50, volume: 8 }]
+        );
+    }
+
+    #[test]
+    fn weeks_start_on_monday() {
+        //1970-01-05 was a Monday
+        let monday = 4 * 86400;
+        assert_eq!(CandleInterval::Week.bucket_start(monday + 3 * 86400), monday);
+        assert_eq!(CandleInterval::Week.bucket_start(monday - 1), monday - 7 * 86400);
+    }
+}
@@ -1,5 +1,5 @@
 use actix_session::{Session};
-use actix_web::{HttpResponse, HttpRequest, Responder, web, Result};
+use actix_web::{HttpResponse, Responder, ResponseError, web, Result};
 use tera::{Context};
 // use uuid::Uuid;
 use serde_json::json;
@@ -16,35 +16,63 @@ use crate::{
     StoreWebsocketListState,
     GameListState
 };
+use crate::ws_connections::WsConnections;
 use steam_market_parser::{
     AdCardHistoryVec, 
     AppContext, 
     BuyerAndStoreIDS, 
     CardAppearingFilter, 
+    CatalogItem,
     CurrentStatusOffer, 
     DraftItem, 
+    FeedFilter,
+    FeedQuery,
+    SearchHit,
+    SearchQuery,
+    NotificationQuery,
+    NotificationsRead,
+    SavedSearchInput,
     FilterInput, 
     HistoryForm, 
     Inventory, 
     InventoryApp, 
     InventoryGame, 
     LoadGameInventory, 
+    Money, 
     MostRecentItemsFilter, 
     OfferCheckResult, 
     OfferContent, 
     OfferContentToCheck, 
     OfferContentUpdated, 
     OfferMakingPlayload, 
+    OrderBookRequest,
+    PriceHistoryQuery,
+    PriceHistoryResponse,
+    PriceOverviewQuery,
+    PriceOverviewRequest,
+    ProfileCurrency,
     ProfileTradeUrl, 
     SteamUser, 
+    SteamClient,
+    build_candles,
+    currency_code,
+    SteamError,
     StoreID, 
-    TradeOfferRequest, 
     UserProfileAds
 };
 
-use crate::db::DataBase;
+use crate::db::{AlertRepo, CatalogRepo, DataBase, DbError, DbPool, DraftRepo, FeedRepo, OfferRepo, UserRepo};
 
-pub async fn load_inventory(_user_inventory: web::Data<UserInventoryState>, params: web::Form<InventoryApp>)-> impl Responder{
+//How long a cached priceoverview row is served before asking Steam again
+const PRICE_OVERVIEW_TTL_SECS: i64 = 15 * 60;
+//Same as the feed poller's default FEED_CURRENCY (EUR)
+const DEFAULT_PRICE_CURRENCY: u32 = 3;
+//Order books move fast, keep the cache short
+const ORDER_BOOK_TTL_SECS: i64 = 60;
+//Default /price_history window when `from` is missing, in candles
+const PRICE_HISTORY_DEFAULT_CANDLES: i64 = 500;
+
+pub async fn load_inventory(_user_inventory: web::Data<UserInventoryState>, params: web::Form<InventoryApp>, db_pool: web::Data<DbPool>)-> impl Responder{
     println!("Call load_inventory!");
     let inventory = &*params;
 
@@ -61,18 +89,19 @@ pub async fn load_inventory(_user_inventory: web::Data<UserInventoryState>, para
 
     println!("{url}");
     
-    let client = reqwest::Client::new();
-
-        let respond = client
-            .get(url)
-            .header("Accept", "application/json")
-            .header("User-Agent", "Mozilla/5.0")
-            .send()
-            .await
-            .unwrap()
-            .text()
-            .await
-            .unwrap();
+    let client = SteamClient::shared();
+
+        let request = client
+            .get(&url)
+            .header("Accept", "application/json");
+
+        let respond = match client.send_text(request).await {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("Steam inventory request failed: {e}");
+                return e.error_response();
+            }
+        };
 
         // println!("{respond:#?}");
 
@@ -90,12 +119,19 @@ pub async fn load_inventory(_user_inventory: web::Data<UserInventoryState>, para
         let mut respond: Inventory = match serde_json::from_str(&respond) {
             Ok(inv) => inv,
             Err(e) => {
+                let e = SteamError::deserialize(e, &respond);
                 eprintln!("Steam inventory parse error: {e}");
-                return HttpResponse::BadGateway().body("Invalid inventory response from Steam");
+                return e.error_response();
             }
         };
         
         respond = keep_only_tradable(respond);
+
+        //Inventories carry the tags listings lack (rarity, exterior), keep them in the catalog
+        let catalog: Vec<CatalogItem> = respond.descriptions.iter().filter_map(CatalogItem::from_inventory_description).collect();
+        if let Err(e) = db_pool.run(move |db| db.db_upsert_catalog_items(&catalog)).await {
+            eprintln!("db_upsert_catalog_items failed: {e}");
+        }
         
         HttpResponse::Ok().json(&respond)
         
@@ -116,23 +152,15 @@ fn keep_only_tradable(mut inv: Inventory) -> Inventory {
         .collect();
 
     // 2) filter descriptions down to tradable ones (optional but usually desired)
-    inv.descriptions = inv
-        .descriptions
-        .into_iter()
-        .filter(|d| d.tradable == Some(1))
-        .collect();
+    inv.descriptions.retain(|d| d.tradable == Some(1));
 
     // 3) filter assets to only those whose (classid, instanceid) is tradable
-    inv.assets = inv
-        .assets
-        .into_iter()
-        .filter(|a| {
-            match (&a.classid, &a.instanceid) {
-                (Some(c), Some(i)) => tradable_keys.contains(&(c.clone(), i.clone())),
-                _ => false,
-            }
-        })
-        .collect();
+    inv.assets.retain(|a| {
+        match (&a.classid, &a.instanceid) {
+            (Some(c), Some(i)) => tradable_keys.contains(&(c.clone(), i.clone())),
+            _ => false,
+        }
+    });
 
     // asset_properties: you can keep as-is, or filter by matching assetid/contextid if you want.
     inv.total_inventory_count = Some(inv.assets.len() as u32);
@@ -141,13 +169,13 @@ fn keep_only_tradable(mut inv: Inventory) -> Inventory {
 }
 
 ///add error handle
-pub async fn steam_logout(session: Session) -> impl Responder {
+pub async fn steam_logout(session: Session, db_pool: web::Data<DbPool>) -> impl Responder {
 
     let steam_user: Option<SteamUser> = session.get("steam_user").unwrap_or(None);
-    if let Some(steamid) = steam_user {
-        let db = DataBase::connect_to_db();
-        db.db_change_user_status(steamid);
-        drop(db);
+    if let Some(steamid) = steam_user
+        && let Err(e) = db_pool.run(move |db| db.db_change_user_status(steamid)).await
+    {
+        eprintln!("db_change_user_status failed: {e}");
     }
     session.clear();
     
@@ -156,16 +184,17 @@ pub async fn steam_logout(session: Session) -> impl Responder {
         .finish()
 }
 
-pub async fn add_ad_steam_user_to_db(form: web::Form<UserProfileAds>, state: web::Data<UserAdState>) -> impl Responder {
+pub async fn add_ad_steam_user_to_db(form: web::Form<UserProfileAds>, state: web::Data<UserAdState>, db_pool: web::Data<DbPool>) -> impl Responder {
     
     let ad_user: UserProfileAds = form.into_inner();
 
-    let db = DataBase::connect_to_db();
-    db.db_add_ad_steam_user(&ad_user);
+    let to_insert = ad_user.clone();
+    if let Err(e) = db_pool.run(move |db| db.db_add_ad_steam_user(&to_insert)).await {
+        return e.error_response();
+    }
 
     state.user_ads.lock().await.queue.push_back(ad_user.clone());
 
-    drop(db);
     HttpResponse::Ok().json(&ad_user)
 }
 
@@ -179,6 +208,7 @@ pub async fn post_most_recent_item_filters(params: web::Query<FilterInput>,
             price_min: deref_param.price_min.clone(),
             price_max: deref_param.price_max.clone(),
             query: deref_param.query.clone(),
+            min_discount: deref_param.min_discount.clone(),
         };
 
         let deref_card_filters = CardAppearingFilter{
@@ -191,17 +221,12 @@ pub async fn post_most_recent_item_filters(params: web::Query<FilterInput>,
         HttpResponse::Ok().json(&*params)
 }
 
-pub async fn get_ad_cards_history(form: web::Form<HistoryForm>) -> impl Responder {
-    let db = DataBase::connect_to_db();
-
-    let deref_form = &*form;
+pub async fn get_ad_cards_history(form: web::Form<HistoryForm>, db_pool: web::Data<DbPool>) -> impl Responder {
+    let steamid = form.into_inner().steamid;
 
-    match db.db_get_ad_cards_history(deref_form.steamid.clone()) {
+    match db_pool.run(move |db| db.db_get_ad_cards_history(steamid)).await {
         Ok(result) => HttpResponse::Ok().json(&result),
-        Err(e) => {
-            println!("Database error: {:?}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({"error": "Database error"}))
-        }
+        Err(e) => e.error_response(),
     }
 }
 
@@ -236,7 +261,7 @@ pub async fn add_to_store_queue(state: web::Data<StoreHashMapState>, buyer_and_s
     }))
 }
 
-pub async fn remove_from_store_queue(state: web::Data<StoreHashMapState>, store_steamid: web::Json<StoreID>, websocket_list_state: web::Data<StoreWebsocketListState>)->impl Responder{
+pub async fn remove_from_store_queue(state: web::Data<StoreHashMapState>, store_steamid: web::Json<StoreID>, _websocket_list_state: web::Data<StoreWebsocketListState>)->impl Responder{
 
     let store_id = &*store_steamid.trader_id;
 
@@ -260,23 +285,14 @@ pub async fn get_inventory_games(params: web::Json<LoadGameInventory>) -> Result
 
     let url = format!("https://steamcommunity.com/profiles/{}/inventory/", steamid);
 
-    let client = reqwest::Client::builder()
-        .gzip(true)
-        .build()
-        .unwrap();
+    let client = SteamClient::shared();
 
+    let request = client
+        .get(&url)
+        .header("Accept", "application/json")
+        .header("Accept-Language", "en-US,en;q=0.9");
 
-        let respond = client
-            .get(url)
-            .header("Accept", "application/json")
-            .header("User-Agent", "Mozilla/5.0")
-            .header("Accept-Language", "en-US,en;q=0.9")
-            .send()
-            .await
-            .unwrap()
-            .text()
-            .await
-            .unwrap();
+    let respond = client.send_text(request).await?;
 
     // (?s) enables "dot matches newline" so .*? spans across lines
     let re = Regex::new(r#"(?s)var\s+g_rgAppContextData\s*=\s*(\{.*?\});"#)
@@ -289,12 +305,12 @@ pub async fn get_inventory_games(params: web::Json<LoadGameInventory>) -> Result
     let json_str = &caps[1];
 
     let data: HashMap<String, AppContext> = serde_json::from_str(json_str)
-        .map_err(actix_web::error::ErrorBadGateway)?;
+        .map_err(|e| SteamError::deserialize(e, json_str))?;
 
     // Convert into a clean response list
     let games: Vec<InventoryGame> = data
-        .into_iter()
-        .map(|(_k, app)| InventoryGame {
+        .into_values()
+        .map(|app| InventoryGame {
             appid: app.appid,
             name: app.name,
             items: app.asset_count,
@@ -328,66 +344,59 @@ pub async fn store_rating(session: Session, state: web::Data<AppState>) -> impl
         })
 }
 
-pub async fn offer_make_offer(ids: web::Json<BuyerAndStoreIDS>) -> OfferMakingPlayload{
-     
-    let db = DataBase::connect_to_db();
+pub async fn offer_make_offer(ids: web::Json<BuyerAndStoreIDS>, db_pool: web::Data<DbPool>) -> Result<OfferMakingPlayload, DbError>{
 
     // Consume the JSON payload to move out the owned strings
     let BuyerAndStoreIDS { buyer_id, trader_id } = ids.into_inner();
 
-    let offer_id = db.db_offer_make_offer(buyer_id, trader_id);
+    let offer_id = db_pool.run(move |db| db.db_offer_make_offer(buyer_id, trader_id)).await?;
 
-    drop(db);
     // println!("offer_id: {offer_id}");
     
-    let playload  = OfferMakingPlayload {
+    Ok(OfferMakingPlayload {
         offer_id
-    };
-    playload
+    })
 }
 
-pub async fn offer_update_offer(offer_content: web::Json<OfferContent>) -> OfferContentUpdated{
-    
-    let db = DataBase::connect_to_db();
+pub async fn offer_update_offer(offer_content: web::Json<OfferContent>, db_pool: web::Data<DbPool>) -> Result<OfferContentUpdated, DbError>{
 
     // Consume the JSON payload to move out the owned strings
     let OfferContent { offer_id, special_for_update_offer} = offer_content.into_inner();
 
-    let result = db.db_offer_update_offer(offer_id, special_for_update_offer);
-
-    drop(db);
-    
-    result
+    db_pool
+        .run(move |db| db.db_offer_update_offer(offer_id, special_for_update_offer))
+        .await
 }
 
-pub async fn offer_update_status_offer(current_status: web::Json<CurrentStatusOffer>)-> impl Responder{
+pub async fn offer_update_status_offer(current_status: web::Json<CurrentStatusOffer>, db_pool: web::Data<DbPool>)-> impl Responder{
 
     let status_and_offer_id  = CurrentStatusOffer {
         offer_id: current_status.offer_id.clone(),
         status: current_status.status.clone()
     };
 
-    let db = DataBase::connect_to_db();
-
-    db.db_offer_update_status_offer(status_and_offer_id);
-
-    drop(db);
-
-    HttpResponse::Ok()
+    match db_pool.run(move |db| db.db_offer_update_status_offer(status_and_offer_id)).await {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => e.error_response(),
+    }
 }
 
-pub async fn offer_check_offer_to_pay(sent_offer: web::Json<OfferContentToCheck>) -> impl Responder {
+pub async fn offer_check_offer_to_pay(sent_offer: web::Json<OfferContentToCheck>, db_pool: web::Data<DbPool>) -> impl Responder {
     let status_and_offer_id  = OfferContentToCheck {
         offer_id: sent_offer.offer_id.clone(),
         special_for_save_offer: sent_offer.special_for_save_offer.clone(),
         partner_steam_id: sent_offer.partner_steam_id.clone(),
     };
 
-    let mut db = DataBase::connect_to_db();
-    let result: OfferCheckResult = db.db_offer_check_offer_to_pay(status_and_offer_id);
+    let result: OfferCheckResult = match db_pool
+        .run(move |db| db.db_offer_check_offer_to_pay(status_and_offer_id))
+        .await
+    {
+        Ok(result) => result,
+        Err(e) => return e.error_response(),
+    };
     println!("{result:#?}");
     if !result.check_result {
-        drop(db);
         return HttpResponse::BadRequest().json(serde_json::json!({
             "ok": false,
             "offer_id": result.offer_id,
@@ -404,24 +413,17 @@ pub async fn offer_check_offer_to_pay(sent_offer: web::Json<OfferContentToCheck>
         amount: 1,
     }).collect();
 
-    let draft_id = match db.db_create_offer_draft(
-        &result.offer_id,
-        &result.partner_trade_url,
-        false,
-        give,
-    ) {
+    let offer_id = result.offer_id.clone();
+    let partner_trade_url = result.partner_trade_url.clone();
+    let draft = db_pool
+        .run(move |db| db.db_create_offer_draft(&offer_id, &partner_trade_url, false, give))
+        .await;
+
+    let draft_id = match draft {
         Ok(id) => id,
-        Err(e) => {
-            drop(db);
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "ok": false,
-                "error": format!("db_create_offer_draft_failed: {e}")
-            }));
-        }
+        Err(e) => return e.error_response(),
     };
 
-    drop(db);
-
     // Append draft_id to the steam trade URL
     let steam_url = format!("{}&tastyrock={}", result.partner_trade_url, draft_id);
     println!("stream_url: {}", steam_url);
@@ -434,51 +436,70 @@ pub async fn offer_check_offer_to_pay(sent_offer: web::Json<OfferContentToCheck>
 }
 
 
-pub async fn account_post_trade_url(profile_trade_url: web::Json<ProfileTradeUrl>)->impl Responder{
+pub async fn account_post_trade_url(profile_trade_url: web::Json<ProfileTradeUrl>, db_pool: web::Data<DbPool>)->impl Responder{
 
-    let steam_id = &profile_trade_url.steam_id;
-    let trade_url = &profile_trade_url.trade_url;
+    let ProfileTradeUrl { steam_id, trade_url } = profile_trade_url.into_inner();
 
-    let db = DataBase::connect_to_db();
+    match db_pool.run(move |db| db.db_account_post_trade_url(&steam_id, &trade_url)).await {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => e.error_response(),
+    }
+}
 
-    db.db_account_post_trade_url(steam_id, trade_url);
+/// The logged-in user's display currency, null until they pick one.
+pub async fn account_get_currency(session: Session, db_pool: web::Data<DbPool>) -> Result<HttpResponse, DbError> {
+    let Some(steam_user) = session.get::<SteamUser>("steam_user").unwrap_or(None) else {
+        return Ok(not_logged_in());
+    };
 
-    drop(db);
+    let currency = db_pool.run(move |db| db.db_account_get_currency(&steam_user.steamid)).await?;
 
-    HttpResponse::Ok()
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "currency": currency })))
 }
 
-pub async fn account_reset_trade_url(profile_trade_url: web::Json<ProfileTradeUrl>)->impl Responder{
+pub async fn account_post_currency(session: Session, profile_currency: web::Json<ProfileCurrency>, db_pool: web::Data<DbPool>)->impl Responder{
 
-    let steam_id = &profile_trade_url.steam_id;
+    let Some(steam_user) = session.get::<SteamUser>("steam_user").unwrap_or(None) else {
+        return not_logged_in();
+    };
+
+    let currency = profile_currency.currency;
+
+    if currency_code(currency).is_none() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "unsupported_currency",
+            "message": format!("Currency {currency} is not supported"),
+        }));
+    }
 
-    let db = DataBase::connect_to_db();
+    match db_pool.run(move |db| db.db_account_post_currency(&steam_user.steamid, currency)).await {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => e.error_response(),
+    }
+}
 
-    db.db_account_reset_trade_url(steam_id);
+pub async fn account_reset_trade_url(profile_trade_url: web::Json<ProfileTradeUrl>, db_pool: web::Data<DbPool>)->impl Responder{
 
-    drop(db);
+    let steam_id = profile_trade_url.into_inner().steam_id;
 
-    HttpResponse::Ok()
+    match db_pool.run(move |db| db.db_account_reset_trade_url(&steam_id)).await {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => e.error_response(),
+    }
 }
 
-pub async fn offer_get_draft(path: web::Path<String>) -> HttpResponse {
+pub async fn offer_get_draft(path: web::Path<String>, db_pool: web::Data<DbPool>) -> HttpResponse {
     let draft_id = path.into_inner();
     println!("offer_get_draft: {draft_id}");
 
-    let db = DataBase::connect_to_db();
-
-    match db.db_get_offer_draft(&draft_id) {
-        Ok(draft) => {
-            drop(db);
-            HttpResponse::Ok().json(draft)
-        }
-        Err(_) => {
-            drop(db);
-            HttpResponse::NotFound().json(serde_json::json!({
-                "error": "draft_not_found",
-                "draft_id": draft_id
-            }))
-        }
+    let lookup_id = draft_id.clone();
+    match db_pool.run(move |db| db.db_get_offer_draft(&lookup_id)).await {
+        Ok(draft) => HttpResponse::Ok().json(draft),
+        Err(DbError::NotFound(_)) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "draft_not_found",
+            "draft_id": draft_id
+        })),
+        Err(e) => e.error_response(),
     }
 }
 
@@ -503,6 +524,7 @@ pub async fn tera_update_data(session: Session,
             price_min: "0".into(),
             price_max: "999999".into(),
             query: "".into(),
+            min_discount: "".into(),
             card_appearing: "stores_items".into(),
         },
     };
@@ -533,3 +555,357 @@ pub async fn tera_update_data(session: Session,
             actix_web::error::ErrorInternalServerError("Template error")
         })
 }
+
+/// `{{ item.price | decimal_price }}`: a Money as "12.50", the same text the feed's JS prints.
+pub fn tera_decimal_price(value: &tera::Value, _args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+    let price: Money = serde_json::from_value(value.clone())
+        .map_err(|e| tera::Error::msg(format!("decimal_price expects a Money: {e}")))?;
+
+    Ok(tera::Value::String(price.to_decimal_string()))
+}
+
+pub async fn get_catalog_item(path: web::Path<(u32, String, String)>, db_pool: web::Data<DbPool>) -> Result<HttpResponse, DbError> {
+    let (appid, classid, instanceid) = path.into_inner();
+
+    let item = db_pool.run(move |db| db.db_get_catalog_item(appid, &classid, &instanceid)).await?;
+    Ok(HttpResponse::Ok().json(item))
+}
+
+pub async fn get_price_overview(path: web::Path<(u32, String)>, query: web::Query<PriceOverviewQuery>, db_pool: web::Data<DbPool>) -> HttpResponse {
+    let (appid, market_hash_name) = path.into_inner();
+    let currency = query.currency.unwrap_or(DEFAULT_PRICE_CURRENCY);
+
+    let name = market_hash_name.clone();
+    let cached = match db_pool.run(move |db| db.db_get_price_overview(appid, &name, currency)).await {
+        Ok(cached) => cached,
+        Err(e) => {
+            eprintln!("db_get_price_overview failed: {e}");
+            None
+        }
+    };
+
+    if let Some(cached) = &cached
+        && chrono::Utc::now().timestamp() - cached.fetched_at < PRICE_OVERVIEW_TTL_SECS
+    {
+        return HttpResponse::Ok().json(cached);
+    }
+
+    match PriceOverviewRequest::new(appid, market_hash_name.clone(), currency).fetch().await {
+        Ok(overview) => {
+            let to_store = overview.clone();
+            if let Err(e) = db_pool.run(move |db| db.db_upsert_price_overview(&to_store)).await {
+                eprintln!("db_upsert_price_overview failed: {e}");
+            }
+
+            HttpResponse::Ok().json(overview)
+        }
+        //priceoverview answers success: false for names it doesn't know
+        Err(SteamError::SteamReportedFailure) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "price_not_found",
+            "appid": appid,
+            "market_hash_name": market_hash_name
+        })),
+        Err(e) => {
+            eprintln!("Price overview for {appid}/{market_hash_name} failed: {e}");
+            match cached {
+                //Stale beats nothing while Steam is throttling us
+                Some(stale) => HttpResponse::Ok().json(stale),
+                None => e.error_response(),
+            }
+        }
+    }
+}
+
+pub async fn get_price_history(path: web::Path<String>, query: web::Query<PriceHistoryQuery>, db_pool: web::Data<DbPool>) -> HttpResponse {
+    let market_hash_name = path.into_inner();
+    let interval = query.interval.unwrap_or_default();
+    let currency = query.currency.unwrap_or(DEFAULT_PRICE_CURRENCY);
+
+    let to = query.to.unwrap_or_else(|| chrono::Utc::now().timestamp());
+    let from = query.from.unwrap_or(to - PRICE_HISTORY_DEFAULT_CANDLES * interval.seconds());
+
+    if from >= to {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "from must be before to"
+        }));
+    }
+
+    // Widen to whole buckets so the first and last candles aren't cut in half
+    let name = market_hash_name.clone();
+    let appid = query.appid;
+    let (start, end) = (interval.bucket_start(from), interval.bucket_start(to) + interval.seconds());
+    let points = db_pool
+        .run(move |db| db.db_get_price_history(&name, currency, appid, start, end))
+        .await;
+
+    match points {
+        Ok(points) => HttpResponse::Ok().json(PriceHistoryResponse {
+            market_hash_name,
+            currency,
+            interval,
+            candles: build_candles(points, interval),
+        }),
+        Err(e) => e.error_response(),
+    }
+}
+
+pub async fn get_order_book(path: web::Path<(u32, String)>, query: web::Query<PriceOverviewQuery>, db_pool: web::Data<DbPool>) -> HttpResponse {
+    let (appid, market_hash_name) = path.into_inner();
+    let currency = query.currency.unwrap_or(DEFAULT_PRICE_CURRENCY);
+
+    let name = market_hash_name.clone();
+    let (cached, known_nameid) = db_pool
+        .run(move |db| {
+            let cached = match db.db_get_order_book(appid, &name, currency) {
+                Ok(cached) => cached,
+                Err(e) => {
+                    eprintln!("db_get_order_book failed: {e}");
+                    None
+                }
+            };
+            let known_nameid = match db.db_get_item_nameid(appid, &name) {
+                Ok(nameid) => nameid,
+                Err(e) => {
+                    eprintln!("db_get_item_nameid failed: {e}");
+                    None
+                }
+            };
+            Ok((cached, known_nameid))
+        })
+        .await
+        .unwrap_or_else(|e| {
+            eprintln!("get_order_book: no database connection: {e}");
+            (None, None)
+        });
+
+    if let Some(cached) = &cached
+        && chrono::Utc::now().timestamp() - cached.fetched_at < ORDER_BOOK_TTL_SECS
+    {
+        return HttpResponse::Ok().json(cached);
+    }
+
+    let request = OrderBookRequest::new(appid, market_hash_name.clone(), currency);
+
+    let result = match known_nameid {
+        Some(item_nameid) => request.fetch(item_nameid).await,
+        None => match request.resolve_item_nameid().await {
+            Ok(item_nameid) => {
+                let name = market_hash_name.clone();
+                if let Err(e) = db_pool.run(move |db| db.db_insert_item_nameid(appid, &name, item_nameid)).await {
+                    eprintln!("db_insert_item_nameid failed: {e}");
+                }
+
+                request.fetch(item_nameid).await
+            }
+            Err(e) => Err(e),
+        },
+    };
+
+    match result {
+        Ok(book) => {
+            let to_store = book.clone();
+            if let Err(e) = db_pool.run(move |db| db.db_upsert_order_book(&to_store)).await {
+                eprintln!("db_upsert_order_book failed: {e}");
+            }
+
+            HttpResponse::Ok().json(book)
+        }
+        Err(SteamError::SteamReportedFailure) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "order_book_not_found",
+            "appid": appid,
+            "market_hash_name": market_hash_name
+        })),
+        Err(e) => {
+            eprintln!("Order book for {appid}/{market_hash_name} failed: {e}");
+            match cached {
+                Some(stale) => HttpResponse::Ok().json(stale),
+                None => e.error_response(),
+            }
+        }
+    }
+}
+
+/// Counters from the /market/recent poller, for monitoring.
+pub async fn get_feed_stats(feed_state: web::Data<FeedItemsState>) -> HttpResponse {
+    let stats = feed_state.poll_stats.lock().await.clone();
+
+    HttpResponse::Ok().json(stats)
+}
+
+/// Feed history from item_feed, for backfilling after a reconnect. See FeedQuery for the cursors.
+pub async fn get_feed(session: Session, query: web::Query<FeedQuery>, db_pool: web::Data<DbPool>) -> Result<HttpResponse, DbError> {
+    let query = query.into_inner();
+    let steam_user: Option<SteamUser> = session.get("steam_user").unwrap_or(None);
+
+    let matcher = match query.filter().and_then(FeedFilter::compile) {
+        Ok(matcher) => matcher,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "invalid_filter",
+                "message": e.to_string(),
+            })));
+        }
+    };
+
+    let page = db_pool
+        .run(move |db| {
+            let currency = display_currency(db, query.currency, steam_user)?;
+            let rates = db.db_get_currency_rates()?;
+
+            db.db_get_feed_page(&query, &matcher, currency, &rates)
+        })
+        .await?;
+
+    Ok(HttpResponse::Ok().json(page))
+}
+
+/// Full-text search over item_feed, see SearchQuery. A query without words finds nothing.
+pub async fn get_search(session: Session, query: web::Query<SearchQuery>, db_pool: web::Data<DbPool>) -> Result<HttpResponse, DbError> {
+    let query = query.into_inner();
+    let steam_user: Option<SteamUser> = session.get("steam_user").unwrap_or(None);
+
+    let hits = db_pool
+        .run(move |db| {
+            let currency = display_currency(db, query.currency, steam_user)?;
+            let rates = db.db_get_currency_rates()?;
+
+            let hits = db.db_search_feed(&query)?;
+            Ok::<_, DbError>(hits
+                .into_iter()
+                .map(|hit| SearchHit { item: hit.item.in_currency(currency, &rates), ..hit })
+                .collect::<Vec<_>>())
+        })
+        .await?;
+
+    Ok(HttpResponse::Ok().json(hits))
+}
+
+//Same display currency /ws would use for this session
+fn display_currency(db: &DataBase, requested: Option<u32>, steam_user: Option<SteamUser>) -> Result<Option<u32>, DbError> {
+    match (requested, steam_user) {
+        (Some(currency), _) => Ok(Some(currency)),
+        (None, Some(user)) => db.db_account_get_currency(&user.steamid),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Live websocket connections per endpoint. Only for the steam ids in ADMIN_STEAMIDS.
+pub async fn get_ws_connections(session: Session, connections: web::Data<WsConnections>) -> HttpResponse {
+    let Some(steam_user) = session.get::<SteamUser>("steam_user").unwrap_or(None) else {
+        return not_logged_in();
+    };
+    if !is_admin(&steam_user.steamid) {
+        return HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "forbidden",
+            "message": "Admins only",
+        }));
+    }
+
+    HttpResponse::Ok().json(connections.counts())
+}
+
+/// ADMIN_STEAMIDS is a comma separated list of steam ids.
+fn is_admin(steamid: &str) -> bool {
+    std::env::var("ADMIN_STEAMIDS").is_ok_and(|ids| ids.split(',').any(|id| id.trim() == steamid))
+}
+
+fn not_logged_in() -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({
+        "error": "not_logged_in",
+        "message": "Log in with Steam first",
+    }))
+}
+
+/// The logged-in user's saved searches, oldest first.
+pub async fn get_saved_searches(session: Session, db_pool: web::Data<DbPool>) -> Result<HttpResponse, DbError> {
+    let Some(steam_user) = session.get::<SteamUser>("steam_user").unwrap_or(None) else {
+        return Ok(not_logged_in());
+    };
+
+    let searches = db_pool.run(move |db| db.db_get_saved_searches(&steam_user.steamid)).await?;
+
+    Ok(HttpResponse::Ok().json(searches))
+}
+
+/// Saves the filters under a name. Prices stay in the currency they were entered in.
+pub async fn post_saved_search(
+    session: Session,
+    input: web::Json<SavedSearchInput>,
+    db_pool: web::Data<DbPool>,
+) -> Result<HttpResponse, DbError> {
+    let Some(steam_user) = session.get::<SteamUser>("steam_user").unwrap_or(None) else {
+        return Ok(not_logged_in());
+    };
+    let mut input = input.into_inner();
+
+    if let Err(message) = input.validate() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "invalid_saved_search",
+            "message": message,
+        })));
+    }
+    if let Some(currency) = input.currency
+        && currency_code(currency).is_none()
+    {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "unsupported_currency",
+            "message": format!("Currency {currency} is not supported"),
+        })));
+    }
+
+    let saved = db_pool
+        .run(move |db| {
+            let steamid = steam_user.steamid.clone();
+            input.currency = display_currency(db, input.currency, Some(steam_user))?;
+            db.db_insert_saved_search(&steamid, &input, chrono::Utc::now().timestamp())
+        })
+        .await?;
+
+    Ok(HttpResponse::Created().json(saved))
+}
+
+pub async fn delete_saved_search(session: Session, id: web::Path<i64>, db_pool: web::Data<DbPool>) -> Result<HttpResponse, DbError> {
+    let Some(steam_user) = session.get::<SteamUser>("steam_user").unwrap_or(None) else {
+        return Ok(not_logged_in());
+    };
+    let id = id.into_inner();
+
+    db_pool.run(move |db| db.db_delete_saved_search(&steam_user.steamid, id)).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Stored saved search matches, newest first. /ws/alerts pushes new ones as they come.
+pub async fn get_notifications(
+    session: Session,
+    query: web::Query<NotificationQuery>,
+    db_pool: web::Data<DbPool>,
+) -> Result<HttpResponse, DbError> {
+    let Some(steam_user) = session.get::<SteamUser>("steam_user").unwrap_or(None) else {
+        return Ok(not_logged_in());
+    };
+    let (unread, limit) = (query.unread, query.page_limit());
+
+    let notifications = db_pool
+        .run(move |db| db.db_get_notifications(&steam_user.steamid, unread, limit))
+        .await?;
+
+    Ok(HttpResponse::Ok().json(notifications))
+}
+
+pub async fn post_notifications_read(
+    session: Session,
+    read: web::Json<NotificationsRead>,
+    db_pool: web::Data<DbPool>,
+) -> Result<HttpResponse, DbError> {
+    let Some(steam_user) = session.get::<SteamUser>("steam_user").unwrap_or(None) else {
+        return Ok(not_logged_in());
+    };
+    let up_to_id = read.up_to_id;
+
+    let marked = db_pool
+        .run(move |db| db.db_mark_notifications_read(&steam_user.steamid, up_to_id, chrono::Utc::now().timestamp()))
+        .await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "marked": marked })))
+}
@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{CurrencyRates, FeedFilter, FeedMatcher, MostRecent};
+
+const SAVED_SEARCH_NAME_MAX: usize = 64;
+
+/// POST /api/saved_searches. The side panel filters under a name: `appid` is an appid
+/// or part of a game name, prices are cents in `currency` (default: the user's display currency).
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct SavedSearchInput {
+    pub name: String,
+    #[serde(default)]
+    pub appid: String,
+    pub price_min: Option<i64>,
+    pub price_max: Option<i64>,
+    #[serde(default)]
+    pub query: String,
+    pub currency: Option<u32>,
+}
+
+impl SavedSearchInput {
+    /// Why it can't be saved. A search without any filter would alert on every listing.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let name = self.name.trim();
+        if name.is_empty() || name.chars().count() > SAVED_SEARCH_NAME_MAX {
+            return Err("name must be 1 to 64 characters");
+        }
+        if self.appid.trim().is_empty() && self.query.trim().is_empty() && self.price_min.is_none() && self.price_max.is_none() {
+            return Err("at least one filter is required");
+        }
+        if let (Some(min), Some(max)) = (self.price_min, self.price_max)
+            && min > max
+        {
+            return Err("price_min is above price_max");
+        }
+        Ok(())
+    }
+}
+
+/// A row of saved_searches.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SavedSearch {
+    pub id: i64,
+    pub steamid: String,
+    pub name: String,
+    pub appid: String,
+    pub price_min: Option<i64>,
+    pub price_max: Option<i64>,
+    pub query: String,
+    /// What price_min / price_max are in, None for the feed currency
+    pub currency: Option<u32>,
+    pub created_at: i64,
+}
+
+impl SavedSearch {
+    /// The feed filter these fields stand for. A saved search has no tradable, exclude,
+    /// regex or discount setting, those stay off.
+    pub fn feed_filter(&self) -> FeedFilter {
+        FeedFilter {
+            game: Some(self.appid.clone()),
+            price_min: self.price_min,
+            price_max: self.price_max,
+            query: Some(self.query.clone()),
+            ..Default::default()
+        }
+    }
+
+    /// `matcher`, compiled from `feed_filter`, on `item` as the feed stores it priced in `currency`.
+    /// Unlike /ws, a price bound skips unpriced listings and ones without a rate into `currency`.
+    pub fn matches(&self, matcher: &FeedMatcher, item: &MostRecent, rates: &CurrencyRates) -> bool {
+        let item = item.in_currency(self.currency, rates);
+
+        if (self.price_min.is_some() || self.price_max.is_some())
+            && item.price.is_none_or(|price| self.currency.is_some_and(|currency| price.currency != currency))
+        {
+            return false;
+        }
+
+        matcher.matches(&item)
+    }
+}
+
+/// A listing that matched a saved search. `item` is a snapshot priced in the search's
+/// currency, it outlives the feed row.
+#[derive(Serialize, Debug, Clone)]
+pub struct Notification {
+    pub id: i64,
+    pub steamid: String,
+    pub saved_search_id: i64,
+    pub search_name: String,
+    pub item: MostRecent,
+    pub created_at: i64,
+    pub read: bool,
+}
+
+/// GET /api/notifications
+#[derive(Deserialize, Debug, Default)]
+pub struct NotificationQuery {
+    #[serde(default)]
+    pub unread: bool,
+    pub limit: Option<u32>,
+}
+
+impl NotificationQuery {
+    pub fn page_limit(&self) -> usize {
+        self.limit.unwrap_or(50).clamp(1, 200) as usize
+    }
+}
+
+/// POST /api/notifications/read: everything up to and including `up_to_id`.
+#[derive(Deserialize, Debug)]
+pub struct NotificationsRead {
+    pub up_to_id: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_like_the_live_feed() {
+        let mut rates = CurrencyRates::default();
+        rates.insert(3, 1, 2.0);
+        let search = SavedSearch {
+            id: 1,
+            steamid: "1".into(),
+            name: "cheap redlines".into(),
+            appid: "counter".into(),
+            price_min: None,
+            price_max: Some(1000),
+            query: "redline".into(),
+            currency: Some(1),
+            created_at: 0,
+        };
+        let matches = |search: &SavedSearch, item: &MostRecent, rates: &CurrencyRates| {
+            search.matches(&search.feed_filter().compile().unwrap(), item, rates)
+        };
+
+        assert!(matches(&search, &MostRecent::sample("AK-47 | Redline", Some(500)), &rates));
+        assert!(!matches(&search, &MostRecent::sample("AK-47 | Redline", Some(501)), &rates));
+        assert!(!matches(&search, &MostRecent::sample("AK-47 | Redline", None), &rates));
+        assert!(!matches(&search, &MostRecent::sample("AWP | Asiimov", Some(100)), &rates));
+        assert!(matches(&SavedSearch { appid: "730".into(), ..search.clone() }, &MostRecent::sample("AK-47 | Redline", Some(500)), &rates));
+        assert!(!matches(&SavedSearch { appid: "440".into(), ..search.clone() }, &MostRecent::sample("AK-47 | Redline", Some(500)), &rates));
+
+        //No rate into the search currency, the bound can't be checked
+        assert!(!matches(&search, &MostRecent::sample("AK-47 | Redline", Some(100)), &CurrencyRates::default()));
+    }
+
+    #[test]
+    fn input_needs_a_name_and_a_filter() {
+        let input = SavedSearchInput { name: "keys".into(), query: "key".into(), ..Default::default() };
+        assert_eq!(input.validate(), Ok(()));
+
+        assert!(SavedSearchInput { name: " ".into(), ..input.clone() }.validate().is_err());
+        assert!(SavedSearchInput { query: String::new(), ..input.clone() }.validate().is_err());
+        assert!(SavedSearchInput { price_min: Some(10), price_max: Some(5), ..input }.validate().is_err());
+    }
+}
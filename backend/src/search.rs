@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+
+use crate::MostRecent;
+
+const SEARCH_DEFAULT_LIMIT: u32 = 20;
+const SEARCH_MAX_LIMIT: u32 = 100;
+//Keeps a pasted paragraph from turning into a huge MATCH expression
+const SEARCH_MAX_TERMS: usize = 8;
+
+/// Wraps matched words in highlights before they are escaped, see `highlight_html`.
+pub const MARK_START: char = '\u{2}';
+pub const MARK_END: char = '\u{3}';
+
+/// GET /api/search. Every word of `q` has to start a word of the item name,
+/// its market_hash_name or the game name. `game` and `currency` work like on /api/feed.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct SearchQuery {
+    pub q: String,
+    pub game: Option<String>,
+    pub limit: Option<u32>,
+    pub currency: Option<u32>,
+}
+
+/// One listing from item_feed that matched, best match first.
+#[derive(Serialize, Debug)]
+pub struct SearchHit {
+    #[serde(flatten)]
+    pub item: MostRecent,
+    /// Item name as HTML, escaped, matched words in `<mark>`
+    pub highlight: String,
+}
+
+impl SearchQuery {
+    /// Lowercase words of `q`, split the way the unicode61 tokenizer splits them.
+    pub fn terms(&self) -> Vec<String> {
+        let mut terms: Vec<String> = Vec::new();
+
+        for word in self.q.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()) {
+            let word = word.to_lowercase();
+            if !terms.contains(&word) {
+                terms.push(word);
+            }
+        }
+
+        terms.truncate(SEARCH_MAX_TERMS);
+        terms
+    }
+
+    /// FTS5 MATCH expression: each term as a quoted prefix query, all of them required.
+    /// None when `q` has no words.
+    pub fn fts_match(&self) -> Option<String> {
+        let terms = self.terms();
+        if terms.is_empty() {
+            return None;
+        }
+
+        Some(terms.iter().map(|term| format!("\"{term}\"*")).collect::<Vec<_>>().join(" "))
+    }
+
+    pub fn game(&self) -> Option<&str> {
+        self.game.as_deref().map(str::trim).filter(|game| !game.is_empty())
+    }
+
+    pub fn page_limit(&self) -> usize {
+        self.limit.unwrap_or(SEARCH_DEFAULT_LIMIT).clamp(1, SEARCH_MAX_LIMIT) as usize
+    }
+}
+
+/// Escapes `marked` for HTML and turns MARK_START / MARK_END into `<mark>` tags.
+/// Item names come from Steam users, they can contain anything.
+pub fn highlight_html(marked: &str) -> String {
+    let mut html = String::with_capacity(marked.len() + 16);
+
+    for c in marked.chars() {
+        match c {
+            MARK_START => html.push_str("<mark>"),
+            MARK_END => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_becomes_prefix_terms() {
+        let query = SearchQuery { q: "AK-47 | red\"line ak".into(), ..Default::default() };
+
+        assert_eq!(query.terms(), ["ak", "47", "red", "line"]);
+        assert_eq!(query.fts_match().as_deref(), Some("\"ak\"* \"47\"* \"red\"* \"line\"*"));
+        assert_eq!(SearchQuery { q: " | ".into(), ..Default::default() }.fts_match(), None);
+    }
+
+    #[test]
+    fn highlight_is_escaped() {
+        let marked = format!("{MARK_START}Sticker{MARK_END} | <b>Crown</b> & co");
+
+        assert_eq!(highlight_html(&marked), "<mark>Sticker</mark> | &lt;b&gt;Crown&lt;/b&gt; &amp; co");
+    }
+}
@@ -0,0 +1,379 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::sync::{Mutex, Semaphore};
+use reqwest::{RequestBuilder, StatusCode, header::{HeaderMap, RETRY_AFTER}};
+
+use crate::{Result, SteamError};
+
+static SHARED_STEAM_CLIENT: OnceLock<SteamClient> = OnceLock::new();
+
+//----------------------------------
+//----------------------------------
+//Config
+
+/// Limits for every request we send to Steam.
+/// Defaults are tuned for steamcommunity.com, which starts answering 429
+/// somewhere around 20 requests per minute from one IP.
+#[derive(Clone, Debug)]
+pub struct SteamClientConfig {
+    pub requests_per_minute: u32,
+    pub burst: u32,
+    pub max_retries: u32,
+    pub base_backoff: Duration,
+    pub max_backoff: Duration,
+    pub timeout: Duration,
+    pub connect_timeout: Duration,
+    pub max_concurrent_per_host: usize,
+}
+
+impl Default for SteamClientConfig {
+    fn default() -> Self {
+        SteamClientConfig {
+            requests_per_minute: 20,
+            burst: 5,
+            max_retries: 4,
+            base_backoff: Duration::from_secs(2),
+            max_backoff: Duration::from_secs(120),
+            timeout: Duration::from_secs(20),
+            connect_timeout: Duration::from_secs(10),
+            max_concurrent_per_host: 2,
+        }
+    }
+}
+
+impl SteamClientConfig {
+    /// Default config with overrides from STEAM_REQUESTS_PER_MINUTE, STEAM_BURST,
+    /// STEAM_MAX_RETRIES, STEAM_TIMEOUT_SECS and STEAM_MAX_CONCURRENT_PER_HOST.
+    pub fn from_env() -> Self {
+        let mut config = SteamClientConfig::default();
+
+        if let Some(v) = env_parse("STEAM_REQUESTS_PER_MINUTE") {
+            config.requests_per_minute = v;
+        }
+        if let Some(v) = env_parse("STEAM_BURST") {
+            config.burst = v;
+        }
+        if let Some(v) = env_parse("STEAM_MAX_RETRIES") {
+            config.max_retries = v;
+        }
+        if let Some(v) = env_parse("STEAM_TIMEOUT_SECS") {
+            config.timeout = Duration::from_secs(v);
+        }
+        if let Some(v) = env_parse("STEAM_MAX_CONCURRENT_PER_HOST") {
+            config.max_concurrent_per_host = v;
+        }
+
+        config
+    }
+}
+
+fn env_parse<T: std::str::FromStr>(key: &str) -> Option<T> {
+    std::env::var(key).ok()?.trim().parse().ok()
+}
+
+//----------------------------------
+//----------------------------------
+//Token bucket
+
+struct TokenBucket {
+    tokens: f64,
+    capacity: f64,
+    per_second: f64,
+    last_refill: Instant,
+    //Set by a 429 so every caller waits, not only the one that got it
+    blocked_until: Option<Instant>,
+}
+
+impl TokenBucket {
+    fn new(requests_per_minute: u32, burst: u32) -> Self {
+        let capacity = burst.max(1) as f64;
+        TokenBucket {
+            tokens: capacity,
+            capacity,
+            per_second: requests_per_minute.max(1) as f64 / 60.0,
+            last_refill: Instant::now(),
+            blocked_until: None,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// Takes a token, or returns how long to wait before trying again.
+    fn try_take(&mut self) -> Option<Duration> {
+        self.try_take_at(Instant::now())
+    }
+
+    fn try_take_at(&mut self, now: Instant) -> Option<Duration> {
+        if let Some(until) = self.blocked_until {
+            if until > now {
+                return Some(until - now);
+            }
+            self.blocked_until = None;
+        }
+
+        self.refill(now);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - self.tokens) / self.per_second))
+        }
+    }
+
+    fn block_for(&mut self, wait: Duration) {
+        let until = Instant::now() + wait;
+        if self.blocked_until.is_none_or(|current| current < until) {
+            self.blocked_until = Some(until);
+        }
+        self.tokens = 0.0;
+    }
+}
+
+//----------------------------------
+//----------------------------------
+//Client
+
+/// One reqwest client shared by every Steam call.
+/// Each request waits for a token from the global bucket and a slot from the
+/// per-host semaphore, and 429 / 5xx / network errors are retried with
+/// exponential backoff (or the server's Retry-After when it sends one).
+pub struct SteamClient {
+    http: reqwest::Client,
+    config: SteamClientConfig,
+    bucket: Mutex<TokenBucket>,
+    hosts: std::sync::Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl SteamClient {
+    pub fn new(config: SteamClientConfig) -> Self {
+        let http = reqwest::Client::builder()
+            .gzip(true)
+            .user_agent("Mozilla/5.0")
+            .timeout(config.timeout)
+            .connect_timeout(config.connect_timeout)
+            .build()
+            .expect("SteamClient: Failed to build reqwest client");
+
+        SteamClient {
+            http,
+            bucket: Mutex::new(TokenBucket::new(config.requests_per_minute, config.burst)),
+            hosts: std::sync::Mutex::new(HashMap::new()),
+            config,
+        }
+    }
+
+    /// Process-wide client, configured from env on first use.
+    pub fn shared() -> &'static SteamClient {
+        SHARED_STEAM_CLIENT.get_or_init(|| SteamClient::new(SteamClientConfig::from_env()))
+    }
+
+    pub fn get(&self, url: &str) -> RequestBuilder {
+        self.http.get(url)
+    }
+
+    /// Sends the request through the limiter and returns the response body.
+    pub async fn send_text(&self, request: RequestBuilder) -> Result<String> {
+        let mut attempt: u32 = 0;
+
+        loop {
+            let builder = request
+                .try_clone()
+                .ok_or_else(|| SteamError::InvalidRequest("request body can't be retried".to_string()))?;
+            let built = builder
+                .build()
+                .map_err(|e| SteamError::InvalidRequest(e.to_string()))?;
+            let url = built.url().clone();
+            let host = url.host_str().unwrap_or_default().to_string();
+
+            let semaphore = self.host_semaphore(&host);
+            let permit = semaphore
+                .acquire_owned()
+                .await
+                .expect("SteamClient: host semaphore closed");
+            self.acquire_token().await;
+
+            let retry_delay = match self.http.execute(built).await {
+                Ok(response) => {
+                    let status = response.status();
+
+                    if status.is_success() {
+                        return Ok(response.text().await?);
+                    }
+
+                    let retry_after = retry_after(response.headers());
+
+                    if !is_retryable(status) || attempt >= self.config.max_retries {
+                        if status == StatusCode::TOO_MANY_REQUESTS {
+                            return Err(SteamError::RateLimited { retry_after });
+                        }
+                        return Err(SteamError::HttpStatus { status: status.as_u16(), url: url.to_string() });
+                    }
+                    let delay = retry_after.unwrap_or_else(|| self.backoff(attempt));
+
+                    if status == StatusCode::TOO_MANY_REQUESTS {
+                        self.bucket.lock().await.block_for(delay);
+                    }
+
+                    eprintln!("Steam returned {status} for {url}, retry {} in {delay:?}", attempt + 1);
+                    delay
+                }
+                Err(e) => {
+                    if attempt >= self.config.max_retries {
+                        return Err(e.into());
+                    }
+
+                    let delay = self.backoff(attempt);
+                    eprintln!("Steam request to {url} failed: {e}, retry {} in {delay:?}", attempt + 1);
+                    delay
+                }
+            };
+
+            drop(permit);
+            tokio::time::sleep(retry_delay).await;
+            attempt += 1;
+        }
+    }
+
+    async fn acquire_token(&self) {
+        loop {
+            let wait = self.bucket.lock().await.try_take();
+
+            match wait {
+                None => return,
+                Some(wait) => tokio::time::sleep(wait).await,
+            }
+        }
+    }
+
+    fn host_semaphore(&self, host: &str) -> Arc<Semaphore> {
+        let mut hosts = self.hosts.lock().expect("SteamClient: hosts lock poisoned");
+
+        hosts
+            .entry(host.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.config.max_concurrent_per_host.max(1))))
+            .clone()
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self.config.base_backoff.saturating_mul(2u32.saturating_pow(attempt));
+        let jitter = Duration::from_millis(
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.subsec_millis() as u64 % 500)
+                .unwrap_or(0),
+        );
+
+        exp.min(self.config.max_backoff) + jitter
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    parse_retry_after(headers.get(RETRY_AFTER)?.to_str().ok()?, chrono::Utc::now().timestamp())
+}
+
+/// Retry-After is either delay seconds or an HTTP date, `now` in unix seconds.
+fn parse_retry_after(value: &str, now: i64) -> Option<Duration> {
+    let value = value.trim();
+
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let secs = (date.timestamp() - now).max(0);
+    Some(Duration::from_secs(secs as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_spends_its_burst_then_refills() {
+        let mut bucket = TokenBucket::new(60, 3);
+        let start = bucket.last_refill;
+
+        for _ in 0..3 {
+            assert_eq!(bucket.try_take_at(start), None);
+        }
+        assert_eq!(bucket.try_take_at(start), Some(Duration::from_secs(1)));
+
+        //One token a second, never more than the burst
+        assert_eq!(bucket.try_take_at(start + Duration::from_millis(1500)), None);
+        assert_eq!(bucket.try_take_at(start + Duration::from_millis(1500)), Some(Duration::from_millis(500)));
+        bucket.try_take_at(start + Duration::from_secs(60));
+        assert!((bucket.tokens - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rate_limit_blocks_the_whole_bucket() {
+        let mut bucket = TokenBucket::new(600, 10);
+
+        bucket.block_for(Duration::from_secs(30));
+
+        let wait = bucket.try_take().unwrap();
+        assert!(wait > Duration::from_secs(29) && wait <= Duration::from_secs(30), "{wait:?}");
+
+        //A shorter block doesn't cut the current one
+        bucket.block_for(Duration::from_secs(1));
+        assert!(bucket.try_take().unwrap() > Duration::from_secs(29));
+
+        let after = bucket.blocked_until.unwrap() + Duration::from_secs(1);
+        assert_eq!(bucket.try_take_at(after), None);
+        assert!(bucket.blocked_until.is_none());
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap_with_jitter() {
+        let client = SteamClient::new(SteamClientConfig {
+            base_backoff: Duration::from_secs(2),
+            max_backoff: Duration::from_secs(20),
+            ..Default::default()
+        });
+        let jitter = Duration::from_millis(500);
+
+        for (attempt, base) in [(0, 2), (1, 4), (2, 8), (3, 16), (4, 20), (30, 20)] {
+            let delay = client.backoff(attempt);
+            let base = Duration::from_secs(base);
+            assert!(delay >= base && delay < base + jitter, "attempt {attempt}: {delay:?}");
+        }
+    }
+
+    #[test]
+    fn retry_after_reads_seconds_and_dates() {
+        //Tue, 15 Nov 1994 08:12:31 GMT
+        let now = 784887151;
+
+        assert_eq!(parse_retry_after("120", now), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after(" 0 ", now), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("Tue, 15 Nov 1994 08:13:01 GMT", now), Some(Duration::from_secs(30)));
+        assert_eq!(parse_retry_after("Tue, 15 Nov 1994 08:00:00 GMT", now), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon", now), None);
+        assert_eq!(parse_retry_after("-5", now), None);
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert(RETRY_AFTER, "7".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(7)));
+    }
+
+    #[test]
+    fn retries_only_rate_limits_and_server_errors() {
+        assert!(is_retryable(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable(StatusCode::BAD_GATEWAY));
+        assert!(!is_retryable(StatusCode::FORBIDDEN));
+        assert!(!is_retryable(StatusCode::NOT_FOUND));
+    }
+}
@@ -0,0 +1,166 @@
+use std::{fmt, time::Duration};
+use actix_web::{HttpResponse, ResponseError, http::StatusCode};
+
+//How much of a bad body we keep for logs
+const BODY_SNIPPET_LEN: usize = 300;
+
+/// Everything that can go wrong while talking to Steam.
+#[derive(Debug)]
+pub enum SteamError {
+    /// Still 429 after all retries. retry_after is what Steam asked for, if anything.
+    RateLimited { retry_after: Option<Duration> },
+    /// Connect, timeout or body read failure.
+    Network(reqwest::Error),
+    /// Non-success status that isn't a rate limit.
+    HttpStatus { status: u16, url: String },
+    /// Body didn't match our structs. Usually means Steam changed the schema.
+    Deserialize { source: serde_json::Error, snippet: String },
+    /// Valid JSON, but Steam says `success: false`.
+    SteamReportedFailure,
+    /// Empty body or a bare `null`.
+    EmptyResponse,
+    /// We couldn't build the request in the first place.
+    InvalidRequest(String),
+}
+
+impl SteamError {
+    pub fn deserialize(source: serde_json::Error, body: &str) -> Self {
+        let snippet = match body.char_indices().nth(BODY_SNIPPET_LEN) {
+            Some((idx, _)) => format!("{}...", &body[..idx]),
+            None => body.to_string(),
+        };
+
+        SteamError::Deserialize { source, snippet }
+    }
+
+    /// Short machine-readable name, used as the `error` field in JSON responses.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SteamError::RateLimited { .. } => "steam_rate_limited",
+            SteamError::Network(_) => "steam_network",
+            SteamError::HttpStatus { .. } => "steam_http_status",
+            SteamError::Deserialize { .. } => "steam_bad_response",
+            SteamError::SteamReportedFailure => "steam_reported_failure",
+            SteamError::EmptyResponse => "steam_empty_response",
+            SteamError::InvalidRequest(_) => "steam_invalid_request",
+        }
+    }
+}
+
+impl fmt::Display for SteamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SteamError::RateLimited { retry_after: Some(wait) } => {
+                write!(f, "Steam rate limit hit, retry after {}s", wait.as_secs())
+            }
+            SteamError::RateLimited { retry_after: None } => write!(f, "Steam rate limit hit"),
+            SteamError::Network(e) => write!(f, "Steam request failed: {e}"),
+            SteamError::HttpStatus { status, url } => write!(f, "Steam returned HTTP {status} for {url}"),
+            SteamError::Deserialize { source, snippet } => {
+                write!(f, "Can't parse Steam response: {source}; body: {snippet}")
+            }
+            SteamError::SteamReportedFailure => write!(f, "Steam reported success: false"),
+            SteamError::EmptyResponse => write!(f, "Steam returned an empty response"),
+            SteamError::InvalidRequest(msg) => write!(f, "Invalid Steam request: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for SteamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SteamError::Network(e) => Some(e),
+            SteamError::Deserialize { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for SteamError {
+    fn from(e: reqwest::Error) -> Self {
+        SteamError::Network(e)
+    }
+}
+
+impl ResponseError for SteamError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SteamError::RateLimited { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            SteamError::Network(e) if e.is_timeout() => StatusCode::GATEWAY_TIMEOUT,
+            SteamError::Network(_) => StatusCode::BAD_GATEWAY,
+            //Private profile / inventory and unknown steamid are worth passing through
+            SteamError::HttpStatus { status: 403, .. } => StatusCode::FORBIDDEN,
+            SteamError::HttpStatus { status: 404, .. } => StatusCode::NOT_FOUND,
+            SteamError::HttpStatus { .. } => StatusCode::BAD_GATEWAY,
+            SteamError::Deserialize { .. } => StatusCode::BAD_GATEWAY,
+            SteamError::SteamReportedFailure => StatusCode::BAD_GATEWAY,
+            SteamError::EmptyResponse => StatusCode::BAD_GATEWAY,
+            SteamError::InvalidRequest(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+
+        if let SteamError::RateLimited { retry_after: Some(wait) } = self {
+            response.insert_header(("Retry-After", wait.as_secs().to_string()));
+        }
+
+        response.json(serde_json::json!({
+            "error": self.kind(),
+            "message": self.to_string(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //A listener that never answers times the request out, a closed port refuses it
+    async fn network_error(answering: bool) -> reqwest::Error {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        if !answering {
+            drop(listener);
+        }
+
+        let client = reqwest::Client::builder().timeout(Duration::from_millis(100)).build().unwrap();
+        client.get(url).send().await.err().unwrap()
+    }
+
+    #[tokio::test]
+    async fn maps_to_http_status() {
+        let json = serde_json::from_str::<u32>("{").err().unwrap();
+        let cases = [
+            (SteamError::RateLimited { retry_after: None }, StatusCode::SERVICE_UNAVAILABLE),
+            (SteamError::Network(network_error(true).await), StatusCode::GATEWAY_TIMEOUT),
+            (SteamError::Network(network_error(false).await), StatusCode::BAD_GATEWAY),
+            (SteamError::HttpStatus { status: 403, url: String::new() }, StatusCode::FORBIDDEN),
+            (SteamError::HttpStatus { status: 404, url: String::new() }, StatusCode::NOT_FOUND),
+            (SteamError::HttpStatus { status: 500, url: String::new() }, StatusCode::BAD_GATEWAY),
+            (SteamError::HttpStatus { status: 400, url: String::new() }, StatusCode::BAD_GATEWAY),
+            (SteamError::deserialize(json, "{"), StatusCode::BAD_GATEWAY),
+            (SteamError::SteamReportedFailure, StatusCode::BAD_GATEWAY),
+            (SteamError::EmptyResponse, StatusCode::BAD_GATEWAY),
+            (SteamError::InvalidRequest("bad url".into()), StatusCode::INTERNAL_SERVER_ERROR),
+        ];
+
+        for (error, status) in cases {
+            assert_eq!(error.status_code(), status, "{error}");
+            assert_eq!(error.error_response().status(), status, "{error}");
+        }
+    }
+
+    #[test]
+    fn rate_limit_passes_retry_after_on() {
+        let limited = SteamError::RateLimited { retry_after: Some(Duration::from_secs(90)) };
+        let response = limited.error_response();
+        assert_eq!(response.headers().get("Retry-After").unwrap(), "90");
+
+        let response = SteamError::RateLimited { retry_after: None }.error_response();
+        assert!(response.headers().get("Retry-After").is_none());
+        let response = SteamError::EmptyResponse.error_response();
+        assert!(response.headers().get("Retry-After").is_none());
+    }
+}
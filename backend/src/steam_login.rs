@@ -1,10 +1,10 @@
 use steam_openid::SteamOpenId;
-use actix_web::{HttpResponse, Responder};
+use actix_web::{HttpResponse, Responder, web};
 use actix_session::Session;
 use std::env;
 
-use steam_market_parser::SteamUser;
-use crate::db::DataBase;
+use steam_market_parser::{SteamClient, SteamUser};
+use crate::db::{DbError, DbPool, UserRepo};
 
 pub async fn steam_login() -> impl Responder {
     let realm = "http://localhost:3000/";
@@ -26,6 +26,7 @@ pub async fn steam_login() -> impl Responder {
 pub async fn steam_return(
     req: actix_web::HttpRequest,
     session: Session,
+    db_pool: web::Data<DbPool>,
 ) -> impl Responder {
     let realm = "http://localhost:3000/";
     let path = "/api/auth/steam/return";
@@ -45,7 +46,7 @@ pub async fn steam_return(
             let steamid_str = steamid.to_string();
             
             // Load profile (ignore errors - don't block login if profile loading fails)
-            if let Ok(steam_user) = load_steam_profile(&steamid_str).await {
+            if let Ok(steam_user) = load_steam_profile(&steamid_str, &db_pool).await {
                 session.insert("steam_user", steam_user).unwrap();
             }
 
@@ -60,15 +61,15 @@ pub async fn steam_return(
 }
 
 ///check status for change!!!
-pub async fn load_steam_profile(steamid: &str) -> Result<SteamUser, Box<dyn std::error::Error>> {
+pub async fn load_steam_profile(steamid: &str, db_pool: &DbPool) -> Result<SteamUser, Box<dyn std::error::Error + Send + Sync>> {
     let api_key = env::var("STEAM_API_KEY")?;
     let url = format!(
         "https://api.steampowered.com/ISteamUser/GetPlayerSummaries/v2/?key={}&steamids={}",
         api_key, steamid
     );
     
-    let response = reqwest::get(url).await?;
-    let text = response.text().await?;
+    let client = SteamClient::shared();
+    let text = client.send_text(client.get(&url)).await?;
     let data: serde_json::Value = serde_json::from_str(&text)?;
     let player = data["response"]["players"][0].clone();
 
@@ -84,9 +85,12 @@ pub async fn load_steam_profile(steamid: &str) -> Result<SteamUser, Box<dyn std:
         status: "online".to_string(),
     };
         
-    let db = DataBase::connect_to_db();
-    db.db_add_steam_user(&steam_user_struct);
-    drop(db);
+    let steam_user_struct = db_pool
+        .run(move |db| {
+            db.db_add_steam_user(&steam_user_struct)?;
+            Ok::<_, DbError>(steam_user_struct)
+        })
+        .await?;
     Ok(steam_user_struct)
 }
 
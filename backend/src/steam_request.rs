@@ -1,21 +1,28 @@
 use serde::de::DeserializeOwned;
 use std::{fmt::Debug};
 
-use reqwest;
-
 // type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
 use crate::{
-    MarketRequest, 
+    MarketSearch, 
     MostRecentItemsRequest,
+    PriceOverviewRequest,
+    OrderBookRequest,
+    SteamClient,
+    SteamError,
     // TradeOfferRequest,
 };
 
-pub trait SteamRequest{}
+pub trait SteamRequest{
+    /// Steam puts `success` into most JSON bodies, even on HTTP 200.
+    fn steam_success(&self) -> bool {
+        true
+    }
+}
 
 pub trait ProcessSteamRequest{
 
-    async fn process_request<T: SteamRequest + Debug + DeserializeOwned>(url: String) -> Result<T, Box<dyn std::error::Error + Send + Sync>>{
+    async fn process_request<T: SteamRequest + Debug + DeserializeOwned>(url: String) -> Result<T, SteamError>{
         // println!("{url}");
         let json_response = send_request(url).await?;
 
@@ -24,13 +31,44 @@ pub trait ProcessSteamRequest{
         Ok(json_response)
     }
 
-    fn custom_market_url(request: MarketRequest) -> String {
+    fn custom_market_url(request: &MarketSearch) -> String {
+
+        let mut url = reqwest::Url::parse("https://steamcommunity.com/market/search/render/")
+            .expect("Market search base url is valid");
+
+        {
+            let mut params = url.query_pairs_mut();
+
+            params
+                .append_pair("appid", &request.appid.to_string())
+                .append_pair("start", &request.start.to_string())
+                .append_pair("count", &request.count.to_string())
+                .append_pair("query", &request.query)
+                .append_pair("search_descriptions", if request.search_descriptions { "1" } else { "0" });
+
+            if let Some((sort, sort_dir)) = request.sort {
+                params
+                    .append_pair("sort_column", sort.as_param())
+                    .append_pair("sort_dir", sort_dir.as_param());
+            }
+
+            if let Some(price_min) = request.price_min {
+                params.append_pair("price_min", &price_min.to_string());
+            }
+
+            if let Some(price_max) = request.price_max {
+                params.append_pair("price_max", &price_max.to_string());
+            }
+
+            for (category, tag) in &request.tags {
+                params.append_pair(&format!("category_{}_{}[]", request.appid, category), tag);
+            }
+
+            params.append_pair("norender", "1");
+        }
 
-        let url = format!("https://steamcommunity.com/market/search/render/
-        ?appid={}&start={}&query={}&sort={}&sort_dir={}&search_descriptions={}&price_min={}&price_max={}&norender=1",
-        request.game, request.page, request.query, request.sort, request.sort_dir, request.search_descriptions, request.price_min, request.price_max);
         // println!("{url}");
-        url
+        url.into()
     }
 
     fn most_recent_items_url(request: MostRecentItemsRequest) -> String{
@@ -40,6 +78,34 @@ pub trait ProcessSteamRequest{
         url
     }
 
+    fn price_overview_url(request: &PriceOverviewRequest) -> String {
+
+        let mut url = reqwest::Url::parse("https://steamcommunity.com/market/priceoverview/")
+            .expect("Price overview base url is valid");
+
+        url.query_pairs_mut()
+            .append_pair("appid", &request.appid.to_string())
+            .append_pair("currency", &request.currency.to_string())
+            .append_pair("market_hash_name", &request.market_hash_name);
+
+        url.into()
+    }
+
+    fn listing_page_url(request: &OrderBookRequest) -> String {
+        format!(
+            "https://steamcommunity.com/market/listings/{}/{}",
+            request.appid,
+            urlencoding::encode(&request.market_hash_name)
+        )
+    }
+
+    fn order_histogram_url(request: &OrderBookRequest, item_nameid: u64) -> String {
+        format!(
+            "https://steamcommunity.com/market/itemordershistogram?country=US&language=english&currency={}&item_nameid={}&two_factor=0&norender=1",
+            request.currency, item_nameid
+        )
+    }
+
     // fn url_send_trade_offer(request: TradeOfferRequest) -> String{
 
     //     let url = format!("https://steamcommunity.com/tradeoffer/new/?partner={}&token={}",
@@ -48,21 +114,110 @@ pub trait ProcessSteamRequest{
     // }
 }
 
-async fn send_request<T: SteamRequest + DeserializeOwned + Debug>(url: String) -> Result<T, Box<dyn std::error::Error + Send + Sync>>{
+async fn send_request<T: SteamRequest + DeserializeOwned + Debug>(url: String) -> Result<T, SteamError>{
     // type SteamResponse: for<'de> Deserialize<'de>;
         
-        let client = reqwest::Client::new();
+        let client = SteamClient::shared();
 
         let respond = client
-            .get(url)
-            .header("Accept", "application/json")
-            .send()
-            .await?
-            .text()
+            .send_text(client.get(&url).header("Accept", "application/json"))
             .await?;
         
         // println!("{:#?}", respond);
-        let respond = serde_json::from_str(&respond)?;
+        let trimmed = respond.trim();
+        if trimmed.is_empty() || trimmed == "null" {
+            return Err(SteamError::EmptyResponse);
+        }
+
+        let respond: T = serde_json::from_str(trimmed)
+            .map_err(|e| SteamError::deserialize(e, trimmed))?;
+
+        if !respond.steam_success() {
+            return Err(SteamError::SteamReportedFailure);
+        }
         
         Ok(respond)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ProcessSteamRequest;
+    use crate::{MarketSearch, PriceOverviewRequest, Sort, SortDirection, parse_price_text};
+
+    #[test]
+    fn market_search_default_url() {
+        assert_eq!(
+            MarketSearch::new(730).url(),
+            "https://steamcommunity.com/market/search/render/?appid=730&start=0&count=10&query=&search_descriptions=0&norender=1"
+        );
+    }
+
+    #[test]
+    fn market_search_full_url() {
+        let url = MarketSearch::new(570)
+            .query("Arcana")
+            .sort(Sort::Price, SortDirection::Desc)
+            .price_range(150, 2500)
+            .search_descriptions(true)
+            .start(20)
+            .count(50)
+            .url();
+
+        assert_eq!(
+            url,
+            "https://steamcommunity.com/market/search/render/?appid=570&start=20&count=50&query=Arcana\
+&search_descriptions=1&sort_column=price&sort_dir=desc&price_min=150&price_max=2500&norender=1"
+        );
+    }
+
+    #[test]
+    fn market_search_encodes_query() {
+        let url = MarketSearch::new(730).query("StatTrak™ AK-47 | Redline & co #1").url();
+
+        assert!(url.contains("&query=StatTrak%E2%84%A2+AK-47+%7C+Redline+%26+co+%231&"), "{url}");
+    }
+
+    #[test]
+    fn market_search_tag_filters() {
+        let url = MarketSearch::new(730)
+            .tag("Exterior", "tag_WearCategory0")
+            .tag("Exterior", "tag_WearCategory1")
+            .tag("Rarity", "tag_Rarity_Ancient_Weapon")
+            .url();
+
+        assert!(url.ends_with(
+            "&category_730_Exterior%5B%5D=tag_WearCategory0\
+&category_730_Exterior%5B%5D=tag_WearCategory1\
+&category_730_Rarity%5B%5D=tag_Rarity_Ancient_Weapon\
+&norender=1"
+        ), "{url}");
+    }
+
+    #[test]
+    fn market_search_clamps_count() {
+        assert!(MarketSearch::new(730).count(500).url().contains("&count=100&"));
+        assert!(MarketSearch::new(730).count(0).url().contains("&count=1&"));
+    }
+
+    #[test]
+    fn price_overview_url_encodes_name() {
+        let request = PriceOverviewRequest::new(730, "AK-47 | Redline (Field-Tested)", 3);
+
+        assert_eq!(
+            PriceOverviewRequest::price_overview_url(&request),
+            "https://steamcommunity.com/market/priceoverview/?appid=730&currency=3\
+&market_hash_name=AK-47+%7C+Redline+%28Field-Tested%29"
+        );
+    }
+
+    #[test]
+    fn price_text_to_cents() {
+        assert_eq!(parse_price_text("$1,234.56"), Some(123456));
+        assert_eq!(parse_price_text("1.234,56€"), Some(123456));
+        assert_eq!(parse_price_text("12,3 pуб."), Some(1230));
+        assert_eq!(parse_price_text("1\u{a0}234,50 pуб."), Some(123450));
+        assert_eq!(parse_price_text("$1,234"), Some(123400));
+        assert_eq!(parse_price_text("0,03€"), Some(3));
+        assert_eq!(parse_price_text("--"), None);
+    }
+}
@@ -1,13 +1,24 @@
 use actix_web_actors::ws;
 use actix::Addr;
 use actix_web::{Error, HttpRequest, Result, HttpResponse, web};
+use actix_session::Session;
 use actix::prelude::*;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
 use steam_market_parser::{
+    SteamUser,
+    ChatLine,
     ChatQuery,
+    ClientMessage,
+    OfferStatus,
+    OfferStepKind,
+    ServerMessage,
+    WsErrorCode,
 };
 
+use crate::websocket::{client_message, open_connection, start_heartbeat, unsupported_message, WsClient};
+use crate::ws_connections::{WsConnection, WsConnections, WsEndpoint};
+
 #[derive(Hash, Eq, PartialEq, Clone, Debug)]
 pub struct RoomId {
     buyer_steamid: String,
@@ -21,6 +32,7 @@ impl RoomId {
 }
 
 pub struct WsSession {
+    connection: WsConnection,
     room: RoomId,
     hub: Addr<ChatHub>,
     offer_id: Option<String>,
@@ -40,10 +52,19 @@ impl Handler<WsText> for WsSession {
     }
 }
 
+impl WsClient for WsSession {
+    fn connection(&mut self) -> &mut WsConnection {
+        &mut self.connection
+    }
+}
+
 impl Actor for WsSession {
     type Context = ws::WebsocketContext<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.text(ServerMessage::hello().to_json());
+        start_heartbeat(self, ctx);
+
         self.hub.do_send(Join {
             room: self.room.clone(),
             addr: ctx.address(),
@@ -61,201 +82,128 @@ impl Actor for WsSession {
 }
 
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession {
-    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, _: &mut Self::Context) {
-        let Ok(ws::Message::Text(text)) = msg else { return };
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        let Some(message) = client_message(self, msg, ctx) else { return };
 
-        let parsed: serde_json::Value = match serde_json::from_str(&text) {
-            Ok(v) => v,
-            Err(_) => return,
-        };
-
-        let msg_type = parsed.get("type").and_then(|v| v.as_str()).unwrap_or("");
-
-        match msg_type {
-            "chat" | "system" => {
-                let body = parsed
-                    .get("text")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .trim()
-                    .to_string();
-
-                if body.is_empty() {
-                    return;
-                }
+        match message {
+            ClientMessage::Chat { text } => self.say(ServerMessage::Chat, text.trim()),
+            ClientMessage::System { text } => self.say(ServerMessage::System, text.trim()),
 
-                self.hub.do_send(Broadcast {
+            ClientMessage::OfferItems { items } => {
+                self.hub.do_send(BroadcastOfferItems {
                     room: self.room.clone(),
-                    msg_type: msg_type.to_string(),
                     from_role: self.role.clone(),
-                    text: body,
+                    items,
                 });
             }
 
-            "offer_items" => {
-                self.hub.do_send(Broadcast {
-                    room: self.room.clone(),
-                    msg_type: "offer_items".to_string(),
-                    from_role: self.role.clone(),
-                    text: parsed.to_string(), // full JSON
-                });
-            }
-
-            "offer_log" => {
-
-                println!("{parsed}");
-                self.hub.do_send(Broadcast {
-                    room: self.room.clone(),
-                    msg_type: "offer_log".to_string(),
-                    from_role: self.role.clone(),
-                    text: parsed.to_string(), // full JSON
-                });
+            //Passed on whole, the pages read the message back out of `text`
+            message @ ClientMessage::OfferLog { .. } => {
+                self.say(ServerMessage::OfferLog, &serde_json::to_string(&message).unwrap_or_default());
             }
 
-            "item_asking" => {
-                let kekw = parsed.to_string();
-                println!("{kekw}");
-                self.hub.do_send(Broadcast {
-                    room: self.room.clone(),
-                    msg_type: "item_asking".to_string(),
-                    from_role: self.role.clone(),
-                    text: parsed.to_string(), // full JSON
-                });
+            message @ ClientMessage::ItemAsking { .. } => {
+                self.say(ServerMessage::ItemAsking, &serde_json::to_string(&message).unwrap_or_default());
             }
 
-            "set_offer" => {
-                let offer_id = parsed
-                    .get("offer_id")
-                    .and_then(|v| v.as_str())
-                    .map(|s| s.trim().to_string())
-                    .filter(|s| !s.is_empty());
+            ClientMessage::SetOffer { offer_id } => {
+                let offer_id = offer_id.trim().to_string();
+                if offer_id.is_empty() {
+                    ctx.text(ServerMessage::error(WsErrorCode::InvalidMessage, "offer_id is empty").to_json());
+                    return;
+                }
 
-                self.offer_id = offer_id.clone();
+                self.offer_id = Some(offer_id.clone());
 
-                self.hub.do_send(Broadcast {
-                    room: self.room.clone(),
-                    msg_type: "offer_system".to_string(),
-                    from_role: self.role.clone(),
-                    text: self.offer_id.clone().unwrap(),
-                });
+                self.say(ServerMessage::OfferSystem, &offer_id);
 
                 self.hub.do_send(OfferState {
                     room: self.room.clone(),
-                    offer_id,
-                    msg_type: "set_offer".to_string(),
-                    offer_accepted: false,
-                    offer_dirty: true,
-                    offer_paid: false,
-                    offer_send: false,
-                    // text: "set_offer".to_string(),
+                    message: ServerMessage::SetOffer,
+                    status: OfferStatus { offer_id: Some(offer_id), offer_dirty: true, ..Default::default() },
                 });
-
             }
 
-            "send_offer" => {
-                
+            ClientMessage::SendOffer => {
                 self.hub.do_send(OfferState {
                     room: self.room.clone(),
-                    offer_id: self.offer_id.clone(),
-                    msg_type: "send_offer".to_string(),
-                    offer_accepted: false,
-                    offer_dirty: false,
-                    offer_paid: false,
-                    offer_send: true,
-                    // text: parsed.to_string(),
+                    message: ServerMessage::SendOffer,
+                    status: OfferStatus { offer_id: self.offer_id.clone(), offer_send: true, ..Default::default() },
                 });
             }
 
-            "accept_offer" => {
-
+            ClientMessage::AcceptOffer => {
                 self.hub.do_send(OfferState {
                     room: self.room.clone(),
-                    offer_id: self.offer_id.clone(),
-                    msg_type: "accept_offer".to_string(),
-                    offer_accepted: true,
-                    offer_dirty: false,
-                    offer_paid: false,
-                    offer_send: true,
-                    // text: parsed.to_string(),
+                    message: ServerMessage::AcceptOffer,
+                    status: OfferStatus {
+                        offer_id: self.offer_id.clone(),
+                        offer_send: true,
+                        offer_accepted: true,
+                        ..Default::default()
+                    },
                 });
             }
 
-            "paid_offer" => {
-
+            ClientMessage::PaidOffer => {
                 self.hub.do_send(OfferState {
                     room: self.room.clone(),
-                    offer_id: self.offer_id.clone(),
-                    msg_type: "pay_offer".to_string(),
-                    offer_accepted: true,
-                    offer_dirty: false,
-                    offer_paid: true,
-                    offer_send: true,
-                    // text: parsed.to_string(),
+                    message: ServerMessage::PayOffer,
+                    status: OfferStatus {
+                        offer_id: self.offer_id.clone(),
+                        offer_send: true,
+                        offer_accepted: true,
+                        offer_paid: true,
+                        ..Default::default()
+                    },
                 });
 
-                self.hub.do_send(Broadcast {
-                    room: self.room.clone(),
-                    msg_type: "system".to_string(),
-                    from_role: self.role.clone(),
-                    text: "Offer successfully paid".to_string(),
-                });
-
-                self.hub.do_send(Broadcast {
-                    room: self.room.clone(),
-                    msg_type: "reveal_send_offer".to_string(),
-                    from_role: self.role.clone(),
-                    text: "Trader is sending offer".to_string(),
-                });
+                self.say(ServerMessage::System, "Offer successfully paid");
+                self.say(ServerMessage::RevealSendOffer, "Trader is sending offer");
             }
 
-            "clear_offer" => {
+            ClientMessage::ClearOffer => {
                 self.offer_id = None;
 
                 self.hub.do_send(OfferState {
                     room: self.room.clone(),
-                    offer_id: None,
-                    msg_type: "clear_offer".to_string(),
-                    offer_accepted: false,
-                    offer_dirty: false,
-                    offer_paid: false,
-                    offer_send: false,
-                    // text: parsed.to_string(),
+                    message: ServerMessage::ClearOffer,
+                    status: OfferStatus::default(),
                 });
             }
 
-            "offer_step_connecting" => {
-                self.hub.do_send(OfferStep {
-                    room: self.room.clone(),
-                    msg_type: "offer_step".to_string(),
-                    from_role: self.role.clone(),
-                    step_type: "connect".to_string(),
-                    text: "Offer stage".to_string(),
-                });
-            }
+            ClientMessage::OfferStepConnecting => self.offer_step(OfferStepKind::Connect, "Offer stage"),
+            ClientMessage::OfferStepAccepting => self.offer_step(OfferStepKind::Accept, "Accepted"),
+            ClientMessage::OfferStepPaying => self.offer_step(OfferStepKind::Pay, "Buyer is paying for offer"),
 
-            "offer_step_accepting" => {
-                self.hub.do_send(OfferStep {
-                    room: self.room.clone(),
-                    msg_type: "offer_step".to_string(),
-                    from_role: self.role.clone(),
-                    step_type: "accept".to_string(),
-                    text: "Accepted".to_string(),
-                });
-            }
-
-            "offer_step_paying" => {
-                self.hub.do_send(OfferStep {
-                    room: self.room.clone(),
-                    msg_type: "offer_step".to_string(),
-                    from_role: self.role.clone(),
-                    step_type: "pay".to_string(),
-                    text: "Buyer is paying for offer".to_string(),
-                });
-            }
+            //Hello never gets here, client_message answers it
+            ClientMessage::Hello { .. } | ClientMessage::Filters(_) => unsupported_message(ctx),
+        }
+    }
+}
 
-            _ => {}
+impl WsSession {
+    /// A line from this side to the whole room. Empty lines are dropped.
+    fn say(&self, line: fn(ChatLine) -> ServerMessage, text: &str) {
+        if text.is_empty() {
+            return;
         }
-        
+
+        self.hub.do_send(Broadcast {
+            room: self.room.clone(),
+            line,
+            from_role: self.role.clone(),
+            text: text.to_string(),
+        });
+    }
+
+    fn offer_step(&self, step: OfferStepKind, text: &str) {
+        self.hub.do_send(OfferStep {
+            room: self.room.clone(),
+            from_role: self.role.clone(),
+            step,
+            text: text.to_string(),
+        });
     }
 }
 
@@ -275,22 +223,30 @@ struct Leave {
     addr: Addr<WsSession>,
 }
 
+/// A chat line to everyone in the room, `line` picks its type.
 #[derive(Message)]
 #[rtype(result = "()")]
 struct Broadcast {
     room: RoomId,
-    msg_type: String,   // "chat" | "system"
+    line: fn(ChatLine) -> ServerMessage,
     from_role: String,  // "buyer" | "trader" | "system"
     text: String,
 }
 
+#[derive(Message)]
+#[rtype(result = "()")]
+struct BroadcastOfferItems {
+    room: RoomId,
+    from_role: String,
+    items: Vec<serde_json::Value>,
+}
+
 #[derive(Message)]
 #[rtype(result = "()")]
 struct OfferStep {
     room: RoomId,
-    msg_type: String,   // "chat" | "system"
     from_role: String,  // "buyer" | "trader" | "system"
-    step_type: String,
+    step: OfferStepKind,
     text: String,
 }
 
@@ -314,34 +270,30 @@ impl Handler<PaymentSucceeded> for ChatHub {
         state.offer_id = Some(msg.offer_id.clone());
 
         // 1) broadcast offer_paid state
-        let payload = serde_json::json!({
-            "type": "pay_offer", // <-- was "paid_offer"
-            "offer_id": msg.offer_id,
-            "offer_dirty": false,
-            "offer_send": true,
-            "offer_accepted": true,
-            "offer_paid": true
-        }).to_string();
-        
+        let payload = ServerMessage::PayOffer(OfferStatus {
+            offer_id: Some(msg.offer_id),
+            offer_dirty: false,
+            offer_send: true,
+            offer_accepted: true,
+            offer_paid: true,
+        }).to_json();
 
         for addr in state.clients.keys() {
             addr.do_send(WsText(payload.clone()));
         }
 
         // 2) broadcast system texts
-        let system1 = serde_json::json!({
-            "type": "system",
-            "from_role": "system",
-            "offer_id": state.offer_id,
-            "text": "Offer successfully paid"
-        }).to_string();
-
-        let system2 = serde_json::json!({
-            "type": "reveal_send_offer",
-            "from_role": "system",
-            "offer_id": state.offer_id,
-            "text": "Trader is sending offer"
-        }).to_string();
+        let system1 = ServerMessage::System(ChatLine {
+            from_role: "system".into(),
+            offer_id: state.offer_id.clone(),
+            text: "Offer successfully paid".into(),
+        }).to_json();
+
+        let system2 = ServerMessage::RevealSendOffer(ChatLine {
+            from_role: "system".into(),
+            offer_id: state.offer_id.clone(),
+            text: "Trader is sending offer".into(),
+        }).to_json();
 
         for addr in state.clients.keys() {
             addr.do_send(WsText(system1.clone()));
@@ -377,13 +329,12 @@ impl ChatHub {
             if role == "trader" { trader_present = true; }
         }
     
-        let payload = serde_json::json!({
-            "type": "presence",
-            "count": state.clients.len(),
-            "buyer_present": buyer_present,
-            "trader_present": trader_present,
-            "offer_id": state.offer_id, // optional: expose to clients
-        }).to_string();
+        let payload = ServerMessage::Presence {
+            count: state.clients.len(),
+            buyer_present,
+            trader_present,
+            offer_id: state.offer_id.clone(), // optional: expose to clients
+        }.to_json();
     
         for addr in state.clients.keys() {
             addr.do_send(WsText(payload.clone()));
@@ -406,13 +357,11 @@ impl Handler<Join> for ChatHub {
 
         // ✅ send offer_id to ONLY the newly joined client as a system message
         if let Some(ref offer_id) = state.offer_id {
-            let payload = serde_json::json!({
-                "type": "offer_system",
-                "from_role": "offer_system",
-                "offer_id": offer_id,
-                "text": format!("{offer_id}")
-            })
-            .to_string();
+            let payload = ServerMessage::OfferSystem(ChatLine {
+                from_role: "offer_system".into(),
+                offer_id: Some(offer_id.clone()),
+                text: offer_id.clone(),
+            }).to_json();
 
             msg.addr.do_send(WsText(payload));
         }
@@ -445,24 +394,29 @@ impl Handler<Broadcast> for ChatHub {
     fn handle(&mut self, msg: Broadcast, _: &mut Context<Self>) {
         let Some(state) = self.rooms.get(&msg.room) else { return };
 
-        let payload = if msg.msg_type == "offer_items" {
-            let v: serde_json::Value = serde_json::from_str(&msg.text).unwrap();
-            serde_json::json!({
-                "type": "offer_items",
-                "from_role": msg.from_role,
-                "offer_id": state.offer_id, // <-- now available here
-                "items": v.get("items").expect("Handler <Broadcast>: no items in the offer!")
-            })
-        } else {
-            serde_json::json!({
-                "type": msg.msg_type,
-                "from_role": msg.from_role,
-                "offer_id": state.offer_id, // <-- now available here
-                "text": msg.text
-            })
-        };
+        let payload = (msg.line)(ChatLine {
+            from_role: msg.from_role,
+            offer_id: state.offer_id.clone(), // <-- now available here
+            text: msg.text,
+        }).to_json();
+
+        for addr in state.clients.keys() {
+            addr.do_send(WsText(payload.clone()));
+        }
+    }
+}
+
+impl Handler<BroadcastOfferItems> for ChatHub {
+    type Result = ();
+
+    fn handle(&mut self, msg: BroadcastOfferItems, _: &mut Context<Self>) {
+        let Some(state) = self.rooms.get(&msg.room) else { return };
 
-        let payload = payload.to_string();
+        let payload = ServerMessage::OfferItems {
+            from_role: msg.from_role,
+            offer_id: state.offer_id.clone(),
+            items: msg.items,
+        }.to_json();
 
         for addr in state.clients.keys() {
             addr.do_send(WsText(payload.clone()));
@@ -476,15 +430,12 @@ impl Handler<OfferStep> for ChatHub {
     fn handle(&mut self, msg: OfferStep, _: &mut Context<Self>) {
         let Some(state) = self.rooms.get(&msg.room) else { return };
 
-        let payload = serde_json::json!({
-            "type": msg.msg_type,
-            "from_role": msg.from_role,
-            "offer_id": state.offer_id,
-            "step": msg.step_type,
-            "text": msg.text
-        });
-
-        let payload = payload.to_string();
+        let payload = ServerMessage::OfferStep {
+            from_role: msg.from_role,
+            offer_id: state.offer_id.clone(),
+            step: msg.step,
+            text: msg.text,
+        }.to_json();
 
         for addr in state.clients.keys() {
             addr.do_send(WsText(payload.clone()));
@@ -496,13 +447,8 @@ impl Handler<OfferStep> for ChatHub {
 #[rtype(result = "()")]
 struct OfferState {
     room: RoomId,
-    msg_type: String,
-    offer_id: Option<String>,
-    offer_accepted: bool,
-    offer_dirty: bool,
-    offer_paid: bool,
-    offer_send: bool,
-    // text: String,
+    message: fn(OfferStatus) -> ServerMessage,
+    status: OfferStatus,
 }
 
 impl Handler<OfferState> for ChatHub {
@@ -511,18 +457,9 @@ impl Handler<OfferState> for ChatHub {
     fn handle(&mut self, msg: OfferState, _: &mut Context<Self>) {
         let Some(state) = self.rooms.get_mut(&msg.room) else { return };
 
-        state.offer_id = msg.offer_id.clone();
+        state.offer_id = msg.status.offer_id.clone();
 
-        let payload = serde_json::json!({
-            "type": msg.msg_type,
-            "offer_id": msg.offer_id,
-            "offer_dirty": msg.offer_dirty,
-            "offer_send": msg.offer_send,
-            "offer_accepted": msg.offer_accepted,
-            "offer_paid": msg.offer_paid,
-            // "text": msg.text,
-        })
-        .to_string();
+        let payload = (msg.message)(msg.status).to_json();
 
         for addr in state.clients.keys() {
             addr.do_send(WsText(payload.clone()));
@@ -539,9 +476,17 @@ pub async fn ws_chat_handler(
     req: HttpRequest,
     stream: web::Payload,
     query: web::Query<ChatQuery>,
+    session: Session,
     hub: web::Data<Addr<ChatHub>>,
+    connections: web::Data<WsConnections>,
 ) -> Result<HttpResponse, Error> {
 
+    let steamid = session.get::<SteamUser>("steam_user")?.map(|user| user.steamid);
+    let connection = match open_connection(&req, connections, WsEndpoint::Chat, steamid) {
+        Ok(connection) => connection,
+        Err(response) => return Ok(response),
+    };
+
     let room = RoomId {
         buyer_steamid: query.buyer.clone(),
         trader_steamid: query.trader.clone(),
@@ -553,12 +498,13 @@ pub async fn ws_chat_handler(
         _ => "buyer", // safe default
     }.to_string();
 
-    let session = WsSession {
+    let ws_session = WsSession {
+        connection,
         room,
         hub: hub.get_ref().clone(),
         offer_id: None,
         role,
     };
 
-    ws::start(session, &req, stream)
+    ws::start(ws_session, &req, stream)
 }
\ No newline at end of file
@@ -4,21 +4,37 @@ use actix_web_actors::ws;
 use actix_web::{Error, HttpRequest, Result, HttpResponse, web};
 use actix::prelude::*;
 use actix_session::Session;
+use tokio::sync::broadcast::{self, error::RecvError};
 use steam_market_parser::{
     UserProfileAds,
-    CardAppearingFilter
+    CardAppearingFilter,
+    CurrencyRates,
+    ClientMessage,
+    FeedFilter,
+    FeedMatcher,
+    Notification,
+    ServerMessage,
+    SteamUser,
+    WsErrorCode,
+    WS_PROTOCOL_VERSION
 };
 
+use crate::db::{AlertRepo, DbError, DbPool, FeedRepo, UserRepo};
+use crate::ws_connections::{parse_client_ip, WsConnection, WsConnections, WsEndpoint, WsRejected};
 use crate::{
     UserAdState, 
     FeedItemsState, 
+    AlertState, 
     MostRecent, 
     MostRecentItemsFilter
 };
 
-#[derive(serde::Serialize, Clone)]
+#[derive(serde::Serialize, Clone, Default)]
 pub struct BroadcastPayload {
     pub items: Vec<MostRecent>,
+    //Latest known rates, each actor converts into its user's currency
+    #[serde(skip)]
+    pub rates: CurrencyRates,
 }
 
 #[derive(serde::Serialize, Clone)]
@@ -26,27 +42,135 @@ pub struct AdsBroadcastPayload {
     pub user_ads: VecDeque<UserProfileAds>,
 }
 
+#[derive(serde::Serialize, Clone)]
+pub struct AlertsBroadcastPayload {
+    pub notifications: Vec<Notification>,
+}
+
+//Unread notifications sent right after connecting
+const ALERT_BACKLOG: usize = 50;
+//Most listings a lagged feed subscriber gets back from item_feed, the newest ones
+const FEED_RESYNC_LIMIT: usize = 500;
+
 struct WsActor {
-    state: web::Data<FeedItemsState>,
-    item_card_filters: MostRecentItemsFilter,
+    connection: WsConnection,
+    db_pool: web::Data<DbPool>,
+    //Taken in started(), subscribed before the snapshot was read so no batch falls in between
+    rx: Option<broadcast::Receiver<BroadcastPayload>>,
+    snapshot: BroadcastPayload,
+    //Highest feed id sent or skipped so far, batches overlapping the snapshot or a resync start past it
+    seen_id: i64,
+    filter: FeedMatcher,
     card_filters: CardAppearingFilter,
+    //Display currency from the user's profile, None keeps the feed currency
+    currency: Option<u32>,
 }
 
 struct AdWSActor {
+    connection: WsConnection,
     state: web::Data<UserAdState>,
+    rx: Option<broadcast::Receiver<AdsBroadcastPayload>>,
+    snapshot: VecDeque<UserProfileAds>,
+}
+
+/// Saved search alerts of one logged-in user, open independent of the feed.
+struct AlertWsActor {
+    connection: WsConnection,
+    rx: Option<broadcast::Receiver<AlertsBroadcastPayload>>,
+    steamid: String,
+    backlog: Vec<Notification>,
+    //Highest notification id sent, the backlog and the first broadcasts can overlap
+    seen_id: i64,
+}
+
+impl WsClient for WsActor {
+    fn connection(&mut self) -> &mut WsConnection {
+        &mut self.connection
+    }
+}
+
+impl WsClient for AdWSActor {
+    fn connection(&mut self) -> &mut WsConnection {
+        &mut self.connection
+    }
+}
+
+impl WsClient for AlertWsActor {
+    fn connection(&mut self) -> &mut WsConnection {
+        &mut self.connection
+    }
+}
+
+impl Actor for AlertWsActor {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.text(ServerMessage::hello().to_json());
+        start_heartbeat(self, ctx);
+
+        let backlog = std::mem::take(&mut self.backlog);
+        if let Some(last) = backlog.iter().map(|notification| notification.id).max() {
+            self.seen_id = last;
+        }
+        if !backlog.is_empty() {
+            send_notifications(ctx, backlog);
+        }
+
+        // Forward updates via actor messages
+        let Some(mut rx) = self.rx.take() else { return };
+        let addr = ctx.address();
+
+        tokio::spawn(async move {
+            while addr.connected() {
+                match rx.recv().await {
+                    Ok(payload) => addr.do_send(BroadcastAlerts(payload)),
+                    //Skipped ones are still unread in GET /api/notifications
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for AlertWsActor {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        //Push only, notifications are marked read over POST /api/notifications/read
+        if client_message(self, msg, ctx).is_some() {
+            unsupported_message(ctx);
+        }
+    }
 }
 
 impl Actor for AdWSActor {
     type Context = ws::WebsocketContext<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
-        // Subscribe to broadcast channel and forward updates via actor messages
-        let mut rx = self.state.ads_broadcaster.subscribe();
+        ctx.text(ServerMessage::hello().to_json());
+        start_heartbeat(self, ctx);
+
+        let user_ads = std::mem::take(&mut self.snapshot);
+        if !user_ads.is_empty() {
+            ctx.text(ServerMessage::Ads { user_ads }.to_json());
+        }
+
+        // Forward updates via actor messages
+        let Some(mut rx) = self.rx.take() else { return };
+        let state = self.state.clone();
         let addr = ctx.address();
 
         tokio::spawn(async move {
-            while let Ok(payload) = rx.recv().await {
-                let _ = addr.do_send(BroadcastAds(payload));
+            while addr.connected() {
+                match rx.recv().await {
+                    Ok(payload) => addr.do_send(BroadcastAds(payload)),
+                    //Every payload is the whole queue, skip the stale ones and send the current
+                    Err(RecvError::Lagged(_)) => {
+                        rx = rx.resubscribe();
+                        let user_ads = state.user_ads.lock().await.queue.clone();
+                        addr.do_send(BroadcastAds(AdsBroadcastPayload { user_ads }));
+                    }
+                    Err(RecvError::Closed) => break,
+                }
             }
         });
     }
@@ -56,80 +180,226 @@ impl Actor for WsActor {
     type Context = ws::WebsocketContext<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
-        // Subscribe to broadcast channel and forward updates via actor messages
-        let mut rx = self.state.broadcaster.subscribe();
+        ctx.text(ServerMessage::hello().to_json());
+        start_heartbeat(self, ctx);
+
+        //The last poll's listings right away instead of at the next one
+        let snapshot = std::mem::take(&mut self.snapshot);
+        self.send_items(ctx, snapshot);
+
+        // Forward updates via actor messages
+        let Some(mut rx) = self.rx.take() else { return };
         let addr = ctx.address();
 
         tokio::spawn(async move {
-            while let Ok(payload) = rx.recv().await {
-                let _ = addr.do_send(BroadcastItems(payload));
+            while addr.connected() {
+                match rx.recv().await {
+                    Ok(payload) => addr.do_send(BroadcastItems(payload)),
+                    //The receiver carries on from the oldest batch it still has,
+                    //what it dropped comes back from item_feed
+                    Err(RecvError::Lagged(skipped)) => addr.do_send(FeedLagged(skipped)),
+                    Err(RecvError::Closed) => break,
+                }
             }
         });
     }
 }
 
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for AdWSActor {
-    fn handle(&mut self, _msg: Result<ws::Message, ws::ProtocolError>, _ctx: &mut Self::Context) {
-        //Put a handler here
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        //Push only for now
+        if client_message(self, msg, ctx).is_some() {
+            unsupported_message(ctx);
+        }
     }
 }
 
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsActor {
-    fn handle(&mut self, _msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
-        if let Ok(ws::Message::Text(text)) = _msg {
-            if let Ok(v) = serde_json::from_str::<serde_json::Value>(&text) {
-                if v["type"] == "filters" {
-                    self.item_card_filters = MostRecentItemsFilter {
-                        appid: v["appid"].as_str().unwrap_or("").into(),
-                        price_min: v["price_min"].as_str().unwrap_or("0").into(),
-                        price_max: v["price_max"].as_str().unwrap_or("999999").into(),
-                        query: v["query"].as_str().unwrap_or("").into(),
-                    };
-
-                    if let Some(ca) = v["card_appearing"].as_str() {
-                        self.card_filters.card_appearing = ca.to_string();
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match client_message(self, msg, ctx) {
+            Some(ClientMessage::Filters(message)) => {
+                //A rejected filter leaves the previous one in place
+                let matcher = match message.filter.into_filter().compile() {
+                    Ok(matcher) => matcher,
+                    Err(e) => {
+                        ctx.text(ServerMessage::error(WsErrorCode::InvalidFilter, e.to_string()).to_json());
+                        return;
                     }
-                
-                    println!(
-                        "WS FILTERS UPDATED → {:?}, card_appearing={}",
-                        self.item_card_filters,
-                        self.card_filters.card_appearing
-                    );
-
-                    ctx.text(serde_json::json!({
-                        "type": "filters",
-                        "card_appearing": self.card_filters.card_appearing,
-                        "appid": self.item_card_filters.appid,
-                        "price_min": self.item_card_filters.price_min,
-                        "price_max": self.item_card_filters.price_max,
-                        "query": self.item_card_filters.query,
-                      }).to_string());
-                      
+                };
+                self.filter = matcher;
+                if let Some(card_appearing) = message.card_appearing {
+                    self.card_filters.card_appearing = card_appearing;
                 }
+
+                println!(
+                    "WS FILTERS UPDATED → {:?}, card_appearing={}",
+                    self.filter.filter(),
+                    self.card_filters.card_appearing
+                );
+
+                ctx.text(ServerMessage::Filters {
+                    card_appearing: self.card_filters.card_appearing.clone(),
+                    filter: self.filter.filter().clone(),
+                }.to_json());
             }
+            Some(_) => unsupported_message(ctx),
+            None => {}
+        }
+    }
+}
+
+/// A websocket actor with its slot in WsConnections.
+pub(crate) trait WsClient: Actor<Context = ws::WebsocketContext<Self>> {
+    fn connection(&mut self) -> &mut WsConnection;
+}
+
+/// Counts a socket before the upgrade. 429 when its address or user is at the cap.
+/// Behind a trusted proxy the address comes from its forwarding headers.
+pub(crate) fn open_connection(
+    req: &HttpRequest,
+    connections: web::Data<WsConnections>,
+    endpoint: WsEndpoint,
+    steamid: Option<String>,
+) -> Result<WsConnection, HttpResponse> {
+    let ip = if connections.config().trust_proxy {
+        req.connection_info().realip_remote_addr().and_then(parse_client_ip)
+    } else {
+        req.peer_addr().map(|addr| addr.ip())
+    };
+
+    connections.into_inner().open(endpoint, ip, steamid).map_err(|rejected| {
+        let message = match rejected {
+            WsRejected::TooManyForIp => "Too many open connections from this address",
+            WsRejected::TooManyForUser => "Too many open connections for this account",
+        };
+        HttpResponse::TooManyRequests().json(serde_json::json!({
+            "error": "too_many_connections",
+            "message": message,
+        }))
+    })
+}
+
+/// Pings on the configured interval and drops the client once it has been quiet
+/// for longer than the timeout, so half-open sockets don't keep their subscriptions.
+pub(crate) fn start_heartbeat<A: WsClient>(act: &mut A, ctx: &mut ws::WebsocketContext<A>) {
+    let interval = act.connection().config().heartbeat_interval;
+
+    ctx.run_interval(interval, |act, ctx| {
+        if act.connection().timed_out() {
+            ctx.stop();
+            return;
+        }
+        ctx.ping(b"");
+    });
+}
+
+/// Reads a client frame. Pings, Close, malformed input and a hello with another version
+/// are answered here, the socket only sees messages left to act on.
+pub(crate) fn client_message<A: WsClient>(
+    act: &mut A,
+    msg: Result<ws::Message, ws::ProtocolError>,
+    ctx: &mut ws::WebsocketContext<A>,
+) -> Option<ClientMessage> {
+    let msg = match msg {
+        Ok(msg) => msg,
+        Err(e) => {
+            eprintln!("Websocket protocol error: {e}");
+            ctx.stop();
+            return None;
+        }
+    };
+    act.connection().touch();
+
+    let text = match msg {
+        ws::Message::Text(text) => text,
+        ws::Message::Binary(_) => {
+            ctx.text(ServerMessage::error(WsErrorCode::InvalidMessage, "expected a JSON text frame").to_json());
+            return None;
+        }
+        ws::Message::Ping(bytes) => {
+            ctx.pong(&bytes);
+            return None;
+        }
+        ws::Message::Close(reason) => {
+            ctx.close(reason);
+            ctx.stop();
+            return None;
+        }
+        ws::Message::Pong(_) | ws::Message::Continuation(_) | ws::Message::Nop => return None,
+    };
+
+    match ClientMessage::parse(&text) {
+        Ok(ClientMessage::Hello { version }) if version != WS_PROTOCOL_VERSION => {
+            ctx.text(ServerMessage::error(
+                WsErrorCode::UnsupportedVersion,
+                format!("protocol version {version} is not supported, this server speaks {WS_PROTOCOL_VERSION}"),
+            ).to_json());
+            ctx.close(Some(ws::CloseReason {
+                code: ws::CloseCode::Policy,
+                description: Some("unsupported protocol version".into()),
+            }));
+            ctx.stop();
+            None
+        }
+        Ok(ClientMessage::Hello { .. }) => None,
+        Ok(message) => Some(message),
+        Err(e) => {
+            ctx.text(ServerMessage::error(WsErrorCode::InvalidMessage, e.to_string()).to_json());
+            None
         }
     }
 }
 
+pub(crate) fn unsupported_message<A>(ctx: &mut ws::WebsocketContext<A>)
+where
+    A: Actor<Context = ws::WebsocketContext<A>>,
+{
+    ctx.text(ServerMessage::error(WsErrorCode::UnsupportedMessage, "this socket doesn't take that message").to_json());
+}
+
 struct BroadcastItems(BroadcastPayload);
+//Batches the forwarding task's receiver skipped
+struct FeedLagged(u64);
 struct BroadcastAds(AdsBroadcastPayload);
+struct BroadcastAlerts(AlertsBroadcastPayload);
 
 impl Message for BroadcastItems {
     type Result = ();
 }
 
+impl Message for FeedLagged {
+    type Result = ();
+}
+
 impl Message for BroadcastAds {
     type Result = ();
 }
 
-#[derive(serde::Serialize)]
-struct WsResponse {
-    items: Vec<MostRecent>,
+impl Message for BroadcastAlerts {
+    type Result = ();
+}
+
+fn send_notifications(ctx: &mut ws::WebsocketContext<AlertWsActor>, notifications: Vec<Notification>) {
+    ctx.text(ServerMessage::Notifications { notifications }.to_json());
 }
 
-#[derive(serde::Serialize)]
-struct WsAdResponse {
-    user_ads: VecDeque<UserProfileAds>,
+impl Handler<BroadcastAlerts> for AlertWsActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: BroadcastAlerts, ctx: &mut Self::Context) {
+        //Every batch goes to every connection, each keeps its own user's
+        let own: Vec<Notification> = msg.0.notifications
+            .into_iter()
+            .filter(|notification| notification.steamid == self.steamid && notification.id > self.seen_id)
+            .collect();
+        if let Some(last) = own.iter().map(|notification| notification.id).max() {
+            self.seen_id = last;
+        }
+
+        if !own.is_empty() {
+            send_notifications(ctx, own);
+        }
+    }
 }
 
 impl Handler<BroadcastAds> for AdWSActor {
@@ -139,13 +409,34 @@ impl Handler<BroadcastAds> for AdWSActor {
         // apply per-user filters
         let user_ads = msg.0.user_ads;
     
-        let response = WsAdResponse {
-            user_ads
-        };
-        // println!("Broadcast → sending {} items", response.items.len());
+        ctx.text(ServerMessage::Ads { user_ads }.to_json());
+    }
+}
 
-        if let Ok(json) = serde_json::to_string(&response) {
-            ctx.text(json);
+impl WsActor {
+    /// Listings of a batch past `seen_id`, priced in the user's currency and filtered.
+    fn send_items(&mut self, ctx: &mut ws::WebsocketContext<Self>, batch: BroadcastPayload) {
+        let fresh: Vec<&MostRecent> = batch.items
+            .iter()
+            .filter(|item| item.id as i64 > self.seen_id)
+            .collect();
+        if let Some(last) = fresh.iter().map(|item| item.id as i64).max() {
+            self.seen_id = last;
+        }
+
+        // apply per-user filters
+        if self.card_filters.card_appearing == "stores_items" || self.card_filters.card_appearing == "items" {
+            let filtered_items: Vec<MostRecent> = fresh
+                .into_iter()
+                .map(|item| item.in_currency(self.currency, &batch.rates))
+                .filter(|item| self.filter.matches(item))
+                .collect();
+        
+            // println!("Broadcast → sending {} items", filtered_items.len());
+            ctx.text(ServerMessage::Items { items: filtered_items }.to_json());
+        }
+        else {
+            ctx.text(ServerMessage::Items { items: Vec::new() }.to_json());
         }
     }
 }
@@ -153,67 +444,30 @@ impl Handler<BroadcastAds> for AdWSActor {
 impl Handler<BroadcastItems> for WsActor {
     type Result = ();
 
-    ///add filter by the game!!!
     fn handle(&mut self, msg: BroadcastItems, ctx: &mut Self::Context) {
-        // apply per-user filters
-        if self.card_filters.card_appearing == "stores_items".to_string() || self.card_filters.card_appearing == "items".to_string() {
-            let filtered_items: Vec<MostRecent> = msg.0.items
-                .iter()
-                .filter(|item| {
-                    // skip items without game
-                    if item.game.trim().is_empty() {
-                        return false;
-                    }
-                
-                    let converted_price = item.converted_price.parse::<f64>().unwrap_or(0.0);
-                    let p_min = self.item_card_filters.price_min.parse::<f64>().unwrap_or(0.0);
-                    let p_max = self.item_card_filters.price_max.parse::<f64>().unwrap_or(f64::MAX);
-                
-                    converted_price >= p_min &&
-                    converted_price <= p_max &&
-                    item.name
-                        .to_lowercase()
-                        .contains(&self.item_card_filters.query.to_lowercase()) &&
-                    item.game
-                        .to_lowercase()
-                        .contains(&self.item_card_filters.appid.to_lowercase())
-                })
-                .cloned()
-                .collect();
-        
-            let response = WsResponse {
-                items: filtered_items,
-            };
-            // println!("Broadcast → sending {} items", response.items.len());
+        self.send_items(ctx, msg.0);
+    }
+}
 
-            if let Ok(json) = serde_json::to_string(&response) {
-                ctx.text(json);
-            }
-        }
-        else {
-            // let filtered_items: Vec<MostRecent> = msg.0.items
-            //     .iter()
-            //     .filter(|item| {
-            //         let converted_price = item.converted_price.parse::<f64>().unwrap_or(0.0);
-            //         let p_min = self.item_card_filters.price_min.parse::<f64>().unwrap_or(0.0);
-            //         let p_max = self.item_card_filters.price_max.parse::<f64>().unwrap_or(f64::MAX);
-            //         //add filter by the game!!!
-            //         converted_price >= p_min &&
-            //         converted_price <= p_max &&
-            //         item.name.to_lowercase().contains(&self.item_card_filters.query.to_lowercase())
-            //     })
-            //     .cloned()
-            //     .collect();
-        
-            let response = WsResponse {
-                items: Vec::new(),
-            };
-            // println!("Broadcast → sending {} items", response.items.len());
+impl Handler<FeedLagged> for WsActor {
+    type Result = ();
 
-            if let Ok(json) = serde_json::to_string(&response) {
-                ctx.text(json);
-            }
-        }
+    fn handle(&mut self, msg: FeedLagged, ctx: &mut Self::Context) {
+        println!("Feed subscriber lagged {} batches, resyncing after id {}", msg.0, self.seen_id);
+
+        let seen_id = self.seen_id;
+        let db_pool = self.db_pool.clone();
+        let resync = async move {
+            db_pool
+                .run(move |db| Ok::<_, DbError>((db.db_feed_since(seen_id, FEED_RESYNC_LIMIT)?, db.db_get_currency_rates()?)))
+                .await
+        };
+
+        //wait() holds back the batches queued behind this until the resync is out
+        ctx.wait(resync.into_actor(self).map(|resync, act, ctx| match resync {
+            Ok((items, rates)) => act.send_items(ctx, BroadcastPayload { items, rates }),
+            Err(e) => eprintln!("Feed resync failed: {e}"),
+        }));
     }
 }
 
@@ -222,6 +476,8 @@ pub async fn ws_handler(
     stream: web::Payload,
     session: Session,
     state: web::Data<FeedItemsState>,
+    db_pool: web::Data<DbPool>,
+    connections: web::Data<WsConnections>,
 ) -> Result<HttpResponse, Error> {
 
     // println!("session: {session:#?}");
@@ -234,16 +490,49 @@ pub async fn ws_handler(
         price_min: "0".into(),
         price_max: "99999".into(),
         query: "".into(),
+        min_discount: "".into(),
     });
 
     let card_filters = card_filters.unwrap_or_else(|| CardAppearingFilter {
         card_appearing: "stores_items".into(),
     });
 
+    let steam_user: Option<SteamUser> = session.get("steam_user")?;
+
+    let steamid = steam_user.as_ref().map(|user| user.steamid.clone());
+    let connection = match open_connection(&req, connections, WsEndpoint::Feed, steamid) {
+        Ok(connection) => connection,
+        Err(response) => return Ok(response),
+    };
+
+    let currency = match steam_user {
+        Some(user) => db_pool
+            .run(move |db| db.db_account_get_currency(&user.steamid))
+            .await
+            .unwrap_or_else(|e| {
+                eprintln!("db_account_get_currency failed: {e}");
+                None
+            }),
+        None => None,
+    };
+
+    let rates = db_pool.run(|db| db.db_get_currency_rates()).await.unwrap_or_else(|e| {
+        eprintln!("db_get_currency_rates failed: {e}");
+        Default::default()
+    });
+
+    let rx = state.broadcaster.subscribe();
+    let items = state.items.lock().await.clone();
+
     let ws = WsActor {
-        state: state.clone(),
-        item_card_filters: item_filters,
-        card_filters
+        connection,
+        db_pool: db_pool.clone(),
+        rx: Some(rx),
+        snapshot: BroadcastPayload { items, rates },
+        seen_id: 0,
+        filter: FeedFilter::from_legacy(&item_filters).compile().unwrap_or_default(),
+        card_filters,
+        currency,
     };
 
     ws::start(ws, &req, stream)
@@ -252,12 +541,66 @@ pub async fn ws_handler(
 pub async fn ws_ad_handler(
     req: HttpRequest,
     stream: web::Payload,
+    session: Session,
     state: web::Data<UserAdState>,
+    connections: web::Data<WsConnections>,
 ) -> Result<HttpResponse, Error> {
 
+    let steamid = session.get::<SteamUser>("steam_user")?.map(|user| user.steamid);
+    let connection = match open_connection(&req, connections, WsEndpoint::Ads, steamid) {
+        Ok(connection) => connection,
+        Err(response) => return Ok(response),
+    };
+
+    let rx = state.ads_broadcaster.subscribe();
+    let snapshot = state.user_ads.lock().await.queue.clone();
+
     let ws_ad = AdWSActor {
+        connection,
         state: state.clone(),
+        rx: Some(rx),
+        snapshot,
     };
     
     ws::start(ws_ad, &req, stream)
-}
\ No newline at end of file
+}
+
+pub async fn ws_alerts_handler(
+    req: HttpRequest,
+    stream: web::Payload,
+    session: Session,
+    state: web::Data<AlertState>,
+    db_pool: web::Data<DbPool>,
+    connections: web::Data<WsConnections>,
+) -> Result<HttpResponse, Error> {
+
+    let Some(steam_user) = session.get::<SteamUser>("steam_user")? else {
+        return Ok(HttpResponse::Unauthorized().finish());
+    };
+
+    let connection = match open_connection(&req, connections, WsEndpoint::Alerts, Some(steam_user.steamid.clone())) {
+        Ok(connection) => connection,
+        Err(response) => return Ok(response),
+    };
+
+    //Subscribe before reading the backlog, a notification stored in between is in one or both
+    let rx = state.broadcaster.subscribe();
+    let steamid = steam_user.steamid.clone();
+    let backlog = db_pool
+        .run(move |db| db.db_get_notifications(&steamid, true, ALERT_BACKLOG))
+        .await
+        .unwrap_or_else(|e| {
+            eprintln!("db_get_notifications failed: {e}");
+            Vec::new()
+        });
+
+    let ws_alerts = AlertWsActor {
+        connection,
+        rx: Some(rx),
+        steamid: steam_user.steamid,
+        backlog,
+        seen_id: 0,
+    };
+
+    ws::start(ws_alerts, &req, stream)
+}
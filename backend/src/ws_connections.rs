@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+/// Heartbeat and connection caps shared by every websocket endpoint.
+#[derive(Clone, Debug)]
+pub struct WsConfig {
+    /// How often the server pings.
+    pub heartbeat_interval: Duration,
+    /// A client that sends nothing, pongs included, for this long is dropped.
+    pub client_timeout: Duration,
+    /// Open sockets per remote address, over all endpoints. Off unless set: behind
+    /// a proxy that isn't trusted every client has the proxy's address.
+    pub max_per_ip: Option<usize>,
+    /// Take the client address from Forwarded / X-Forwarded-For, only behind a proxy
+    /// that sets them.
+    pub trust_proxy: bool,
+    /// Open sockets per logged-in user, over all endpoints.
+    pub max_per_steamid: usize,
+}
+
+impl Default for WsConfig {
+    fn default() -> Self {
+        WsConfig {
+            heartbeat_interval: Duration::from_secs(15),
+            client_timeout: Duration::from_secs(45),
+            max_per_ip: None,
+            trust_proxy: false,
+            max_per_steamid: 16,
+        }
+    }
+}
+
+impl WsConfig {
+    /// Default config with overrides from WS_HEARTBEAT_SECS, WS_CLIENT_TIMEOUT_SECS,
+    /// WS_MAX_PER_IP, WS_MAX_PER_STEAMID and WS_TRUST_PROXY.
+    pub fn from_env() -> Self {
+        let mut config = WsConfig::default();
+
+        if let Some(v) = env_parse::<u64>("WS_HEARTBEAT_SECS") {
+            config.heartbeat_interval = Duration::from_secs(v.max(1));
+        }
+        if let Some(v) = env_parse::<u64>("WS_CLIENT_TIMEOUT_SECS") {
+            config.client_timeout = Duration::from_secs(v.max(1));
+        }
+        if let Some(v) = env_parse("WS_MAX_PER_IP") {
+            config.max_per_ip = Some(v);
+        }
+        if let Some(v) = env_parse("WS_MAX_PER_STEAMID") {
+            config.max_per_steamid = v;
+        }
+        if let Some(v) = env_parse::<u8>("WS_TRUST_PROXY") {
+            config.trust_proxy = v != 0;
+        }
+
+        config
+    }
+}
+
+fn env_parse<T: std::str::FromStr>(key: &str) -> Option<T> {
+    std::env::var(key).ok()?.trim().parse().ok()
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum WsEndpoint {
+    Feed,
+    Ads,
+    Alerts,
+    Chat,
+}
+
+/// Why a socket was turned away before the upgrade.
+#[derive(Debug, PartialEq)]
+pub enum WsRejected {
+    TooManyForIp,
+    TooManyForUser,
+}
+
+/// Live sockets per endpoint, GET /api/admin/ws_connections.
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct WsConnectionCounts {
+    pub feed: usize,
+    pub ads: usize,
+    pub alerts: usize,
+    pub chat: usize,
+    pub total: usize,
+}
+
+#[derive(Default)]
+struct Counts {
+    endpoints: HashMap<WsEndpoint, usize>,
+    ips: HashMap<IpAddr, usize>,
+    steamids: HashMap<String, usize>,
+}
+
+/// Every open websocket, counted from the upgrade until its actor is dropped.
+pub struct WsConnections {
+    config: WsConfig,
+    counts: Mutex<Counts>,
+}
+
+impl WsConnections {
+    pub fn new(config: WsConfig) -> Arc<Self> {
+        Arc::new(WsConnections { config, counts: Mutex::new(Counts::default()) })
+    }
+
+    pub fn config(&self) -> &WsConfig {
+        &self.config
+    }
+
+    /// Takes a slot for a new socket, released when the returned guard is dropped.
+    /// Sockets without a remote address or a login only count against the caps they have.
+    pub fn open(
+        self: &Arc<Self>,
+        endpoint: WsEndpoint,
+        ip: Option<IpAddr>,
+        steamid: Option<String>,
+    ) -> Result<WsConnection, WsRejected> {
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+
+        if let (Some(ip), Some(max_per_ip)) = (ip, self.config.max_per_ip)
+            && counts.ips.get(&ip).copied().unwrap_or(0) >= max_per_ip
+        {
+            return Err(WsRejected::TooManyForIp);
+        }
+        if let Some(steamid) = &steamid
+            && counts.steamids.get(steamid).copied().unwrap_or(0) >= self.config.max_per_steamid
+        {
+            return Err(WsRejected::TooManyForUser);
+        }
+
+        *counts.endpoints.entry(endpoint).or_default() += 1;
+        if let Some(ip) = ip {
+            *counts.ips.entry(ip).or_default() += 1;
+        }
+        if let Some(steamid) = &steamid {
+            *counts.steamids.entry(steamid.clone()).or_default() += 1;
+        }
+
+        Ok(WsConnection {
+            connections: self.clone(),
+            endpoint,
+            ip,
+            steamid,
+            last_seen: Instant::now(),
+        })
+    }
+
+    pub fn counts(&self) -> WsConnectionCounts {
+        let counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        let count = |endpoint| counts.endpoints.get(&endpoint).copied().unwrap_or(0);
+
+        WsConnectionCounts {
+            feed: count(WsEndpoint::Feed),
+            ads: count(WsEndpoint::Ads),
+            alerts: count(WsEndpoint::Alerts),
+            chat: count(WsEndpoint::Chat),
+            total: counts.endpoints.values().sum(),
+        }
+    }
+
+    fn close(&self, connection: &WsConnection) {
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+
+        release(&mut counts.endpoints, &connection.endpoint);
+        if let Some(ip) = &connection.ip {
+            release(&mut counts.ips, ip);
+        }
+        if let Some(steamid) = &connection.steamid {
+            release(&mut counts.steamids, steamid);
+        }
+    }
+}
+
+/// A forwarded client address, with or without a port: "203.0.113.7", "203.0.113.7:5120", "[2001:db8::1]:443".
+pub fn parse_client_ip(addr: &str) -> Option<IpAddr> {
+    let addr = addr.trim();
+    addr.parse::<IpAddr>()
+        .or_else(|_| addr.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .or_else(|_| addr.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>())
+        .ok()
+}
+
+fn release<K: std::hash::Hash + Eq>(counts: &mut HashMap<K, usize>, key: &K) {
+    if let Some(count) = counts.get_mut(key) {
+        *count -= 1;
+        if *count == 0 {
+            counts.remove(key);
+        }
+    }
+}
+
+/// One open socket, kept by its actor. Tracks when the client was last heard from.
+pub struct WsConnection {
+    connections: Arc<WsConnections>,
+    endpoint: WsEndpoint,
+    ip: Option<IpAddr>,
+    steamid: Option<String>,
+    last_seen: Instant,
+}
+
+impl WsConnection {
+    pub fn config(&self) -> &WsConfig {
+        &self.connections.config
+    }
+
+    /// Any frame from the client counts as a heartbeat.
+    pub fn touch(&mut self) {
+        self.last_seen = Instant::now();
+    }
+
+    pub fn timed_out(&self) -> bool {
+        self.last_seen.elapsed() > self.config().client_timeout
+    }
+}
+
+impl Drop for WsConnection {
+    fn drop(&mut self) {
+        self.connections.close(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caps_hold_until_sockets_close() {
+        let connections = WsConnections::new(WsConfig { max_per_ip: Some(2), max_per_steamid: 1, ..Default::default() });
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+
+        let feed = connections.open(WsEndpoint::Feed, Some(ip), None).unwrap();
+        let chat = connections.open(WsEndpoint::Chat, Some(ip), Some("1".into())).unwrap();
+        assert_eq!(connections.open(WsEndpoint::Ads, Some(ip), None).err(), Some(WsRejected::TooManyForIp));
+        assert_eq!(connections.open(WsEndpoint::Ads, Some(other), Some("1".into())).err(), Some(WsRejected::TooManyForUser));
+        assert_eq!(connections.counts(), WsConnectionCounts { feed: 1, chat: 1, total: 2, ..Default::default() });
+
+        drop(chat);
+        let _alerts = connections.open(WsEndpoint::Alerts, Some(other), Some("1".into())).unwrap();
+        drop(feed);
+        assert_eq!(connections.counts(), WsConnectionCounts { alerts: 1, total: 1, ..Default::default() });
+        assert!(connections.open(WsEndpoint::Feed, Some(ip), None).is_ok());
+    }
+
+    #[test]
+    fn ip_cap_is_off_unless_set() {
+        let connections = WsConnections::new(WsConfig::default());
+        let proxy: IpAddr = "127.0.0.1".parse().unwrap();
+
+        let sockets: Vec<_> = (0..64).map(|_| connections.open(WsEndpoint::Feed, Some(proxy), None).unwrap()).collect();
+        assert_eq!(connections.counts().feed, sockets.len());
+    }
+
+    #[test]
+    fn forwarded_addresses_parse() {
+        let v4: IpAddr = "203.0.113.7".parse().unwrap();
+        let v6: IpAddr = "2001:db8::1".parse().unwrap();
+
+        assert_eq!(parse_client_ip("203.0.113.7"), Some(v4));
+        assert_eq!(parse_client_ip(" 203.0.113.7:5120"), Some(v4));
+        assert_eq!(parse_client_ip("2001:db8::1"), Some(v6));
+        assert_eq!(parse_client_ip("[2001:db8::1]:443"), Some(v6));
+        assert_eq!(parse_client_ip("[2001:db8::1]"), Some(v6));
+        assert_eq!(parse_client_ip("unknown"), None);
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::VecDeque;
+use serde::{Deserialize, Serialize};
+
+use crate::{FeedFilter, MostRecent, MostRecentItemsFilter, Notification, UserProfileAds};
+
+/// Sent in the `hello` frame every socket opens with. A client announcing another
+/// version gets an `unsupported_version` error and the socket is closed.
+pub const WS_PROTOCOL_VERSION: u32 = 1;
+
+/// A text frame from a client on /ws, /ws/ads, /ws/alerts or /ws/chat, tagged by `type`.
+/// Each socket acts on its own messages and answers the rest with `unsupported_message`.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Hello { version: u32 },
+
+    //Feed
+    Filters(FiltersMessage),
+
+    //Store chat
+    Chat { text: String },
+    System { text: String },
+    OfferItems { items: Vec<serde_json::Value> },
+    /// Offer summary the buyer's page built, passed on as is
+    OfferLog { json: serde_json::Value },
+    ItemAsking {
+        text: String,
+        name: String,
+        #[serde(default)]
+        link: String,
+        #[serde(default)]
+        image: String,
+    },
+    SetOffer { offer_id: String },
+    SendOffer,
+    AcceptOffer,
+    PaidOffer,
+    ClearOffer,
+    OfferStepConnecting,
+    OfferStepAccepting,
+    OfferStepPaying,
+}
+
+/// `filters` on /ws. The side panel sends its string form keyed by `appid`,
+/// anything else is read as a FeedFilter.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct FiltersMessage {
+    #[serde(default)]
+    pub card_appearing: Option<String>,
+    #[serde(flatten)]
+    pub filter: FilterSpec,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum FilterSpec {
+    Legacy(MostRecentItemsFilter),
+    Typed(FeedFilter),
+}
+
+impl FilterSpec {
+    pub fn into_filter(self) -> FeedFilter {
+        match self {
+            FilterSpec::Legacy(legacy) => FeedFilter::from_legacy(&legacy),
+            FilterSpec::Typed(filter) => filter,
+        }
+    }
+}
+
+/// A text frame to a client, tagged by `type`.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Hello { version: u32 },
+    Error { error: WsErrorCode, message: String },
+
+    //Feed, ads and alerts
+    Items { items: Vec<MostRecent> },
+    Ads { user_ads: VecDeque<UserProfileAds> },
+    Notifications { notifications: Vec<Notification> },
+    /// The filter now applied, echoed back after a `filters` message
+    Filters {
+        card_appearing: String,
+        #[serde(flatten)]
+        filter: FeedFilter,
+    },
+
+    //Store chat
+    Presence {
+        count: usize,
+        buyer_present: bool,
+        trader_present: bool,
+        offer_id: Option<String>,
+    },
+    Chat(ChatLine),
+    System(ChatLine),
+    OfferSystem(ChatLine),
+    /// `text` is the sender's offer_log message as JSON
+    OfferLog(ChatLine),
+    /// `text` is the sender's item_asking message as JSON
+    ItemAsking(ChatLine),
+    RevealSendOffer(ChatLine),
+    OfferItems {
+        from_role: String,
+        offer_id: Option<String>,
+        items: Vec<serde_json::Value>,
+    },
+    OfferStep {
+        from_role: String,
+        offer_id: Option<String>,
+        step: OfferStepKind,
+        text: String,
+    },
+    SetOffer(OfferStatus),
+    SendOffer(OfferStatus),
+    AcceptOffer(OfferStatus),
+    PayOffer(OfferStatus),
+    ClearOffer(OfferStatus),
+}
+
+/// A line in a store chat room, `from_role` is "buyer", "trader" or "system".
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ChatLine {
+    pub from_role: String,
+    pub offer_id: Option<String>,
+    pub text: String,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OfferStepKind {
+    Connect,
+    Accept,
+    Pay,
+}
+
+/// Where the room's offer stands, sent whenever it moves.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct OfferStatus {
+    pub offer_id: Option<String>,
+    pub offer_dirty: bool,
+    pub offer_send: bool,
+    pub offer_accepted: bool,
+    pub offer_paid: bool,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WsErrorCode {
+    /// Not JSON, no or an unknown `type`, or fields of the wrong shape
+    InvalidMessage,
+    /// A known message this socket doesn't take
+    UnsupportedMessage,
+    UnsupportedVersion,
+    InvalidFilter,
+}
+
+impl ClientMessage {
+    /// Reads a text frame. The error goes back to the client as an `invalid_message` frame.
+    pub fn parse(text: &str) -> Result<ClientMessage, serde_json::Error> {
+        serde_json::from_str(text)
+    }
+}
+
+impl ServerMessage {
+    pub fn hello() -> ServerMessage {
+        ServerMessage::Hello { version: WS_PROTOCOL_VERSION }
+    }
+
+    pub fn error(error: WsErrorCode, message: impl Into<String>) -> ServerMessage {
+        ServerMessage::Error { error, message: message.into() }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("ServerMessage: every field serializes to JSON")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Money;
+
+    fn listing() -> MostRecent {
+        MostRecent {
+            id: 7,
+            listinginfo_id: "4242".into(),
+            market_hash_name: "AK-47 | Redline (Field-Tested)".into(),
+            icon: "icon".into(),
+            game_icon: "game_icon".into(),
+            exterior: Some("Field-Tested".into()),
+            reference_price: Some(Money::new(1500, 3)),
+            discount_pct: Some(16.7),
+            ..MostRecent::sample("AK-47 | Redline", Some(1250))
+        }
+    }
+
+    #[test]
+    fn server_frames_keep_their_shape() {
+        assert_eq!(ServerMessage::hello().to_json(), r#"{"type":"hello","version":1}"#);
+        assert_eq!(
+            ServerMessage::error(WsErrorCode::InvalidFilter, "invalid regex").to_json(),
+            r#"{"type":"error","error":"invalid_filter","message":"invalid regex"}"#
+        );
+        assert_eq!(
+            ServerMessage::Items { items: vec![listing()] }.to_json(),
+            concat!(
+                r#"{"type":"items","items":[{"id":7,"listinginfo_id":"4242","name":"AK-47 | Redline","#,
+                r#""price":{"cents":1250,"currency":3},"appid":"730","game":"Counter-Strike 2","#,
+                r#""market_hash_name":"AK-47 | Redline (Field-Tested)","tradable":"1","icon":"icon","#,
+                r#""game_icon":"game_icon","rarity":null,"exterior":"Field-Tested","#,
+                r#""reference_price":{"cents":1500,"currency":3},"discount_pct":16.7}]}"#
+            )
+        );
+        assert_eq!(
+            ServerMessage::Filters {
+                card_appearing: "items".into(),
+                filter: FeedFilter { appids: vec![730], price_max: Some(500), ..Default::default() },
+            }
+            .to_json(),
+            concat!(
+                r#"{"type":"filters","card_appearing":"items","appids":[730],"game":null,"tradable_only":false,"#,
+                r#""price_min":null,"price_max":500,"query":null,"regex":false,"exclude":[],"min_discount":null}"#
+            )
+        );
+    }
+
+    #[test]
+    fn chat_frames_keep_their_shape() {
+        let line = ChatLine { from_role: "buyer".into(), offer_id: Some("77".into()), text: "hi".into() };
+        assert_eq!(ServerMessage::Chat(line.clone()).to_json(), r#"{"type":"chat","from_role":"buyer","offer_id":"77","text":"hi"}"#);
+        assert_eq!(
+            ServerMessage::RevealSendOffer(line).to_json(),
+            r#"{"type":"reveal_send_offer","from_role":"buyer","offer_id":"77","text":"hi"}"#
+        );
+        assert_eq!(
+            ServerMessage::Presence { count: 2, buyer_present: true, trader_present: true, offer_id: None }.to_json(),
+            r#"{"type":"presence","count":2,"buyer_present":true,"trader_present":true,"offer_id":null}"#
+        );
+        assert_eq!(
+            ServerMessage::OfferStep { from_role: "trader".into(), offer_id: None, step: OfferStepKind::Pay, text: "Paying".into() }
+                .to_json(),
+            r#"{"type":"offer_step","from_role":"trader","offer_id":null,"step":"pay","text":"Paying"}"#
+        );
+        let paid = OfferStatus { offer_id: Some("77".into()), offer_send: true, offer_accepted: true, offer_paid: true, ..Default::default() };
+        assert_eq!(
+            ServerMessage::PayOffer(paid).to_json(),
+            r#"{"type":"pay_offer","offer_id":"77","offer_dirty":false,"offer_send":true,"offer_accepted":true,"offer_paid":true}"#
+        );
+        assert_eq!(
+            ServerMessage::OfferItems { from_role: "buyer".into(), offer_id: None, items: vec![serde_json::json!({"assetid": "1"})] }
+                .to_json(),
+            r#"{"type":"offer_items","from_role":"buyer","offer_id":null,"items":[{"assetid":"1"}]}"#
+        );
+    }
+
+    #[test]
+    fn client_frames_parse() {
+        assert_eq!(ClientMessage::parse(r#"{"type":"hello","version":1}"#).ok(), Some(ClientMessage::Hello { version: 1 }));
+        //Extra fields on a bare message are ignored, the pages send a text along with some
+        assert_eq!(ClientMessage::parse(r#"{"type":"accept_offer","text":"Trader accept offer"}"#).ok(), Some(ClientMessage::AcceptOffer));
+
+        let Ok(ClientMessage::Filters(legacy)) = ClientMessage::parse(
+            r#"{"type":"filters","appid":"730","price_min":"0","price_max":"500","query":"","card_appearing":"items"}"#,
+        ) else {
+            panic!("side panel filters");
+        };
+        assert_eq!(legacy.card_appearing.as_deref(), Some("items"));
+        assert_eq!(legacy.filter.into_filter(), FeedFilter { game: Some("730".into()), price_min: Some(0), price_max: Some(500), ..Default::default() });
+
+        let Ok(ClientMessage::Filters(typed)) =
+            ClientMessage::parse(r#"{"type":"filters","appids":[730,440],"exclude":["souvenir"],"tradable_only":true}"#)
+        else {
+            panic!("typed filters");
+        };
+        assert_eq!(
+            typed.filter.into_filter(),
+            FeedFilter { appids: vec![730, 440], exclude: vec!["souvenir".into()], tradable_only: true, ..Default::default() }
+        );
+    }
+
+    #[test]
+    fn malformed_frames_are_rejected() {
+        for text in ["not json", r#"{"text":"no type"}"#, r#"{"type":"shout"}"#, r#"{"type":"chat"}"#, r#"{"type":"hello","version":"1"}"#] {
+            assert!(ClientMessage::parse(text).is_err(), "{text} parsed");
+        }
+    }
+}
@@ -0,0 +1,176 @@
+-- steam_items.db schema as created by DataBase::create_tables before versioned migrations
+
+CREATE TABLE IF NOT EXISTS item_feed (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    listinginfo_id TEXT UNIQUE,
+    name TEXT,
+    converted_price TEXT,
+    game TEXT,
+    appid TEXT,
+    market_hash_name TEXT,
+    tradable TEXT,
+    icon_url TEXT,
+    game_icon TEXT
+);
+CREATE TABLE IF NOT EXISTS steam_user (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    steamid TEXT UNIQUE,
+    nickname TEXT,
+    avatar_url_small TEXT,
+    avatar_url_full TEXT,
+    trade_url TEXT,
+    status TEXT
+);
+CREATE TABLE IF NOT EXISTS user_wallets (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    steamid TEXT UNIQUE,     -- 1:1 with steam_user
+    stripe_id TEXT UNIQUE,
+    bitcoin TEXT UNIQUE,
+    FOREIGN KEY (steamid)
+        REFERENCES steam_user(steamid)
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+    );
+CREATE TABLE IF NOT EXISTS stripe_wallet (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+
+    steamid TEXT NOT NULL,          -- tie to wallet owner (cascade path)
+    stripe_id TEXT NOT NULL,        -- optional consistency
+    offer_id TEXT NOT NULL,
+    amount_cents INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    stripe_event_id TEXT UNIQUE,
+    stripe_transfer_id TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+
+    FOREIGN KEY (steamid)
+        REFERENCES user_wallets(steamid)
+        ON UPDATE CASCADE
+        ON DELETE CASCADE,
+
+    -- optional: ensure stripe_id exists in user_wallets (prevents random stripe_id values)
+    FOREIGN KEY (stripe_id)
+        REFERENCES user_wallets(stripe_id)
+        ON UPDATE CASCADE
+        ON DELETE RESTRICT
+    );
+
+CREATE TABLE IF NOT EXISTS stripe_customer (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    steamid TEXT UNIQUE NOT NULL,
+    stripe_customer_id TEXT UNIQUE NOT NULL,
+    FOREIGN KEY (steamid)
+        REFERENCES steam_user(steamid)
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+    );
+
+CREATE TABLE IF NOT EXISTS transactions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    steamid TEXT NOT NULL,
+    offer_id TEXT NOT NULL,
+    amount TEXT NOT NULL,
+    amount_with_fee TEXT NOT NULL,
+    method TEXT NOT NULL,
+    pay_method TEXT NOT NULL,
+    status TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (steamid)
+        REFERENCES steam_user(steamid)
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+    );
+
+CREATE INDEX IF NOT EXISTS idx_user_wallets_steamid ON user_wallets(steamid);
+CREATE INDEX IF NOT EXISTS idx_stripe_wallet_steamid ON stripe_wallet(steamid);
+CREATE INDEX IF NOT EXISTS idx_transactions_steamid ON transactions(steamid);
+
+CREATE TABLE IF NOT EXISTS ad_steam_user (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    steamid TEXT UNIQUE,
+    nickname TEXT,
+    avatar_url_full TEXT,
+    first_item_image TEXT,
+    second_item_image TEXT,
+    third_item_image TEXT,
+    fourth_item_image TEXT
+);
+CREATE TABLE IF NOT EXISTS chat_messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    chat_id TEXT,
+    buyer_steamid TEXT,
+    trader_steamid TEXT,
+    message_type TEXT,
+    message TEXT,
+    data TEXT
+);
+CREATE TABLE IF NOT EXISTS offer (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    offer_id TEXT UNIQUE,
+    buyer_steamid TEXT,
+    trader_steamid TEXT,
+    count TEXT,
+    price TEXT,
+    accepted BOOLEAN,
+    paid BOOLEAN,
+    status TEXT,
+    created TEXT,
+    last_update TEXT
+);
+CREATE TABLE IF NOT EXISTS offer_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    offer_id TEXT,
+    round INTEGER,
+    item_asset_id TEXT,
+    item_contextid TEXT,
+    item_appid TEXT,
+    item_name TEXT,
+    items_price TEXT,
+    item_link TEXT,
+    item_image TEXT,
+    time TEXT,
+    FOREIGN KEY (offer_id) REFERENCES offer(offer_id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS trade_offer_drafts (
+    draft_id TEXT PRIMARY KEY,
+    offer_id TEXT NOT NULL,
+    partner_trade_url TEXT NOT NULL,
+    autosend INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS trade_offer_draft_items (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    draft_id TEXT NOT NULL,
+    appid INTEGER NOT NULL,
+    contextid TEXT NOT NULL,
+    assetid TEXT NOT NULL,
+    amount INTEGER NOT NULL DEFAULT 1,
+    side TEXT NOT NULL DEFAULT 'give',  -- 'give' now, later could add 'receive'
+    FOREIGN KEY(draft_id) REFERENCES trade_offer_drafts(draft_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_draft_items_draft_id ON trade_offer_draft_items(draft_id);
+
+-- Rows the way the pre-migration code wrote them
+INSERT INTO item_feed (listinginfo_id, name, converted_price, game, appid, market_hash_name, tradable, icon_url, game_icon)
+VALUES ('1001', 'AK-47 | Redline', '1234', 'Counter-Strike 2', '730', 'AK-47 | Redline (Field-Tested)', '1', 'icon', 'game_icon'),
+       ('1002', 'Mann Co. Supply Crate Key', '?', 'Team Fortress 2', '440', 'Mann Co. Supply Crate Key', '1', 'icon', 'game_icon');
+
+INSERT INTO steam_user (steamid, nickname, avatar_url_small, avatar_url_full, trade_url, status)
+VALUES ('76561198000000001', 'buyer', 'a', 'b', NULL, 'online'),
+       ('76561198000000002', 'trader', 'a', 'b', 'https://steamcommunity.com/tradeoffer/new/?partner=1&token=x', 'online');
+
+INSERT INTO offer (offer_id, buyer_steamid, trader_steamid, count, price, accepted, paid, status, created, last_update)
+VALUES ('offer-1', '76561198000000001', '76561198000000002', '2', '12.5', 'false', 'false', 'IN PROCESS', '2025-01-01 00:00:00', '2025-01-01 00:00:00');
+
+INSERT INTO offer_log (offer_id, round, item_asset_id, item_contextid, item_appid, item_name, items_price, item_link, item_image, time)
+VALUES ('offer-1', 0, 'Nope', 'Nope', 'Nope', 'Nope', 'Nope', 'Nope', 'Nope', '2025-01-01 00:00:00'),
+       ('offer-1', 1, '111', '2', '730', 'AK-47 | Redline', '$10.20', 'link', 'image', '2025-01-01 00:00:00'),
+       ('offer-1', 1, '222', '2', '730', 'Sticker', '2.3', 'link', 'image', '2025-01-01 00:00:00');
+
+INSERT INTO transactions (steamid, offer_id, amount, amount_with_fee, method, pay_method, status, created_at, updated_at)
+VALUES ('76561198000000001', 'offer-1', '1250', '1369', 'STRIPE', 'checkout', 'SUCCESS', '2025-01-01 00:00:00', '2025-01-01 00:00:00');
@@ -127,7 +127,8 @@ function mapRustItemToItemData(it: any): ItemCardData {
     return {
         id: it.id ?? (it.market_hash_name ? hashStr(it.market_hash_name) : Math.floor(Math.random() * 1e9)),
         title: it.name ?? it.title ?? "Unknown",
-        price: (it.converted_price ?? it.price ?? null),
+        price: (it.price?.cents ?? null),
+        currency: it.price?.currency,
         type: "single",                 // or "bundle" if you have that concept
         color: "#A8DADC",               // or derive by game/appid if you want
     };
@@ -401,6 +402,11 @@ export default function Page() {
             // console.log("main ws")
             const payload = JSON.parse(event.data);
 
+            if (payload?.type === "error") {
+                console.warn("main_ws rejected a message:", payload.error, payload.message);
+                return;
+            }
+
             // server echoes filters
             if (payload?.type === "filters" && payload?.card_appearing) {
                 cardAdAppearingRef.current = payload.card_appearing;
@@ -436,8 +442,9 @@ export default function Page() {
                 c.card.kind === "item"
         );
         const prices = itemCards
-            .map((c) => Number.parseFloat(c.card.data.converted_price))
-            .filter((n) => Number.isFinite(n));
+            .map((c) => c.card.data.price?.cents)
+            .filter((n): n is number => n !== undefined)
+            .map((cents) => cents / 100);
 
         return {
             totalItems: itemCards.length,
@@ -476,11 +483,11 @@ export default function Page() {
 
 
     function mapMostRecentToItemCardData(it: MostRecent): ItemCardData {
-        const priceNum = Number.parseFloat(it.converted_price);
         return {
             id: it.id,
             title: it.name,
-            price: Number.isFinite(priceNum) ? priceNum : null,
+            price: it.price?.cents ?? null,
+            currency: it.price?.currency,
             type: "single",
             color: "#A8DADC",
             appid: it.appid,
@@ -2,11 +2,13 @@
 
 import { Check, MoreVertical } from "lucide-react"
 import { cn } from "@/lib/utils"
+import { formatSteamPrice } from "@/lib/currency"
 
 export interface ItemCardData {
     id: number;
     title: string;
     price: number | null;
+    currency?: number;
     type: "single" | "bundle";
     color: string;
     store_id?: string;
@@ -100,7 +102,7 @@ export function ItemCard({ item, onBundleClick }: ItemCardProps) {
             <div className="px-2 pb-2 pt-0 space-y-1">
                 {item.price !== null && (
                     <p className="text-center text-xs font-medium text-foreground">
-                        ${item.price}
+                        {formatSteamPrice(item.price, item.currency)}
                     </p>
                 )}
 
@@ -1,16 +1,21 @@
 "use client"
 
-import { useState } from "react"
+import { useEffect, useState } from "react"
 import { X, CreditCard, Bitcoin, Search, RotateCcw, Save, Unplug } from "lucide-react"
 import { Input } from "@/components/ui/input"
 import { cn } from "@/lib/utils"
 import { useMe } from "@/hooks/userAuth";
+import { STEAM_CURRENCIES } from "@/lib/currency"
 
 interface ProfileTradeUrl {
     steam_id: string,
     trade_url: string,
 }
 
+interface ProfileCurrency {
+    currency: number,
+}
+
 const connectStripe = () => {
     // open immediately to avoid popup blocker
     const newTab = window.open("", "_blank");
@@ -55,6 +60,17 @@ export function ProfileSettingsPopup({ isOpen, onClose }: ProfileSettingsPopupPr
     const [paymentSearch, setPaymentSearch] = useState("")
     const [connectedMethods, setConnectedMethods] = useState<Set<string>>(new Set(["stripe"]))
     const [saving, setSaving] = useState(false)
+    // null until loaded, or while the user hasn't picked one: prices stay as listed
+    const [currency, setCurrency] = useState<number | null>(null)
+
+    useEffect(() => {
+        if (!isOpen || !steamUser) return
+
+        fetch("/api/account/currency", { credentials: "include", cache: "no-store" })
+            .then((r) => (r.ok ? r.json() : null))
+            .then((data) => setCurrency(data?.currency ?? null))
+            .catch((e) => console.error("account/currency failed:", e))
+    }, [isOpen, steamUser])
 
     const filteredMethods = PAYMENT_METHODS.filter((m) =>
         m.name.toLowerCase().includes(paymentSearch.toLowerCase())
@@ -112,6 +128,32 @@ export function ProfileSettingsPopup({ isOpen, onClose }: ProfileSettingsPopupPr
         }
     }
 
+    const handleSaveCurrency = async (next: number) => {
+        if (!steamUser?.steamid) {
+            console.error("Not authed: no steamUser")
+            return
+        }
+
+        const payload: ProfileCurrency = {
+            currency: next,
+        }
+
+        const res = await fetch("/api/account/post_currency", {
+            method: "POST",
+            headers: { "Content-Type": "application/json" },
+            credentials: "include",
+            body: JSON.stringify(payload),
+        })
+
+        if (!res.ok) {
+            console.error("post_currency failed:", await res.text())
+            return
+        }
+
+        // feed socket reads the currency on connect
+        setCurrency(next)
+    }
+
     const handleResetTradeUrl = async () => {
         // setSaving(true)
         if (!steamUser?.steamid) {
@@ -203,6 +245,21 @@ export function ProfileSettingsPopup({ isOpen, onClose }: ProfileSettingsPopupPr
                                 </button>
                             </div>
                         </div>
+
+                        <div>
+                            <label className="text-xs text-muted-foreground mb-1.5 block">Display currency</label>
+                            <select
+                                value={currency ?? ""}
+                                onChange={(e) => handleSaveCurrency(Number(e.target.value))}
+                                disabled={!steamUser}
+                                className="w-full bg-secondary border border-border rounded-lg h-9 px-2 text-sm text-foreground"
+                            >
+                                {currency === null && <option value="" disabled>As listed</option>}
+                                {Object.entries(STEAM_CURRENCIES).map(([id, code]) => (
+                                    <option key={id} value={id}>{code}</option>
+                                ))}
+                            </select>
+                        </div>
                     </div>
 
                     {/* Divider */}
@@ -1,15 +1,71 @@
 // Rust structs mirrored to TS (minimum fields you use)
+export type Money = {
+    cents: number;
+    currency: number;           // Steam currency id
+};
+
 export type MostRecent = {
     id: number;                 // usize -> number
     listinginfo_id: string;
     name: string;
-    converted_price: string;    // IMPORTANT: Rust sends string
+    price: Money | null;        // null when Steam didn't convert the listing
     appid: string;
     game: string;
     market_hash_name: string;
     tradable: string;           // "1" or "0"
     icon: string;
     game_icon: string;
+    rarity: string | null;      // from item_catalog, inventory tags
+    exterior: string | null;    // wear, e.g. "Field-Tested"
+    reference_price: Money | null;  // feed median or Steam's, same currency as price
+    discount_pct: number | null;    // % under reference_price, negative when above
+};
+
+// GET /api/search
+export type SearchHit = MostRecent & {
+    highlight: string;          // escaped HTML, matched words in <mark>
+};
+
+// /ws `{ type: "filters", ...FeedFilter }`, echoed back once applied
+export type FeedFilter = {
+    appids: number[];           // any of these apps, [] for all
+    game: string | null;        // appid or part of a game name
+    tradable_only: boolean;
+    price_min: number | null;   // cents in the display currency
+    price_max: number | null;
+    query: string | null;       // name substring, or a pattern when `regex`
+    regex: boolean;
+    exclude: string[];          // names containing any of these are left out
+    min_discount: number | null;
+};
+
+// /api/saved_searches
+export type SavedSearch = {
+    id: number;
+    name: string;
+    appid: string;              // appid or part of a game name, "" for any
+    price_min: number | null;   // cents in `currency`
+    price_max: number | null;
+    query: string;
+    currency: number | null;
+    created_at: number;         // unix seconds
+};
+
+// /api/notifications and /ws/alerts ({ notifications: Notification[] })
+export type Notification = {
+    id: number;
+    saved_search_id: number;
+    search_name: string;
+    item: MostRecent;           // as it matched, priced in the search's currency
+    created_at: number;
+    read: boolean;
+};
+
+// Every socket opens with { type: "hello", version } and answers bad input with:
+export type WsError = {
+    type: "error";
+    error: "invalid_message" | "unsupported_message" | "unsupported_version" | "invalid_filter";
+    message: string;
 };
 
 export type UserProfileAds = {